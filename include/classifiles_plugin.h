@@ -0,0 +1,21 @@
+/* Detector plugin interface of classifiles, see README.md */
+#ifndef CLASSIFILES_PLUGIN_H
+#define CLASSIFILES_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define CLASSIFILES_PLUGIN_ABI_VERSION 1
+
+/* returns CLASSIFILES_PLUGIN_ABI_VERSION */
+uint32_t classifiles_plugin_abi_version(void);
+
+/*
+ * buf holds the first len bytes of the file at path (NUL-terminated).
+ * On a match, writes the NUL-terminated MIME type into mime and the extension (without a dot,
+ * empty if unknown) into ext and returns 1, otherwise returns 0.
+ */
+int classifiles_detect(const uint8_t *buf, size_t len, const char *path,
+                       char *mime, size_t mime_cap, char *ext, size_t ext_cap);
+
+#endif
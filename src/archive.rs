@@ -0,0 +1,274 @@
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::input::{BufferedContent, InputEntry, InputError, InputSource};
+
+// a regular file of an archive given to scan --input-archive
+#[derive(Debug)]
+#[cfg_attr(not(feature = "archives"), allow(dead_code))]
+pub struct ArchiveEntry {
+    // relative, without . and .. components
+    pub name: PathBuf,
+    pub size: u64,
+    pub mtime: Option<i64>,
+    // user and group names as stored in tar headers
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub mode: Option<u32>,
+    pub data: io::Result<EntryData>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(not(feature = "archives"), allow(dead_code))]
+pub struct EntryData {
+    // the first 16 MiB at most
+    pub leading: Vec<u8>,
+    // of the whole entry
+    pub sha256: Option<String>,
+}
+
+#[cfg(feature = "archives")]
+mod formats {
+    use std::error::Error;
+    use std::fs::File;
+    use std::io::{self, BufReader, Read, Seek, SeekFrom};
+    use std::path::{Component, Path, PathBuf};
+
+    use flate2::read::{DeflateDecoder, MultiGzDecoder};
+    use sha2::{Digest, Sha256};
+
+    use super::{ArchiveEntry, EntryData};
+    use crate::containers::{self, ZipEntry};
+    use crate::hashing;
+
+    // entries are classified from memory, larger ones by their leading part (enough for all magic but the central
+    // directory of a zip archive stored inside)
+    const ENTRY_READ_LIMIT: usize = 16 * 1024 * 1024;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Format {
+        Tar,
+        TarGzip,
+        TarZstd,
+        Zip,
+    }
+
+    // by content, the name of an archive says little (backup.bin, .tgz, .cbz, .jar)
+    fn sniff_format(archive: &Path) -> Result<Format, Box<dyn Error>> {
+        let mut leading = Vec::with_capacity(512);
+        File::open(archive)?.take(512).read_to_end(&mut leading)?;
+
+        let format = if leading.starts_with(b"PK\x03\x04") || leading.starts_with(b"PK\x05\x06") {
+            Format::Zip
+        } else if leading.starts_with(&[0x1f, 0x8b]) {
+            Format::TarGzip
+        } else if leading.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Format::TarZstd
+        } else if leading.get(257..262) == Some(b"ustar") {
+            Format::Tar
+        } else if leading.starts_with(b"BZh") || leading.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0]) {
+            return Err("bzip2 and xz compressed archives are not supported".into());
+        } else {
+            return Err("not a tar or zip archive".into());
+        };
+        Ok(format)
+    }
+
+    // archive names are not trusted, absolute and parent components are dropped
+    fn entry_name(name: &Path) -> PathBuf {
+        name.components().filter_map(|c| match c {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        }).collect()
+    }
+
+    // MS-DOS timestamps carry no time zone, they are taken as UTC; days from civil date after Howard Hinnant
+    fn dos_mtime((date, time): (u16, u16)) -> Option<i64> {
+        let (year, month, day) = (1980 + (date >> 9) as i64, ((date >> 5) & 0xf) as i64, (date & 0x1f) as i64);
+        if !(1..=12).contains(&month) || day < 1 {
+            return None;
+        }
+        let y = if month <= 2 { year - 1 } else { year };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+        let secs = (time >> 11) as i64 * 3600 + ((time >> 5) & 0x3f) as i64 * 60 + (time & 0x1f) as i64 * 2;
+        Some(days * 86400 + secs)
+    }
+
+    // the rest of the entry is only read for its hash
+    fn read_data(reader: &mut dyn Read, hash: bool) -> io::Result<EntryData> {
+        let mut leading = Vec::new();
+        (&mut *reader).take(ENTRY_READ_LIMIT as u64).read_to_end(&mut leading)?;
+        if !hash {
+            return Ok(EntryData{leading, sha256: None});
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(&leading);
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => hasher.update(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(EntryData{leading, sha256: Some(hashing::to_hex(&hasher.finalize()))})
+    }
+
+    pub fn read_tar(reader: impl Read, hash: bool, f: &mut dyn FnMut(ArchiveEntry) -> Result<(), Box<dyn Error>>)
+        -> Result<(), Box<dyn Error>> {
+
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            // links and directories have no content of their own
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let header = entry.header();
+            let name = entry_name(&entry.path()?);
+            let size = entry.size();
+            let mtime = header.mtime().ok().map(|mtime| mtime as i64);
+            let owner = header.username().ok().flatten().filter(|name| !name.is_empty()).map(str::to_owned);
+            let group = header.groupname().ok().flatten().filter(|name| !name.is_empty()).map(str::to_owned);
+            let mode = header.mode().ok().map(|mode| mode & 0o7777);
+            let data = read_data(&mut entry, hash);
+            f(ArchiveEntry{name, size, mtime, owner, group, mode, data})?;
+        }
+        Ok(())
+    }
+
+    fn zip_entry_data(file: &mut File, entry: &ZipEntry, hash: bool) -> io::Result<EntryData> {
+        let unsupported = |what: String| Err(io::Error::other(what));
+        if entry.flags & 1 != 0 {
+            return unsupported("encrypted zip entry".to_owned());
+        }
+        if entry.compressed_size == u32::MAX as u64 || entry.size == u32::MAX as u64 {
+            return unsupported("zip64 entry".to_owned());
+        }
+        let offset = entry.data_offset(file)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut compressed = BufReader::new(file.take(entry.compressed_size));
+        match entry.method {
+            0 => read_data(&mut compressed, hash),
+            8 => read_data(&mut DeflateDecoder::new(compressed), hash),
+            method => unsupported(format!("zip compression method {}", method)),
+        }
+    }
+
+    pub fn read_zip(archive: &Path, hash: bool, f: &mut dyn FnMut(ArchiveEntry) -> Result<(), Box<dyn Error>>)
+        -> Result<(), Box<dyn Error>> {
+
+        let mut file = File::open(archive)?;
+        let entries = containers::zip_entries(&mut file)?;
+        for entry in entries.iter().filter(|entry| !entry.is_dir()) {
+            let data = zip_entry_data(&mut file, entry, hash);
+            f(ArchiveEntry{
+                name: entry_name(&PathBuf::from(&entry.name)),
+                size: entry.size,
+                mtime: dos_mtime(entry.dos_time),
+                owner: None,
+                group: None,
+                mode: entry.unix_mode.map(|mode| mode & 0o7777),
+                data,
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn read(archive: &Path, hash: bool, f: &mut dyn FnMut(ArchiveEntry) -> Result<(), Box<dyn Error>>)
+        -> Result<(), Box<dyn Error>> {
+
+        match sniff_format(archive)? {
+            Format::Zip => read_zip(archive, hash, f),
+            Format::Tar => read_tar(BufReader::new(File::open(archive)?), hash, f),
+            Format::TarGzip => read_tar(MultiGzDecoder::new(BufReader::new(File::open(archive)?)), hash, f),
+            Format::TarZstd => read_tar(zstd::stream::read::Decoder::new(File::open(archive)?)?, hash, f),
+        }
+    }
+}
+
+// fails early in a build without archive support, before the output root is touched
+#[cfg(feature = "archives")]
+pub fn check() -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+// streams the regular files of a tar (plain, gzip or zstd compressed) or zip archive to f in archive order;
+// errors reading single entries are passed on with the entry, a corrupt archive ends the iteration
+#[cfg(feature = "archives")]
+pub fn for_each_entry(archive: &Path, hash: bool, f: &mut dyn FnMut(ArchiveEntry) -> Result<(), Box<dyn Error>>)
+    -> Result<(), Box<dyn Error>> {
+
+    formats::read(archive, hash, f)
+        .map_err(|e| -> Box<dyn Error> { format!("could not read {}: {}", archive.display(), e).into() })
+}
+
+#[cfg(not(feature = "archives"))]
+pub fn check() -> Result<(), Box<dyn Error>> {
+    Err(Box::new(crate::ClassifierError(crate::ErrorKind::Config,
+        "--input-archive requires classifiles to be built with the archives feature".to_owned()
+    )))
+}
+
+#[cfg(not(feature = "archives"))]
+pub fn for_each_entry(_archive: &Path, _hash: bool, _f: &mut dyn FnMut(ArchiveEntry) -> Result<(), Box<dyn Error>>)
+    -> Result<(), Box<dyn Error>> {
+
+    check()
+}
+
+// the regular files of a tar or zip archive as buffered entries, under the archive path joined with their names;
+// a thread streams the archive ahead of classification
+pub struct ArchiveSource {
+    archive: PathBuf,
+    // the whole entries are read for their hashes
+    hash: bool,
+}
+
+impl ArchiveSource {
+    pub fn open(archive: &Path, hash: bool) -> Result<Self, Box<dyn Error>> {
+        check()?;
+        if !archive.is_file() {
+            return Err(format!("{} is not an archive file", archive.display()).into());
+        }
+        Ok(Self{archive: archive.to_owned(), hash})
+    }
+}
+
+impl InputSource for ArchiveSource {
+    fn entries(&self) -> Box<dyn Iterator<Item = Result<InputEntry, InputError>> + '_> {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let (archive, hash) = (self.archive.clone(), self.hash);
+        thread::spawn(move || {
+            let result = for_each_entry(&archive, hash, &mut |entry| {
+                let path = archive.join(&entry.name);
+                let entry = match entry.data {
+                    Ok(data) => Ok(InputEntry::buffered(path, BufferedContent{leading: data.leading, size: entry.size,
+                        mtime: entry.mtime, owner: entry.owner, group: entry.group, mode: entry.mode,
+                        sha256: data.sha256})),
+                    Err(e) => Err(InputError::new(&path, &e)),
+                };
+                // classification stopped early
+                sender.send(entry).map_err(|_| "entries no longer wanted".into())
+            });
+            if let Err(e) = result {
+                let _ = sender.send(Err(InputError::of_source(&archive, &*e)));
+            }
+        });
+        Box::new(receiver.into_iter())
+    }
+
+    // each call decompresses the archive
+    fn countable(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,162 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+
+use crate::hashing;
+use crate::manifest::Header;
+
+// prev of the first entry in a log
+static GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Event {
+    // one per run, with what is needed to reproduce the classification
+    Start {
+        command: String,
+        input: PathBuf,
+        output: PathBuf,
+        versions: Header,
+    },
+    Classify {
+        path: PathBuf,
+        mime: Option<String>,
+        ext: Option<String>,
+        sha256: Option<String>,
+    },
+    Link {
+        path: PathBuf,
+        link: PathBuf,
+        mode: String,
+    },
+    Remove {
+        path: PathBuf,
+        link: PathBuf,
+    },
+    // a file clamd detected, moved out of the input tree
+    Quarantine {
+        path: PathBuf,
+        quarantine: PathBuf,
+        signature: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    seq: u64,
+    time: String,
+    event: Event,
+    // hash of the previous entry
+    prev: String,
+    // SHA-256 of this entry serialized with an empty hash
+    hash: String,
+}
+
+impl Entry {
+    fn compute_hash(&mut self) -> Result<String, Box<dyn Error>> {
+        let hash = std::mem::take(&mut self.hash);
+        let digest = hashing::sha256_bytes(&serde_json::to_vec(self)?);
+        self.hash = hash;
+        Ok(digest)
+    }
+}
+
+// ISO 8601 in UTC with milliseconds, days to civil date after Howard Hinnant
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, day_secs) = ((secs / 86400) as i64, secs % 86400);
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day,
+        day_secs / 3600, day_secs / 60 % 60, day_secs % 60, since_epoch.subsec_millis())
+}
+
+// append-only JSON lines, each entry chained to the previous one by its hash, also across runs
+pub struct AuditLog {
+    out: File,
+    seq: u64,
+    prev: String,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let (seq, prev) = match path.exists() {
+            true => {
+                let last = read_entries(path)?.pop();
+                last.map_or((0, GENESIS.to_owned()), |e| (e.seq + 1, e.hash))
+            }
+            false => (0, GENESIS.to_owned()),
+        };
+        let out = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| format!("could not open audit log {}: {}", path.display(), e))?;
+        Ok(Self{out, seq, prev})
+    }
+
+    // written and flushed right away, a crash loses nothing already logged
+    pub fn log(&mut self, event: Event) -> Result<(), Box<dyn Error>> {
+        let mut entry = Entry{
+            seq: self.seq,
+            time: utc_timestamp(SystemTime::now()),
+            event,
+            prev: self.prev.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash()?;
+
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        self.out.write_all(&line)?;
+        self.out.flush()?;
+
+        self.seq += 1;
+        self.prev = entry.hash;
+        Ok(())
+    }
+}
+
+fn read_entries(path: &Path) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let file = File::open(path)
+        .map_err(|e| format!("could not open audit log {}: {}", path.display(), e))?;
+
+    let mut entries = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let entry = serde_json::from_str(&line)
+            .map_err(|e| format!("{}:{}: invalid audit entry: {}", path.display(), i + 1, e))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+// checks that every entry hashes to its recorded hash and points at its predecessor, returns the entry count
+pub fn verify(path: &Path) -> Result<usize, Box<dyn Error>> {
+    let mut prev = GENESIS.to_owned();
+    let entries = read_entries(path)?;
+    let entry_count = entries.len();
+
+    for (i, mut entry) in entries.into_iter().enumerate() {
+        if entry.seq != i as u64 || entry.prev != prev {
+            return Err(format!("{}:{}: chain broken, entries were removed or reordered", path.display(), i + 1).into());
+        }
+        if entry.compute_hash()? != entry.hash {
+            return Err(format!("{}:{}: hash mismatch, entry was modified", path.display(), i + 1).into());
+        }
+        prev = entry.hash;
+    }
+    Ok(entry_count)
+}
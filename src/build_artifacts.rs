@@ -0,0 +1,45 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// curated lists of regenerable build output and caches for skip.build_artifacts, extended with classifiles releases;
+// skip.artifact_dir_names adds further names
+
+// directory names that are build output or caches wherever they occur
+static ARTIFACT_DIR_NAMES: &[&str] = &[
+    "node_modules", "__pycache__", ".pytest_cache", ".mypy_cache", ".ruff_cache", ".tox", ".nox", ".gradle",
+    ".next", ".nuxt", ".parcel-cache", ".terraform", ".cache",
+];
+
+// common names that are only build output with a marker file of the tool inside, e.g. target/ of cargo
+static MARKED_DIRS: &[(&str, &str)] = &[
+    ("target", ".rustc_info.json"),
+    ("target", "maven-status"),
+    ("build", "CMakeCache.txt"),
+    ("build", "build.ninja"),
+    ("venv", "pyvenv.cfg"),
+    (".venv", "pyvenv.cfg"),
+];
+
+// https://bford.info/cachedir/, written by cargo, ccache, restic and others into cache directories of any name
+static CACHEDIR_TAG: &str = "CACHEDIR.TAG";
+static CACHEDIR_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+fn has_cachedir_tag(dir: &Path) -> bool {
+    let mut signature = vec![0; CACHEDIR_SIGNATURE.len()];
+    File::open(dir.join(CACHEDIR_TAG))
+        .and_then(|mut tag| tag.read_exact(&mut signature))
+        .is_ok_and(|_| signature == CACHEDIR_SIGNATURE)
+}
+
+pub fn is_artifact_dir(dir: &Path, extra_names: &[String]) -> bool {
+    let name = match dir.file_name() {
+        Some(name) => name,
+        None => return false,
+    };
+    ARTIFACT_DIR_NAMES.iter().any(|n| OsStr::new(n) == name)
+        || extra_names.iter().any(|n| OsStr::new(n) == name)
+        || MARKED_DIRS.iter().any(|(n, marker)| OsStr::new(n) == name && dir.join(marker).exists())
+        || has_cachedir_tag(dir)
+}
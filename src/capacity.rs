@@ -0,0 +1,107 @@
+use std::error::Error;
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use fnv::FnvHashSet;
+use slog::{Logger, debug, warn};
+
+use crate::manifest::Record;
+use crate::report::format_size;
+use crate::{ClassifierError, ErrorKind, LinkMode, OutputSplit, Params, OUTPUT_UNKNOWN};
+
+// warn when the output would take more than this share of what is free
+static WARN_PERCENT: u64 = 90;
+
+// what materialize is about to create, directories of merged outputs that exist already are not counted
+struct Estimate {
+    inodes: u64,
+    bytes: u64,
+}
+
+// free inodes (none on filesystems without a fixed inode table, e.g. btrfs) and bytes
+fn free_capacity(path: &Path) -> io::Result<(Option<u64>, u64)> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let inodes = if stat.f_files == 0 { None } else { Some(stat.f_favail as u64) };
+    Ok((inodes, stat.f_bavail as u64 * stat.f_frsize as u64))
+}
+
+fn estimate(records: &[Record], params: &Params) -> Estimate {
+    // type directories mirror the input directories unless the layout is flat (routes are not known yet)
+    let mut dirs = FnvHashSet::default();
+    for record in records {
+        let dir = match &record.link {
+            Some(link) => link.parent().map(Path::to_owned).unwrap_or_default(),
+            None => {
+                let type_dir = PathBuf::from(record.mime.as_deref().unwrap_or(OUTPUT_UNKNOWN));
+                let input_rel_dir = match params.flat {
+                    true => None,
+                    false => record.path.strip_prefix(&params.input_path).ok().and_then(Path::parent),
+                };
+                input_rel_dir.map_or_else(|| type_dir.clone(), |rel| type_dir.join(rel))
+            }
+        };
+        for dir in dir.ancestors().take_while(|dir| !dir.as_os_str().is_empty()) {
+            if !dirs.insert(dir.to_owned()) {
+                break;
+            }
+        }
+    }
+    let mut new_dirs = dirs.iter().filter(|dir| !params.output_path.join(dir).is_dir()).count() as u64;
+    // round-robin may create every directory in every root
+    if params.output_split == OutputSplit::RoundRobin {
+        new_dirs *= 1 + params.extra_outputs.len() as u64;
+    }
+
+    let (links, bytes) = match params.link_mode {
+        LinkMode::Symlink => (records.len() as u64, 0),
+        // hardlinks share the inode of the input file
+        LinkMode::Hardlink => (0, 0),
+        LinkMode::Copy => (records.len() as u64, records.iter().filter_map(|r| r.size).sum()),
+    };
+    Estimate{inodes: new_dirs + links, bytes}
+}
+
+// compares the estimate with the free inodes and space of the output filesystems (counted once when several
+// output roots share one), fails when it does not fit and warns when it barely does
+pub fn check(records: &[Record], params: &Params, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let needed = estimate(records, params);
+
+    let mut devices = FnvHashSet::default();
+    let mut free_inodes = Some(0);
+    let mut free_bytes = 0;
+    for root in std::iter::once(&params.output_path).chain(&params.extra_outputs) {
+        if !devices.insert(root.metadata()?.dev()) {
+            continue;
+        }
+        let (inodes, bytes) = free_capacity(root)
+            .map_err(|e| format!("could not get free space of {}: {}", root.display(), e))?;
+        free_inodes = free_inodes.zip(inodes).map(|(free, inodes)| free + inodes);
+        free_bytes += bytes;
+    }
+    debug!(log, "Output needs about {} inodes and {}, {} inodes and {} free", needed.inodes,
+        format_size(needed.bytes), free_inodes.map_or("unlimited".to_owned(), |i| i.to_string()), format_size(free_bytes));
+
+    let fits = |needed: u64, free: u64| needed <= free;
+    let tight = |needed: u64, free: u64| needed.saturating_mul(100) > free.saturating_mul(WARN_PERCENT);
+    if !free_inodes.is_none_or(|free| fits(needed.inodes, free)) || !fits(needed.bytes, free_bytes) {
+        return Err(Box::new(ClassifierError(ErrorKind::InvalidOutput, format!(
+            "output needs about {} inodes and {}, but only {} inodes and {} are free, use --skip-capacity-check to try anyway",
+            needed.inodes, format_size(needed.bytes),
+            free_inodes.map_or("unlimited".to_owned(), |i| i.to_string()), format_size(free_bytes)
+        ))));
+    }
+    if free_inodes.is_some_and(|free| tight(needed.inodes, free)) {
+        warn!(log, "Output will use more than {} % of the free inodes", WARN_PERCENT);
+    }
+    if needed.bytes > 0 && tight(needed.bytes, free_bytes) {
+        warn!(log, "Output will use more than {} % of the free space", WARN_PERCENT);
+    }
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::error::Error;
+use std::io::Read;
+
+// the start of a file format as it may appear anywhere inside a disk image or memory dump
+pub struct Signature {
+    pub name: &'static str,
+    magic: &'static [u8],
+    // of the magic from the start of the file, e.g. the ftyp box of MP4 follows its size
+    offset: usize,
+}
+
+const fn sig(name: &'static str, magic: &'static [u8], offset: usize) -> Signature {
+    Signature{name, magic, offset}
+}
+
+// formats worth recovering with magic long enough not to match every few KiB of random data (MZ and BM are not)
+static SIGNATURES: &[Signature] = &[
+    sig("jpeg", b"\xff\xd8\xff", 0),
+    sig("png", b"\x89PNG\r\n\x1a\n", 0),
+    sig("gif", b"GIF87a", 0),
+    sig("gif", b"GIF89a", 0),
+    sig("tiff", b"II*\x00", 0),
+    sig("tiff", b"MM\x00*", 0),
+    sig("riff", b"RIFF", 0),
+    sig("mp4", b"ftyp", 4),
+    sig("matroska", b"\x1a\x45\xdf\xa3", 0),
+    sig("ogg", b"OggS\x00", 0),
+    sig("flac", b"fLaC", 0),
+    sig("mp3", b"ID3", 0),
+    sig("pdf", b"%PDF-", 0),
+    sig("zip", b"PK\x03\x04", 0),
+    sig("ole", b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", 0),
+    sig("7z", b"7z\xbc\xaf\x27\x1c", 0),
+    sig("rar", b"Rar!\x1a\x07", 0),
+    sig("gzip", b"\x1f\x8b\x08", 0),
+    sig("bzip2", b"BZh", 0),
+    sig("xz", b"\xfd7zXZ\x00", 0),
+    sig("zstd", b"\x28\xb5\x2f\xfd", 0),
+    sig("tar", b"ustar", 257),
+    sig("sqlite", b"SQLite format 3\x00", 0),
+    sig("elf", b"\x7fELF", 0),
+];
+
+const CHUNK_LEN: usize = 4 * 1024 * 1024;
+
+// calls f with the start offset and signature of every match in the order of the data, returns the number of bytes
+// read; chunks overlap by the longest magic, so that matches across chunk borders are found, and found once
+pub fn scan(reader: &mut dyn Read, f: &mut dyn FnMut(u64, &'static Signature) -> Result<(), Box<dyn Error>>)
+    -> Result<u64, Box<dyn Error>> {
+
+    let overlap = SIGNATURES.iter().map(|s| s.magic.len()).max().unwrap_or(1) - 1;
+    // signatures by their first byte
+    let mut by_first_byte: Vec<Vec<&'static Signature>> = vec![Vec::new(); 256];
+    for signature in SIGNATURES {
+        by_first_byte[signature.magic[0] as usize].push(signature);
+    }
+
+    let mut buf = Vec::with_capacity(CHUNK_LEN + overlap);
+    // absolute offset of buf[0]
+    let mut base = 0u64;
+    loop {
+        let read = (&mut *reader).take(CHUNK_LEN as u64).read_to_end(&mut buf)?;
+        let at_end = read == 0;
+        // the last bytes are examined with the next chunk, when more magic may fit behind them
+        let examined = match at_end {
+            true => buf.len(),
+            false => buf.len().saturating_sub(overlap),
+        };
+        for pos in 0..examined {
+            for signature in &by_first_byte[buf[pos] as usize] {
+                let absolute = base + pos as u64;
+                if buf[pos..].starts_with(signature.magic) && absolute >= signature.offset as u64 {
+                    f(absolute - signature.offset as u64, signature)?;
+                }
+            }
+        }
+        if at_end {
+            return Ok(base + buf.len() as u64);
+        }
+        buf.drain(..examined);
+        base += examined as u64;
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use fnv::FnvHashSet;
+
+// on case-insensitive filesystems (exFAT, NTFS, FAT32, default APFS) Foo.JPG and foo.jpg are the same entry
+
+static PROBE_NAME: &str = ".classifiles-case-probe";
+
+// a lower-case probe file is looked up in upper case
+pub fn is_case_insensitive(dir: &Path) -> io::Result<bool> {
+    let probe = dir.join(format!("{}-{}", PROBE_NAME, std::process::id()));
+    fs::File::create(&probe)?;
+    let found = fs::symlink_metadata(dir.join(format!("{}-{}", PROBE_NAME.to_uppercase(), std::process::id()))).is_ok();
+    fs::remove_file(&probe)?;
+    Ok(found)
+}
+
+// lower case, bytes that are not valid UTF-8 are kept as they are
+fn folded(path: &Path) -> Vec<u8> {
+    let mut folded = Vec::with_capacity(path.as_os_str().len());
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        folded.extend_from_slice(chunk.valid().to_lowercase().as_bytes());
+        folded.extend_from_slice(chunk.invalid());
+    }
+    folded
+}
+
+// output paths created by this run, compared case-folded
+#[derive(Default)]
+pub struct FoldedPaths {
+    paths: FnvHashSet<Vec<u8>>,
+}
+
+impl FoldedPaths {
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.contains(&folded(path))
+    }
+
+    // false when the path, or one differing only in case, was created before
+    pub fn insert(&mut self, path: &Path) -> bool {
+        self.paths.insert(folded(path))
+    }
+}
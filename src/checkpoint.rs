@@ -0,0 +1,96 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::manifest::{self, Record};
+
+// progress of scan --checkpoint by pipeline stage in OUTPUT_DIR/.classifiles/checkpoint, removed once the scan
+// has written its manifest:
+//   walk.list         input paths to classify as walked, NUL-separated, complete or missing
+//   classified.jsonl  records in manifest format, appended as each file is classified
+//   linked.jsonl      the same records with their output entry, appended as each file is linked
+static CHECKPOINT_DIR: &str = "checkpoint";
+pub static WALK_LIST: &str = "walk.list";
+pub static CLASSIFIED: &str = "classified.jsonl";
+pub static LINKED: &str = "linked.jsonl";
+
+pub struct Checkpoint {
+    dir: PathBuf,
+}
+
+// appends one record per write, so that at most the record being written when the run died is lost
+pub struct Stage {
+    out: File,
+}
+
+impl Stage {
+    pub fn append(&mut self, record: &Record) -> Result<(), Box<dyn Error>> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        self.out.write_all(&line)?;
+        Ok(())
+    }
+}
+
+impl Checkpoint {
+    pub fn open(output_root: &Path) -> Result<Self, Box<dyn Error>> {
+        let dir = manifest::state_dir(output_root).join(CHECKPOINT_DIR);
+        fs::create_dir_all(&dir)?;
+        Ok(Self{dir})
+    }
+
+    pub fn walk_list(&self) -> Result<Option<Vec<PathBuf>>, Box<dyn Error>> {
+        let bytes = match fs::read(self.dir.join(WALK_LIST)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(bytes.split(|&b| b == 0)
+            .filter(|path| !path.is_empty())
+            .map(|path| PathBuf::from(OsStr::from_bytes(path)))
+            .collect()))
+    }
+
+    // written in full before it replaces a missing list
+    pub fn write_walk_list(&self, paths: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+        let partial = self.dir.join(format!("{}.partial", WALK_LIST));
+        let mut out = std::io::BufWriter::new(File::create(&partial)?);
+        for path in paths {
+            out.write_all(path.as_os_str().as_bytes())?;
+            out.write_all(b"\0")?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(partial, self.dir.join(WALK_LIST))?;
+        Ok(())
+    }
+
+    // records of a stage so far and the stage to append to, a line cut off by a crash is dropped
+    pub fn resume(&self, stage: &str) -> Result<(Vec<Record>, Stage), Box<dyn Error>> {
+        let mut out = OpenOptions::new().create(true).read(true).append(true).open(self.dir.join(stage))?;
+        let mut records = Vec::new();
+        let mut valid_len = 0;
+        let mut reader = BufReader::new(&mut out);
+        let mut line = Vec::new();
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            if line.last() != Some(&b'\n') {
+                break;
+            }
+            match serde_json::from_slice(&line) {
+                Ok(record) => records.push(record),
+                Err(_) => break,
+            }
+            valid_len += line.len() as u64;
+            line.clear();
+        }
+        out.set_len(valid_len)?;
+        Ok((records, Stage{out}))
+    }
+
+    pub fn remove(self) -> Result<(), Box<dyn Error>> {
+        fs::remove_dir_all(&self.dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::manifest::{self, Record};
+
+static CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+// line formats of sha256sum (coreutils) and sha256 -r / shasum --tag (BSD)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumFormat {
+    Gnu,
+    Bsd,
+}
+
+impl FromStr for ChecksumFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gnu" => Ok(ChecksumFormat::Gnu),
+            "bsd" => Ok(ChecksumFormat::Bsd),
+            _ => Err(format!("invalid checksum format {}, valid formats are: gnu, bsd", s)),
+        }
+    }
+}
+
+pub fn checksums_path(output_root: &Path) -> PathBuf {
+    manifest::state_dir(output_root).join(CHECKSUMS_FILE)
+}
+
+// the line of a hashed file: its output entry relative to the output root, so that sha256sum -c run there checks the
+// output tree from any machine; files without an entry are listed by their (absolute) input path
+pub fn entry(record: &Record) -> Option<(PathBuf, String)> {
+    let path = record.link.as_ref().unwrap_or(&record.path);
+    record.sha256.as_ref().map(|hash| (path.clone(), hash.clone()))
+}
+
+// coreutils escapes names with a backslash or a newline and marks such lines with a leading backslash (both formats)
+fn escaped_name(path: &Path) -> (bool, Vec<u8>) {
+    let name = path.as_os_str().as_bytes();
+    if !name.contains(&b'\\') && !name.contains(&b'\n') {
+        return (false, name.to_vec());
+    }
+    let mut escaped = Vec::with_capacity(name.len() + 2);
+    for &b in name {
+        match b {
+            b'\\' => escaped.extend_from_slice(b"\\\\"),
+            b'\n' => escaped.extend_from_slice(b"\\n"),
+            _ => escaped.push(b),
+        }
+    }
+    (true, escaped)
+}
+
+fn line(hash: &str, path: &Path, format: ChecksumFormat) -> Vec<u8> {
+    let (escaped, name) = escaped_name(path);
+    let mut line = Vec::with_capacity(hash.len() + name.len() + 16);
+    if escaped {
+        line.push(b'\\');
+    }
+    match format {
+        ChecksumFormat::Gnu => {
+            line.extend_from_slice(hash.as_bytes());
+            line.extend_from_slice(b"  ");
+            line.extend_from_slice(&name);
+        }
+        ChecksumFormat::Bsd => {
+            line.extend_from_slice(b"SHA256 (");
+            line.extend_from_slice(&name);
+            line.extend_from_slice(b") = ");
+            line.extend_from_slice(hash.as_bytes());
+        }
+    }
+    line.push(b'\n');
+    line
+}
+
+pub fn write(path: &Path, checksums: &[(PathBuf, String)], format: ChecksumFormat) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(File::create(path)?);
+    for (file_path, hash) in checksums {
+        out.write_all(&line(hash, file_path, format))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn unescape(name: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(name.len());
+    let mut bytes = name.iter();
+    while let Some(&b) = bytes.next() {
+        match (b, bytes.clone().next()) {
+            (b'\\', Some(b'n')) => { unescaped.push(b'\n'); bytes.next(); }
+            (b'\\', Some(b'\\')) => { unescaped.push(b'\\'); bytes.next(); }
+            _ => unescaped.push(b),
+        }
+    }
+    unescaped
+}
+
+fn is_sha256(hash: &[u8]) -> bool {
+    hash.len() == 64 && hash.iter().all(u8::is_ascii_hexdigit)
+}
+
+// either format, the GNU binary mode marker (*) is accepted as well
+fn parse_line(line: &[u8]) -> Option<(PathBuf, String)> {
+    let (escaped, line) = match line.strip_prefix(b"\\") {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let (hash, name) = match line.strip_prefix(b"SHA256 (") {
+        Some(rest) => {
+            let split = rest.windows(4).rposition(|w| w == b") = ")?;
+            (&rest[split + 4..], &rest[..split])
+        }
+        None => match (line.get(..64), line.get(64..66), line.get(66..)) {
+            (Some(hash), Some(b"  "), Some(name)) | (Some(hash), Some(b" *"), Some(name)) => (hash, name),
+            _ => return None,
+        },
+    };
+    if !is_sha256(hash) {
+        return None;
+    }
+    let name = if escaped { unescape(name) } else { name.to_vec() };
+    Some((PathBuf::from(OsStr::from_bytes(&name)), String::from_utf8_lossy(hash).to_ascii_lowercase()))
+}
+
+pub fn read(path: &Path) -> Result<Vec<(PathBuf, String)>, Box<dyn Error>> {
+    let file = File::open(path)
+        .map_err(|e| format!("could not open checksums {}: {}", path.display(), e))?;
+
+    let mut checksums = Vec::new();
+    for (i, line) in BufReader::new(file).split(b'\n').enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let checksum = parse_line(&line)
+            .ok_or_else(|| format!("{}:{}: not a SHA-256 checksum line", path.display(), i + 1))?;
+        checksums.push(checksum);
+    }
+    Ok(checksums)
+}
@@ -0,0 +1,108 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+// files submitted to a running clamd during scan and sync, its verdict is recorded in the manifest
+#[derive(Debug, Clone, Default)]
+pub struct Clamav {
+    // clamd's LocalSocket, e.g. /run/clamav/clamd.ctl
+    pub socket: PathBuf,
+    // MIME patterns as for --only-mime of the files submitted, all regular files if empty
+    pub mime: Vec<String>,
+    // detected files are moved below it (keeping their input path) and linked from there
+    pub quarantine: Option<PathBuf>,
+}
+
+// the verdict of files clamd found nothing in, others get the name of the signature
+pub const CLEAN: &str = "OK";
+
+// chunks of the INSTREAM command; files above clamd's StreamMaxLength (25 MiB by default) are refused
+const CHUNK_LEN: usize = 64 * 1024;
+// scanning a large archive may take clamd minutes
+const REPLY_TIMEOUT: Duration = Duration::from_secs(600);
+
+impl Clamav {
+    pub fn wants(&self, mime: &str) -> bool {
+        self.mime.is_empty() || self.mime.iter().any(|pattern| crate::mime_matches(pattern, mime))
+    }
+}
+
+// clamd closes the connection after the reply to a z-command, which ends with a NUL byte
+fn request(socket: &Path, command: &[u8], body: &mut dyn FnMut(&mut UnixStream) -> io::Result<()>)
+    -> io::Result<String> {
+
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+    stream.write_all(command)?;
+    // a refused stream is cut short by clamd, its reply tells why
+    let sent = body(&mut stream);
+    let mut reply = Vec::new();
+    let received = stream.read_to_end(&mut reply);
+    let reply = String::from_utf8_lossy(&reply).trim_end_matches(['\0', '\n']).to_owned();
+    match (sent, received) {
+        (Err(e), _) if reply.is_empty() => Err(e),
+        (_, Err(e)) => Err(e),
+        _ => Ok(reply),
+    }
+}
+
+// fails early when clamd is not running, before the output root is touched
+pub fn check(clamav: &Clamav) -> Result<(), Box<dyn Error>> {
+    match request(&clamav.socket, b"zPING\0", &mut |_| Ok(())) {
+        Ok(reply) if reply == "PONG" => Ok(()),
+        Ok(reply) => Err(format!("unexpected reply of clamd at {}: {}", clamav.socket.display(), reply).into()),
+        Err(e) => Err(format!("could not reach clamd at {}: {}", clamav.socket.display(), e).into()),
+    }
+}
+
+// streams the file to clamd, which needs no access to it; CLEAN or the name of the signature found
+pub fn scan(clamav: &Clamav, path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0; CHUNK_LEN];
+    let reply = request(&clamav.socket, b"zINSTREAM\0", &mut |stream| {
+        loop {
+            let n = match file.read(&mut buf) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            stream.write_all(&(n as u32).to_be_bytes())?;
+            if n == 0 {
+                return Ok(());
+            }
+            stream.write_all(&buf[..n])?;
+        }
+    }).map_err(|e| format!("clamd: {}", e))?;
+
+    match reply.strip_prefix("stream: ") {
+        Some(CLEAN) => Ok(CLEAN.to_owned()),
+        Some(found) if found.ends_with(" FOUND") => Ok(found.trim_end_matches(" FOUND").to_owned()),
+        _ => Err(format!("clamd: {}", reply).into()),
+    }
+}
+
+// moves a detected file to the same path below the quarantine root, readable by its owner only
+pub fn quarantine(root: &Path, path: &Path) -> io::Result<PathBuf> {
+    let relative: PathBuf = path.components().filter(|c| matches!(c, Component::Normal(_))).collect();
+    let target = root.join(relative);
+    if fs::symlink_metadata(&target).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", target.display())));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::rename(path, &target) {
+        // the quarantine is on another filesystem
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            fs::copy(path, &target)?;
+            fs::remove_file(path)?;
+        }
+        result => result?,
+    }
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o400))?;
+    Ok(target)
+}
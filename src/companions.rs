@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use fnv::{FnvHashMap, FnvHashSet};
+use slog::{Logger, debug};
+
+use crate::manifest::Record;
+
+// files laid out next to a primary file of the same name instead of by their own type, e.g. the JPEG and XMP
+// sidecars of a camera raw file, the subtitles of a video or the checksum of a download
+#[derive(Debug, Clone, Default)]
+pub struct CompanionRule {
+    // MIME pattern as for --only-mime
+    pub primary: String,
+    // without the dot, in any case; companions are named NAME.EXT, NAME.*.EXT (movie.en.srt) or
+    // NAME.PRIMARY_EXT.EXT (disk.iso.sha256) for a primary file NAME.PRIMARY_EXT
+    pub extensions: Vec<String>,
+}
+
+// the last extension of a companion's name is compared, so it is given without any dot
+pub fn check_companion_extensions(extensions: &[String]) -> Result<(), String> {
+    if extensions.is_empty() {
+        return Err("empty list".to_owned());
+    }
+    match extensions.iter().find(|ext| ext.is_empty() || ext.contains(['.', '/'])) {
+        Some(ext) => Err(format!("invalid extension {:?}, expected one like xmp without the dot", ext)),
+        None => Ok(()),
+    }
+}
+
+// the name without its last extension, all of it for .bashrc
+fn stem(name: &str) -> &str {
+    match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    }
+}
+
+impl CompanionRule {
+    fn companion_of(&self, primary_name: &str, name: &str) -> bool {
+        let stem = stem(primary_name);
+        let rest = match name.strip_prefix(stem).and_then(|rest| rest.strip_prefix('.')) {
+            Some(rest) => rest,
+            None => return false,
+        };
+        // the middle part is a language code or the extension of the primary file
+        let ext = rest.rsplit_once('.').map_or(rest, |(_, ext)| ext);
+        name != primary_name && self.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext))
+    }
+}
+
+// sets companion_of of the records of companion files, the first rule and then the first primary file by name wins;
+// files already taken as companions are not primary files
+pub fn mark(rules: &[CompanionRule], records: &mut [Record], log: &Logger) {
+    let mut dirs: FnvHashMap<&Path, BTreeMap<&str, usize>> = FnvHashMap::default();
+    for (i, record) in records.iter().enumerate() {
+        let name = record.path.file_name().and_then(|name| name.to_str());
+        if let (Some(dir), Some(name)) = (record.path.parent(), name) {
+            dirs.entry(dir).or_default().insert(name, i);
+        }
+    }
+
+    // companion, primary
+    let mut companions: FnvHashMap<usize, usize> = FnvHashMap::default();
+    for rule in rules {
+        for names in dirs.values() {
+            for (primary_name, &primary) in names {
+                let mime = records[primary].mime.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN);
+                if companions.contains_key(&primary) || !crate::mime_matches(&rule.primary, mime) {
+                    continue;
+                }
+                let stem = stem(primary_name);
+                // names sharing the stem are next to each other
+                for (name, &companion) in names.range(stem..).take_while(|(name, _)| name.starts_with(stem)) {
+                    if rule.companion_of(primary_name, name) && !companions.contains_key(&companion) {
+                        companions.insert(companion, primary);
+                    }
+                }
+            }
+        }
+    }
+
+    // a primary file found to be a companion itself under a later rule takes its companions along
+    let marked: Vec<(usize, PathBuf)> = companions.iter()
+        .map(|(&companion, &primary)| {
+            let mut primary = primary;
+            while let Some(&next) = companions.get(&primary) {
+                primary = next;
+            }
+            (companion, records[primary].path.clone())
+        })
+        .collect();
+    debug!(log, "Found {} companion files", marked.len());
+    for (companion, primary) in marked {
+        records[companion].companion_of = Some(primary);
+    }
+}
+
+// the records of the primary files of the companions among records by their input paths, companions are laid out
+// by them, next to them
+pub fn primaries<'a>(records: &'a [Record], merged: &'a [Record]) -> FnvHashMap<&'a Path, &'a Record> {
+    let wanted: FnvHashSet<&Path> = records.iter().filter_map(|record| record.companion_of.as_deref()).collect();
+    if wanted.is_empty() {
+        return FnvHashMap::default();
+    }
+    merged.iter().chain(records).filter(|record| wanted.contains(record.path.as_path()))
+        .map(|record| (record.path.as_path(), record))
+        .collect()
+}
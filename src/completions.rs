@@ -0,0 +1,115 @@
+use crate::{ValueKind, VerbSpec, COMMON_FLAGS, COMMON_VALUE_OPTS};
+
+static PROGRAM: &str = "classifiles";
+static MIME_TYPES_CMD: &str = "classifiles mime-types 2>/dev/null";
+
+fn flags(verb: &VerbSpec) -> impl Iterator<Item = &'static str> + '_ {
+    verb.flags.iter().chain(COMMON_FLAGS).copied()
+}
+
+fn value_opts(verb: &VerbSpec) -> impl Iterator<Item = (&'static str, ValueKind)> + '_ {
+    verb.value_opts.iter().chain(COMMON_VALUE_OPTS).copied()
+}
+
+fn verb_names(verbs: &[&VerbSpec]) -> String {
+    verbs.iter().map(|v| v.name).collect::<Vec<_>>().join(" ")
+}
+
+fn bash_compgen(kind: ValueKind) -> String {
+    match kind {
+        ValueKind::Path => "COMPREPLY=($(compgen -f -- \"$cur\"))".to_owned(),
+        ValueKind::Words(words) => format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", words.join(" ")),
+        ValueKind::MimeType => format!("COMPREPLY=($(compgen -W \"$({})\" -- \"$cur\"))", MIME_TYPES_CMD),
+        ValueKind::Any => "COMPREPLY=()".to_owned(),
+    }
+}
+
+pub fn bash(verbs: &[&VerbSpec]) -> String {
+    let mut script = format!("_{}() {{\n", PROGRAM);
+    script += "    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n";
+    script += "    if [ \"$COMP_CWORD\" -eq 1 ]; then\n";
+    script += &format!("        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n        return\n    fi\n", verb_names(verbs));
+    script += "    case \"${COMP_WORDS[1]}\" in\n";
+
+    for verb in verbs {
+        script += &format!("    {})\n        case \"$prev\" in\n", verb.name);
+        for (opt, kind) in value_opts(verb) {
+            script += &format!("            {}) {}; return;;\n", opt, bash_compgen(kind));
+        }
+        script += "        esac\n";
+        let opts: Vec<&str> = flags(verb).chain(value_opts(verb).map(|(opt, _)| opt)).collect();
+        script += &format!("        if [[ \"$cur\" == -* ]]; then\n            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n", opts.join(" "));
+        script += &format!("        else\n            {}\n        fi\n        ;;\n", bash_compgen(verb.positional));
+    }
+
+    script += "    esac\n}\n";
+    script += &format!("complete -o filenames -F _{0} {0}\n", PROGRAM);
+    script
+}
+
+fn zsh_action(kind: ValueKind) -> String {
+    match kind {
+        ValueKind::Path => "_files".to_owned(),
+        ValueKind::Words(words) => format!("({})", words.join(" ")),
+        ValueKind::MimeType => format!("{{compadd -- ${{(f)\"$({})\"}}}}", MIME_TYPES_CMD),
+        ValueKind::Any => " ".to_owned(),
+    }
+}
+
+pub fn zsh(verbs: &[&VerbSpec]) -> String {
+    let mut script = format!("#compdef {0}\n\n_{0}() {{\n", PROGRAM);
+    script += "    if (( CURRENT == 2 )); then\n";
+    script += &format!("        compadd {}\n        return\n    fi\n", verb_names(verbs));
+    script += "    local verb=$words[2]\n    shift words\n    (( CURRENT-- ))\n";
+    script += "    case $verb in\n";
+
+    for verb in verbs {
+        let mut specs: Vec<String> = flags(verb).map(|flag| format!("'{}'", flag)).collect();
+        specs.extend(value_opts(verb).map(|(opt, kind)| format!("'{}:value:{}'", opt, zsh_action(kind))));
+        specs.push(format!("'*:argument:{}'", zsh_action(verb.positional)));
+        script += &format!("    {})\n        _arguments {}\n        ;;\n", verb.name, specs.join(" \\\n            "));
+    }
+
+    script += "    esac\n}\n\n";
+    script += &format!("_{} \"$@\"\n", PROGRAM);
+    script
+}
+
+// long options map to -l, single-dash ones (-q, -vv) to old-style -o
+fn fish_option(opt: &str) -> String {
+    match opt.strip_prefix("--") {
+        Some(long) => format!("-l {}", long),
+        None => format!("-o {}", opt.trim_start_matches('-')),
+    }
+}
+
+fn fish_args(kind: ValueKind) -> String {
+    match kind {
+        ValueKind::Path => "-r -F".to_owned(),
+        ValueKind::Words(words) => format!("-x -a \"{}\"", words.join(" ")),
+        ValueKind::MimeType => format!("-x -a \"({})\"", MIME_TYPES_CMD),
+        ValueKind::Any => "-x".to_owned(),
+    }
+}
+
+pub fn fish(verbs: &[&VerbSpec]) -> String {
+    let mut script = format!("complete -c {} -f\n", PROGRAM);
+    script += &format!("complete -c {} -n __fish_use_subcommand -a \"{}\"\n", PROGRAM, verb_names(verbs));
+
+    for verb in verbs {
+        let condition = format!("complete -c {} -n \"__fish_seen_subcommand_from {}\"", PROGRAM, verb.name);
+        for flag in flags(verb) {
+            script += &format!("{} {}\n", condition, fish_option(flag));
+        }
+        for (opt, kind) in value_opts(verb) {
+            script += &format!("{} {} {}\n", condition, fish_option(opt), fish_args(kind));
+        }
+        match verb.positional {
+            ValueKind::Path => script += &format!("{} -F\n", condition),
+            ValueKind::Words(words) => script += &format!("{} -a \"{}\"\n", condition, words.join(" ")),
+            ValueKind::MimeType => script += &format!("{} -a \"({})\"\n", condition, MIME_TYPES_CMD),
+            ValueKind::Any => (),
+        }
+    }
+    script
+}
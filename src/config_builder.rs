@@ -0,0 +1,336 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{Clamav, ClassifierError, CompanionRule, Config, DetectionStrategy, Detector, ErrorKind,
+    Geocoding, Hook, NameSanitization, Notify, PhotoEvents, PolicyRule, ReadBackend, TagRule};
+
+// Config for library users: starts from the defaults and checks the referenced paths and the numeric limits in
+// build(), like config.yaml is checked by the command line. The plain struct keeps its public fields for existing
+// callers, which get no checks; Config::default() is deprecated to point them here.
+//
+//   let config = ConfigBuilder::new().libmagic_db_file("/usr/share/misc/magic.mgc").pipeline_depth(64).build()?;
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl Config {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
+    // shadows Default::default() for Config::default() and Config{.., ..Config::default()}, the unchecked way
+    #[deprecated(note = "use Config::builder(), which checks the configuration")]
+    #[allow(clippy::should_implement_trait)]
+    pub fn default() -> Self {
+        Default::default()
+    }
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mime_info_db_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.config.mime_info_db_root = root.into();
+        self
+    }
+
+    pub fn libmagic_db_file(mut self, db_file: impl Into<PathBuf>) -> Self {
+        self.config.libmagic_db_file = Some(db_file.into());
+        self
+    }
+
+    pub fn libmagic_used_for(mut self, mime_types: Vec<String>) -> Self {
+        self.config.libmagic_used_for = mime_types;
+        self
+    }
+
+    pub fn create_output(mut self, create: bool) -> Self {
+        self.config.create_output = create;
+        self
+    }
+
+    pub fn skip_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.config.skip_paths = paths;
+        self
+    }
+
+    pub fn skip_dir_names(mut self, names: Vec<String>) -> Self {
+        self.config.skip_dir_names = names;
+        self
+    }
+
+    pub fn skip_build_artifacts(mut self, skip: bool) -> Self {
+        self.config.skip_build_artifacts = skip;
+        self
+    }
+
+    pub fn artifact_dir_names(mut self, names: Vec<String>) -> Self {
+        self.config.artifact_dir_names = names;
+        self
+    }
+
+    // None disables learned overrides
+    pub fn overrides_db(mut self, db: Option<PathBuf>) -> Self {
+        self.config.overrides_db = db;
+        self
+    }
+
+    pub fn hook(mut self, hook: Hook) -> Self {
+        self.config.hooks.push(hook);
+        self
+    }
+
+    pub fn tag_rule(mut self, rule: TagRule) -> Self {
+        self.config.tag_rules.push(rule);
+        self
+    }
+
+    pub fn policy_rule(mut self, rule: PolicyRule) -> Self {
+        self.config.policy.push(rule);
+        self
+    }
+
+    pub fn companion_rule(mut self, rule: CompanionRule) -> Self {
+        self.config.companions.push(rule);
+        self
+    }
+
+    pub fn photo_events(mut self, events: PhotoEvents) -> Self {
+        self.config.photo_events = Some(events);
+        self
+    }
+
+    pub fn geocoding(mut self, geocoding: Geocoding) -> Self {
+        self.config.geocoding = Some(geocoding);
+        self
+    }
+
+    pub fn routing_script(mut self, script: impl Into<PathBuf>) -> Self {
+        self.config.routing_script = Some(script.into());
+        self
+    }
+
+    pub fn plugins_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.plugins_dir = Some(dir.into());
+        self
+    }
+
+    pub fn detectors(mut self, detectors: Vec<Detector>) -> Self {
+        self.config.detectors = detectors;
+        self
+    }
+
+    pub fn detection_strategy(mut self, strategy: DetectionStrategy) -> Self {
+        self.config.detection_strategy = strategy;
+        self
+    }
+
+    pub fn read_buffer_size(mut self, size: usize) -> Self {
+        self.config.read_buffer_size = size;
+        self
+    }
+
+    pub fn read_backend(mut self, backend: ReadBackend) -> Self {
+        self.config.read_backend = backend;
+        self
+    }
+
+    pub fn mmap_min_size(mut self, size: u64) -> Self {
+        self.config.mmap_min_size = Some(size);
+        self
+    }
+
+    pub fn pipeline_depth(mut self, depth: usize) -> Self {
+        self.config.pipeline_depth = depth;
+        self
+    }
+
+    pub fn sniff_buffer_size(mut self, size: usize) -> Self {
+        self.config.sniff_buffer_size = size;
+        self
+    }
+
+    pub fn classify_timeout(mut self, timeout: Duration) -> Self {
+        self.config.classify_timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_dir_entries(mut self, entries: usize) -> Self {
+        self.config.max_dir_entries = Some(entries);
+        self
+    }
+
+    pub fn sanitize_names(mut self, sanitization: NameSanitization) -> Self {
+        self.config.sanitize_names = sanitization;
+        self
+    }
+
+    pub fn executable_paths(mut self, globs: Vec<String>) -> Self {
+        self.config.executable_paths = globs;
+        self
+    }
+
+    pub fn lowercase_extensions(mut self, lowercase: bool) -> Self {
+        self.config.lowercase_extensions = lowercase;
+        self
+    }
+
+    pub fn notify(mut self, notify: Notify) -> Self {
+        self.config.notify = notify;
+        self
+    }
+
+    pub fn entropy(mut self, entropy: bool) -> Self {
+        self.config.entropy = entropy;
+        self
+    }
+
+    pub fn encrypted_min_entropy(mut self, entropy: f64) -> Self {
+        self.config.encrypted_min_entropy = Some(entropy);
+        self
+    }
+
+    pub fn detect_encrypted_archives(mut self, detect: bool) -> Self {
+        self.config.detect_encrypted_archives = detect;
+        self
+    }
+
+    pub fn group_split_archives(mut self, group: bool) -> Self {
+        self.config.group_split_archives = group;
+        self
+    }
+
+    pub fn clamav(mut self, clamav: Clamav) -> Self {
+        self.config.clamav = Some(clamav);
+        self
+    }
+
+    // all problems are reported at once, as a Config error
+    pub fn build(self) -> Result<Config, Box<dyn Error>> {
+        let config = self.config;
+        let mut problems = Vec::new();
+        let mut check = |key: &str, result: Result<(), String>| {
+            if let Err(e) = result {
+                problems.push(format!("{}: {}", key, e));
+            }
+        };
+
+        // the default one may be missing like for the command line, e.g. in images built with bundled-mime-data
+        if config.mime_info_db_root != crate::default_mime_info_db_root() {
+            check("mime_info_db_root", check_dir(&config.mime_info_db_root));
+        }
+        if let Some(db_file) = &config.libmagic_db_file {
+            check("libmagic_db_file", check_file(db_file));
+        }
+        if let Some(script) = &config.routing_script {
+            check("routing_script", check_file(script));
+        }
+        if let Some(dir) = &config.plugins_dir {
+            check("plugins_dir", check_dir(dir));
+        }
+        if let Some(clamav) = config.clamav.as_ref().filter(|clamav| !clamav.socket.exists()) {
+            check("clamav.socket", Err(format!("{} does not exist", clamav.socket.display())));
+        }
+        // created on first use, its directory has to be there
+        if let Some(db) = &config.overrides_db {
+            if !db.is_file() && db.parent().is_some_and(|dir| !dir.as_os_str().is_empty() && !dir.is_dir()) {
+                check("overrides_db", Err(format!("the directory of {} does not exist", db.display())));
+            }
+        }
+        let sizes = [("read_buffer_size", config.read_buffer_size), ("pipeline_depth", config.pipeline_depth),
+            ("sniff_buffer_size", config.sniff_buffer_size), ("max_dir_entries", config.max_dir_entries.unwrap_or(1))];
+        for (key, size) in sizes.iter() {
+            check(key, check_positive(*size as u64));
+        }
+        if let Some(timeout) = config.classify_timeout {
+            check("classify_timeout", check_positive(timeout.as_millis() as u64));
+        }
+        if let Some(entropy) = config.encrypted_min_entropy {
+            check("encrypted_min_entropy", check_min_entropy(entropy));
+        }
+        for (i, hook) in config.hooks.iter().enumerate() {
+            check(&format!("hooks[{}].exec", i), check_hook_exec(&hook.exec));
+        }
+        for (i, rule) in config.tag_rules.iter().enumerate() {
+            check(&format!("tag_rules[{}].tag", i), crate::check_tag(&rule.tag));
+        }
+        for (i, rule) in config.policy.iter().enumerate() {
+            check(&format!("policy[{}]", i), check_policy_rule(&rule.deny, &rule.allow));
+        }
+        for (i, rule) in config.companions.iter().enumerate() {
+            check(&format!("companions[{}].extensions", i), crate::check_companion_extensions(&rule.extensions));
+        }
+        if let Some(events) = &config.photo_events {
+            check("photo_events.max_gap", check_positive(events.max_gap.as_secs()));
+            check("photo_events.min_photos", check_positive(events.min_photos as u64));
+        }
+        if let Some(layout) = config.geocoding.as_ref().and_then(|geocoding| geocoding.layout.as_deref()) {
+            check("geocoding.layout", crate::check_geocoding_layout(layout));
+        }
+        if let Some(url) = &config.notify.webhook {
+            check("notify.webhook", check_webhook_url(url));
+        }
+
+        match problems.len() {
+            0 => Ok(config),
+            _ => Err(Box::new(ClassifierError(ErrorKind::Config, problems.join("\n")))),
+        }
+    }
+}
+
+// checks of single values, the command line applies them to config.yaml
+
+pub fn check_dir(path: &Path) -> Result<(), String> {
+    match path.is_dir() {
+        true => Ok(()),
+        false => Err(format!("{} is not a directory", path.display())),
+    }
+}
+
+pub fn check_file(path: &Path) -> Result<(), String> {
+    match path.is_file() {
+        true => Ok(()),
+        false => Err(format!("{} is not a file", path.display())),
+    }
+}
+
+// sizes, counts and durations
+pub fn check_positive(value: u64) -> Result<(), String> {
+    match value {
+        0 => Err("has to be positive".to_owned()),
+        _ => Ok(()),
+    }
+}
+
+// Shannon entropy in bits per byte
+pub fn check_min_entropy(entropy: f64) -> Result<(), String> {
+    match (0.0..=8.0).contains(&entropy) {
+        true => Ok(()),
+        false => Err("expected bits per byte from 0 to 8".to_owned()),
+    }
+}
+
+pub fn check_hook_exec(exec: &[String]) -> Result<(), String> {
+    match exec.is_empty() {
+        true => Err("empty command".to_owned()),
+        false => Ok(()),
+    }
+}
+
+pub fn check_policy_rule(deny: &[String], allow: &[String]) -> Result<(), String> {
+    match deny.is_empty() && allow.is_empty() {
+        true => Err("neither deny nor allow given".to_owned()),
+        false => Ok(()),
+    }
+}
+
+pub fn check_webhook_url(url: &str) -> Result<(), String> {
+    match url.starts_with("http://") || url.starts_with("https://") {
+        true => Ok(()),
+        false => Err(format!("{} is not an http or https URL", url)),
+    }
+}
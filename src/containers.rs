@@ -0,0 +1,173 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// built-in detection of formats based on zip, used when libmagic is not available, and the central directory
+// listing behind archive input
+
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const EOCD_LEN: usize = 22;
+const CENTRAL_HEADER_LEN: usize = 46;
+const LOCAL_HEADER_LEN: usize = 30;
+// the end of central directory record is followed by a comment of at most 64 KiB
+const EOCD_SEARCH_LEN: u64 = EOCD_LEN as u64 + 0xffff;
+const MIMETYPE_MAX_LEN: usize = 256;
+
+fn u16_at(buf: &[u8], offset: usize) -> usize {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]]) as usize
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// an entry as listed in the central directory
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "archives"), allow(dead_code))]
+pub struct ZipEntry {
+    pub name: String,
+    // general purpose bit flags, bit 0 marks encrypted entries
+    pub flags: u16,
+    // 0 stored, 8 deflated
+    pub method: u16,
+    // MS-DOS date and time
+    pub dos_time: (u16, u16),
+    pub compressed_size: u64,
+    pub size: u64,
+    // permission bits of archives made on Unix
+    pub unix_mode: Option<u32>,
+    local_header_offset: u64,
+}
+
+#[cfg_attr(not(feature = "archives"), allow(dead_code))]
+impl ZipEntry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    // the compressed data follows the local header, whose name and extra field may differ from the central ones
+    pub fn data_offset<R: Read + Seek>(&self, reader: &mut R) -> io::Result<u64> {
+        let mut header = [0; LOCAL_HEADER_LEN];
+        reader.seek(SeekFrom::Start(self.local_header_offset))?;
+        reader.read_exact(&mut header)?;
+        if u32_at(&header, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid("bad local header"));
+        }
+        let name_len = u16_at(&header, 26);
+        let extra_len = u16_at(&header, 28);
+        Ok(self.local_header_offset + (LOCAL_HEADER_LEN + name_len + extra_len) as u64)
+    }
+}
+
+// all entries, read from the central directory; zip64 archives are not supported
+pub fn zip_entries<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<ZipEntry>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let tail_start = len.saturating_sub(EOCD_SEARCH_LEN);
+    reader.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail)?;
+
+    let eocd = (0..tail.len().saturating_sub(EOCD_LEN - 1)).rev()
+        .find(|&i| u32_at(&tail, i) == EOCD_SIGNATURE)
+        .ok_or_else(|| invalid("no end of central directory"))?;
+    let entry_count = u16_at(&tail, eocd + 10);
+    let cd_size = u32_at(&tail, eocd + 12) as usize;
+    let cd_offset = u32_at(&tail, eocd + 16) as u64;
+    if cd_offset + cd_size as u64 > len {
+        return Err(invalid("central directory out of bounds"));
+    }
+
+    let mut cd = vec![0; cd_size];
+    reader.seek(SeekFrom::Start(cd_offset))?;
+    reader.read_exact(&mut cd)?;
+
+    let mut entries = Vec::with_capacity(entry_count);
+    let mut pos = 0;
+    while entries.len() < entry_count && pos + CENTRAL_HEADER_LEN <= cd.len() {
+        if u32_at(&cd, pos) != CENTRAL_HEADER_SIGNATURE {
+            return Err(invalid("bad central directory header"));
+        }
+        let name_len = u16_at(&cd, pos + 28);
+        let extra_len = u16_at(&cd, pos + 30);
+        let comment_len = u16_at(&cd, pos + 32);
+        let name = cd.get(pos + CENTRAL_HEADER_LEN..pos + CENTRAL_HEADER_LEN + name_len)
+            .ok_or_else(|| invalid("truncated central directory"))?;
+        // the upper half of the external attributes, if the entry was made on Unix
+        let made_on_unix = cd[pos + 5] == 3;
+        let external_attrs = u32_at(&cd, pos + 38);
+        entries.push(ZipEntry{
+            name: String::from_utf8_lossy(name).into_owned(),
+            flags: u16_at(&cd, pos + 8) as u16,
+            method: u16_at(&cd, pos + 10) as u16,
+            dos_time: (u16_at(&cd, pos + 14) as u16, u16_at(&cd, pos + 12) as u16),
+            compressed_size: u32_at(&cd, pos + 20) as u64,
+            size: u32_at(&cd, pos + 24) as u64,
+            unix_mode: Some(external_attrs >> 16).filter(|&mode| made_on_unix && mode != 0),
+            local_header_offset: u32_at(&cd, pos + 42) as u64,
+        });
+        pos += CENTRAL_HEADER_LEN + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+// OpenDocument and EPUB start with an uncompressed entry called mimetype holding the type
+fn zip_mimetype_entry<R: Read + Seek>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut header = [0; LOCAL_HEADER_LEN];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut header)?;
+
+    let method = u16_at(&header, 8);
+    let size = u32_at(&header, 18) as usize;
+    let name_len = u16_at(&header, 26);
+    let extra_len = u16_at(&header, 28);
+    if u32_at(&header, 0) != LOCAL_HEADER_SIGNATURE || method != 0 || size > MIMETYPE_MAX_LEN {
+        return Ok(None);
+    }
+
+    let mut name_and_data = vec![0; name_len + extra_len + size];
+    reader.read_exact(&mut name_and_data)?;
+    if &name_and_data[..name_len] != b"mimetype" {
+        return Ok(None);
+    }
+    let mime = String::from_utf8_lossy(&name_and_data[name_len + extra_len..]).trim().to_owned();
+    Ok(Some(mime).filter(|m| m.contains('/')))
+}
+
+static OOXML_TYPES: &[(&str, &str)] = &[
+    ("word/", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+    ("xl/", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+    ("ppt/", "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+];
+
+// refines application/zip into the format stored inside it, None if it is a plain zip archive
+pub fn refine_zip(path: &Path) -> io::Result<Option<String>> {
+    refine_zip_reader(&mut File::open(path)?)
+}
+
+// as refine_zip, e.g. for a zip archive inside an archive read into memory
+pub fn refine_zip_reader<R: Read + Seek>(reader: &mut R) -> io::Result<Option<String>> {
+    if let Some(mime) = zip_mimetype_entry(reader)? {
+        return Ok(Some(mime));
+    }
+
+    let entries = zip_entries(reader)?;
+    let has = |name: &str| entries.iter().any(|e| e.name == name);
+    let has_prefix = |prefix: &str| entries.iter().any(|e| e.name.starts_with(prefix));
+
+    let mime = if has("[Content_Types].xml") {
+        OOXML_TYPES.iter().find(|(dir, _)| has_prefix(dir)).map(|(_, mime)| *mime)
+    } else if has("AndroidManifest.xml") && has("classes.dex") {
+        Some("application/vnd.android.package-archive")
+    } else if has("META-INF/MANIFEST.MF") {
+        Some("application/java-archive")
+    } else {
+        None
+    };
+    Ok(mime.map(str::to_owned))
+}
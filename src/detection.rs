@@ -0,0 +1,94 @@
+use std::fmt;
+use std::str::FromStr;
+
+// sources of a file's type, tried in the configured order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Detector {
+    // learned override rules
+    Overrides,
+    // detector plugins
+    Plugins,
+    // file name extension looked up in mime-db
+    Extension,
+    // shared-mime-info magic
+    TreeMagic,
+    // libmagic on its own, regardless of libmagic_used_for
+    LibMagic,
+    // built-in zip container detector
+    Container,
+}
+
+static DETECTORS: &[(&str, Detector)] = &[
+    ("overrides", Detector::Overrides),
+    ("plugins", Detector::Plugins),
+    ("extension", Detector::Extension),
+    ("tree_magic", Detector::TreeMagic),
+    ("libmagic", Detector::LibMagic),
+    ("container", Detector::Container),
+];
+
+static GENERIC_TYPES: &[&str] = &["application/octet-stream", "text/plain", "application/zip"];
+
+impl Detector {
+    // the default chain, libmagic only refines tree_magic matches listed in libmagic_used_for
+    pub fn default_chain() -> Vec<Detector> {
+        vec![Detector::Overrides, Detector::Plugins, Detector::TreeMagic]
+    }
+
+    // used by the best-confidence strategy, generic types lose against any specific one
+    pub fn confidence(self, mime: &str) -> u8 {
+        if GENERIC_TYPES.contains(&mime) {
+            return 10;
+        }
+        match self {
+            Detector::Overrides => 100,
+            Detector::Plugins => 90,
+            Detector::Container => 80,
+            Detector::LibMagic => 60,
+            Detector::TreeMagic => 50,
+            Detector::Extension => 20,
+        }
+    }
+}
+
+impl fmt::Display for Detector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = DETECTORS.iter().find(|(_, d)| d == self).map_or("", |(name, _)| name);
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Detector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DETECTORS.iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, d)| *d)
+            .ok_or_else(|| {
+                let names: Vec<&str> = DETECTORS.iter().map(|(name, _)| *name).collect();
+                format!("invalid detector {}, valid detectors are: {}", s, names.join(", "))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DetectionStrategy {
+    // the first detector with a result wins
+    #[default]
+    FirstMatch,
+    // all detectors run, the most confident result wins (the earlier one on a tie)
+    BestConfidence,
+}
+
+impl FromStr for DetectionStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first_match" => Ok(DetectionStrategy::FirstMatch),
+            "best_confidence" => Ok(DetectionStrategy::BestConfidence),
+            _ => Err(format!("invalid detection strategy {}, valid strategies are: first_match, best_confidence", s)),
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::containers;
+
+// recognizes zip, 7z and rar archives that need a password to extract, see Config::detect_encrypted_archives
+
+// tag added to the records of such archives
+pub static ENCRYPTED_TAG: &str = "encrypted";
+
+// types whose files are opened again to look for encryption
+static ARCHIVE_TYPES: &[&str] = &[
+    "application/zip",
+    "application/x-zip-compressed",
+    "application/java-archive",
+    "application/x-7z-compressed",
+    "application/vnd.rar",
+    "application/x-rar",
+    "application/x-rar-compressed",
+];
+
+const SEVEN_ZIP_MAGIC: &[u8] = b"7z\xbc\xaf\x27\x1c";
+const SEVEN_ZIP_HEADER_LEN: u64 = 32;
+const SEVEN_ZIP_HEADER: u8 = 0x01;
+const SEVEN_ZIP_ENCODED_HEADER: u8 = 0x17;
+// coder flags (4-byte id with properties) followed by the id of 7zAES
+const SEVEN_ZIP_AES_CODER: &[u8] = &[0x24, 0x06, 0xf1, 0x07, 0x01];
+// the coders are listed near the start of the header, the rest are file names and times
+const SEVEN_ZIP_HEADER_READ_LEN: u64 = 64 * 1024;
+
+const RAR4_MAGIC: &[u8] = b"Rar!\x1a\x07\x00";
+const RAR4_MAIN_HEADER: u8 = 0x73;
+const RAR4_FILE_HEADER: u8 = 0x74;
+const RAR4_END_HEADER: u8 = 0x7b;
+const RAR4_MAIN_PASSWORD: u16 = 0x0080;
+const RAR4_FILE_PASSWORD: u16 = 0x0004;
+const RAR4_LONG_BLOCK: u16 = 0x8000;
+
+const RAR5_MAGIC: &[u8] = b"Rar!\x1a\x07\x01\x00";
+const RAR5_FILE_HEADER: u64 = 2;
+const RAR5_ENCRYPTION_HEADER: u64 = 4;
+const RAR5_END_HEADER: u64 = 5;
+const RAR5_HAS_EXTRA: u64 = 0x1;
+const RAR5_HAS_DATA: u64 = 0x2;
+const RAR5_FILE_ENCRYPTION_RECORD: u64 = 0x1;
+
+// a damaged or crafted archive must not keep the scan busy
+const MAX_BLOCKS: usize = 100_000;
+
+pub fn applies_to(mime: &str) -> bool {
+    ARCHIVE_TYPES.contains(&mime)
+}
+
+// by the magic, the type may come from the name or a plugin
+pub fn is_encrypted<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let mut magic = [0; 8];
+    reader.seek(SeekFrom::Start(0))?;
+    let len = read_up_to(reader, &mut magic)?;
+    let magic = &magic[..len];
+
+    if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") {
+        // bit 0 of the flags, set with traditional PKWARE and AES encryption alike
+        Ok(containers::zip_entries(reader)?.iter().any(|entry| entry.flags & 1 != 0))
+    } else if magic.starts_with(SEVEN_ZIP_MAGIC) {
+        seven_zip_encrypted(reader)
+    } else if magic.starts_with(RAR5_MAGIC) {
+        rar5_encrypted(reader)
+    } else if magic.starts_with(RAR4_MAGIC) {
+        rar4_encrypted(reader)
+    } else {
+        Ok(false)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// the coders are visible in a plain header and in the encoded header of an archive with encrypted file names
+// (7z -mhe=on); a header compressed with LZMA hides them, such archives are not recognized
+fn seven_zip_encrypted<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let mut start = [0; SEVEN_ZIP_HEADER_LEN as usize];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut start)?;
+    let next_offset = u64::from_le_bytes([start[12], start[13], start[14], start[15], start[16], start[17], start[18],
+        start[19]]);
+    let next_size = u64::from_le_bytes([start[20], start[21], start[22], start[23], start[24], start[25], start[26],
+        start[27]]);
+
+    let offset = SEVEN_ZIP_HEADER_LEN.checked_add(next_offset).ok_or_else(|| invalid("bad 7z header offset"))?;
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut header = Vec::new();
+    (&mut *reader).take(next_size.min(SEVEN_ZIP_HEADER_READ_LEN)).read_to_end(&mut header)?;
+    match header.first() {
+        Some(&SEVEN_ZIP_HEADER) | Some(&SEVEN_ZIP_ENCODED_HEADER) => {
+            Ok(header.windows(SEVEN_ZIP_AES_CODER.len()).any(|window| window == SEVEN_ZIP_AES_CODER))
+        }
+        _ => Ok(false),
+    }
+}
+
+// RAR 1.5 to 4.x: the main header marks encrypted headers, each file header an encrypted file
+fn rar4_encrypted<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let mut pos = RAR4_MAGIC.len() as u64;
+    for _ in 0..MAX_BLOCKS {
+        let mut block = [0; 11];
+        reader.seek(SeekFrom::Start(pos))?;
+        let len = read_up_to(reader, &mut block)?;
+        if len < 7 {
+            return Ok(false);
+        }
+        let kind = block[2];
+        let flags = u16::from_le_bytes([block[3], block[4]]);
+        let header_size = u16::from_le_bytes([block[5], block[6]]) as u64;
+        match kind {
+            RAR4_MAIN_HEADER if flags & RAR4_MAIN_PASSWORD != 0 => return Ok(true),
+            RAR4_FILE_HEADER if flags & RAR4_FILE_PASSWORD != 0 => return Ok(true),
+            RAR4_END_HEADER => return Ok(false),
+            _ => (),
+        }
+        // the size of the data following the header, the packed size for file headers
+        let data_size = match flags & RAR4_LONG_BLOCK != 0 && len == block.len() {
+            true => u32::from_le_bytes([block[7], block[8], block[9], block[10]]) as u64,
+            false => 0,
+        };
+        if header_size < 7 {
+            return Err(invalid("bad rar block size"));
+        }
+        pos += header_size + data_size;
+    }
+    Ok(false)
+}
+
+// RAR 5: an archive encryption header precedes all others when the headers are encrypted, encrypted files have an
+// encryption record in the extra area of their file header
+fn rar5_encrypted<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let mut pos = RAR5_MAGIC.len() as u64;
+    for _ in 0..MAX_BLOCKS {
+        // CRC32, then the size of the header from its type on
+        reader.seek(SeekFrom::Start(pos.checked_add(4).ok_or_else(|| invalid("bad rar block size"))?))?;
+        let (header_size, size_len) = match read_vint(reader) {
+            Ok(vint) => vint,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let mut header = Vec::new();
+        (&mut *reader).take(header_size).read_to_end(&mut header)?;
+        if (header.len() as u64) < header_size {
+            return Ok(false);
+        }
+
+        let mut fields = &header[..];
+        let kind = read_vint(&mut fields)?.0;
+        let flags = read_vint(&mut fields)?.0;
+        let extra_size = match flags & RAR5_HAS_EXTRA != 0 {
+            true => read_vint(&mut fields)?.0,
+            false => 0,
+        };
+        let data_size = match flags & RAR5_HAS_DATA != 0 {
+            true => read_vint(&mut fields)?.0,
+            false => 0,
+        };
+        match kind {
+            RAR5_ENCRYPTION_HEADER => return Ok(true),
+            RAR5_FILE_HEADER if extra_size > 0 => {
+                let extra_start = header.len().checked_sub(extra_size as usize)
+                    .ok_or_else(|| invalid("bad rar extra area"))?;
+                if rar5_extra_has_encryption(&header[extra_start..])? {
+                    return Ok(true);
+                }
+            }
+            RAR5_END_HEADER => return Ok(false),
+            _ => (),
+        }
+        // the sizes are read from the file, a crafted one may overflow
+        pos = [4, size_len, header_size, data_size].iter().try_fold(pos, |pos, &size| pos.checked_add(size))
+            .ok_or_else(|| invalid("bad rar block size"))?;
+    }
+    Ok(false)
+}
+
+// records of the extra area: size (of type and data), type, data
+fn rar5_extra_has_encryption(mut extra: &[u8]) -> io::Result<bool> {
+    while !extra.is_empty() {
+        let size = read_vint(&mut extra)?.0 as usize;
+        let record = extra.get(..size).ok_or_else(|| invalid("bad rar extra record"))?;
+        if read_vint(&mut &record[..])?.0 == RAR5_FILE_ENCRYPTION_RECORD {
+            return Ok(true);
+        }
+        extra = &extra[size..];
+    }
+    Ok(false)
+}
+
+// variable length integer of RAR 5: 7 bits per byte, least significant first, the high bit marks more bytes;
+// returns the value and the number of bytes read
+fn read_vint<R: Read>(reader: &mut R) -> io::Result<(u64, u64)> {
+    let mut value = 0;
+    for i in 0..10 {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err(invalid("bad rar vint"))
+}
+
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn rar5_huge_data_size() {
+        // a file header with HAS_DATA and a data size of u64::MAX
+        let mut rar = RAR5_MAGIC.to_vec();
+        rar.extend_from_slice(&[0; 4]);
+        let header = [&[2, 2][..], &[0xff; 9], &[0x01]].concat();
+        rar.push(header.len() as u8);
+        rar.extend_from_slice(&header);
+        let e = is_encrypted(&mut Cursor::new(rar)).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,29 @@
+// Shannon entropy of the sniff buffer, recorded with Config::entropy and behind the encrypted/ output directory
+
+// directory of the files routed by Config::encrypted_min_entropy, in place of the type directory
+pub static OUTPUT_ENCRYPTED: &str = "encrypted";
+
+// bits per byte, from 0 for a repeated byte to 8 for uniformly random data; a few KiB of random data stay just
+// below 8. Rounded to two decimals, the manifest does not need more
+pub fn shannon(data: &[u8]) -> Option<f64> {
+    if data.is_empty() {
+        return None;
+    }
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    let entropy: f64 = counts.iter().filter(|&&count| count > 0).map(|&count| {
+        // not -p * log2(p), which is -0 for a single byte value
+        let p = count as f64 / len;
+        p * (1.0 / p).log2()
+    }).sum();
+    Some((entropy * 100.0).round() / 100.0)
+}
+
+// compressed formats with known magic are dense as well, only data of no recognized type is taken for encrypted
+// (or packed with an unknown format)
+pub fn likely_encrypted(mime: Option<&str>, entropy: Option<f64>, min_entropy: f64) -> bool {
+    matches!(mime, None | Some("application/octet-stream")) && entropy.is_some_and(|entropy| entropy >= min_entropy)
+}
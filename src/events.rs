@@ -0,0 +1,68 @@
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use slog::{Logger, debug};
+
+use crate::manifest::Record;
+use crate::Timestamp;
+
+// photos taken close in time are laid out together in event directories below their type directory, named by the
+// first photo, e.g. image/jpeg/2024-06-01_14-03/IMG_0001.jpg
+#[derive(Debug, Clone)]
+pub struct PhotoEvents {
+    // MIME patterns as for --only-mime of the files whose EXIF capture time is read
+    pub mime: Vec<String>,
+    // a longer pause between two photos starts a new event
+    pub max_gap: Duration,
+    // photos of events with fewer of them stay directly in their type directories
+    pub min_photos: usize,
+}
+
+impl Default for PhotoEvents {
+    fn default() -> Self {
+        Self{
+            mime: vec!["image/*".to_owned()],
+            max_gap: Duration::from_secs(2 * 3600),
+            min_photos: 3,
+        }
+    }
+}
+
+impl PhotoEvents {
+    pub fn wants(&self, mime: &str) -> bool {
+        self.mime.iter().any(|pattern| crate::mime_matches(pattern, mime))
+    }
+}
+
+// YYYY-MM-DD_HH-MM of the capture time
+fn event_name(taken: &str) -> String {
+    taken.get(..16).unwrap_or(taken).replace('T', "_").replace(':', "-")
+}
+
+// sets event of the records of photos by their capture times, across all input directories and types; a camera
+// and a phone at the same party end up in one event as long as both clocks were right
+pub fn mark(events: &PhotoEvents, records: &mut [Record], log: &Logger) {
+    let mut taken: Vec<(i64, usize)> = records.iter().enumerate()
+        .filter_map(|(i, record)| Some((record.taken.as_deref()?.parse::<Timestamp>().ok()?.0, i)))
+        .collect();
+    taken.sort_unstable();
+
+    let max_gap = i64::try_from(events.max_gap.as_secs()).unwrap_or(i64::MAX);
+    let mut named = 0;
+    let mut start = 0;
+    for end in 1..=taken.len() {
+        if end < taken.len() && taken[end].0 - taken[end - 1].0 <= max_gap {
+            continue;
+        }
+        let event = &taken[start..end];
+        if event.len() >= events.min_photos {
+            let name = event_name(records[event[0].1].taken.as_deref().unwrap_or_default());
+            for &(_, i) in event {
+                records[i].event = Some(name.clone());
+            }
+            named += 1;
+        }
+        start = end;
+    }
+    debug!(log, "Grouped photos into {} events, {} with a capture time", named, taken.len());
+}
@@ -0,0 +1,244 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+// capture metadata of photos from their EXIF data, in JPEG files (APP1 segment) and TIFF based ones, which most
+// camera raw formats are (CR2, NEF, ARW, DNG, PEF, ORF, RW2)
+
+const JPEG_SOI: &[u8] = &[0xff, 0xd8];
+const JPEG_APP1: u8 = 0xe1;
+const JPEG_SOS: u8 = 0xda;
+const JPEG_EOI: u8 = 0xd9;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+// little and big endian TIFF, the variants of Olympus (ORF) and Panasonic (RW2)
+static TIFF_MAGICS: &[&[u8]] = &[b"II*\0", b"MM\0*", b"IIRO", b"IIRS", b"IIU\0"];
+
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+const TAG_GPS_LATITUDE_REF: u16 = 1;
+const TAG_GPS_LATITUDE: u16 = 2;
+const TAG_GPS_LONGITUDE_REF: u16 = 3;
+const TAG_GPS_LONGITUDE: u16 = 4;
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+// a damaged or crafted file must not keep the scan busy
+const MAX_SEGMENTS: usize = 1000;
+const MAX_ENTRIES: u16 = 1000;
+const MAX_ASCII_LEN: u32 = 256;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Exif {
+    // DateTimeOriginal (or DateTimeDigitized) as YYYY-MM-DDTHH:MM:SS, in the local time the camera was set to
+    pub taken: Option<String>,
+    // latitude and longitude in degrees, negative to the south and west
+    pub gps: Option<[f64; 2]>,
+}
+
+// None for files without EXIF data
+pub fn read<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Exif>> {
+    let tiff = match find_tiff(reader)? {
+        Some(start) => Tiff::open(reader, start)?,
+        None => return Ok(None),
+    };
+    let ifd0 = tiff.entries(reader, tiff.first_ifd)?;
+    let exif_ifd = match find(&ifd0, TAG_EXIF_IFD) {
+        Some(entry) => tiff.entries(reader, tiff.long(entry)?)?,
+        None => Vec::new(),
+    };
+    let taken = match find(&exif_ifd, TAG_DATE_TIME_ORIGINAL).or_else(|| find(&exif_ifd, TAG_DATE_TIME_DIGITIZED)) {
+        Some(entry) => tiff.ascii(reader, entry)?.as_deref().and_then(date_time),
+        None => None,
+    };
+    let gps = match find(&ifd0, TAG_GPS_IFD) {
+        Some(entry) => {
+            let gps_ifd = tiff.entries(reader, tiff.long(entry)?)?;
+            let latitude = tiff.coordinate(reader, &gps_ifd, TAG_GPS_LATITUDE, TAG_GPS_LATITUDE_REF, "S")?;
+            let longitude = tiff.coordinate(reader, &gps_ifd, TAG_GPS_LONGITUDE, TAG_GPS_LONGITUDE_REF, "W")?;
+            match (latitude, longitude) {
+                // 0, 0 is written by some cameras without a fix
+                (Some(latitude), Some(longitude)) if (latitude != 0.0 || longitude != 0.0)
+                    && latitude.abs() <= 90.0 && longitude.abs() <= 180.0 => Some([latitude, longitude]),
+                _ => None,
+            }
+        }
+        None => None,
+    };
+    Ok(Some(Exif{taken, gps}))
+}
+
+// offset of the TIFF header holding the EXIF data
+fn find_tiff<R: Read + Seek>(reader: &mut R) -> io::Result<Option<u64>> {
+    let mut magic = [0; 4];
+    reader.seek(SeekFrom::Start(0))?;
+    if read_up_to(reader, &mut magic)? < magic.len() {
+        return Ok(None);
+    }
+    if TIFF_MAGICS.contains(&&magic[..]) {
+        return Ok(Some(0));
+    }
+    if !magic.starts_with(JPEG_SOI) {
+        return Ok(None);
+    }
+    // marker and length (including itself) of each segment up to the image data
+    let mut pos = JPEG_SOI.len() as u64;
+    for _ in 0..MAX_SEGMENTS {
+        let mut segment = [0; 4 + EXIF_HEADER.len()];
+        reader.seek(SeekFrom::Start(pos))?;
+        let len = read_up_to(reader, &mut segment)?;
+        if len < 4 || segment[0] != 0xff || segment[1] == JPEG_SOS || segment[1] == JPEG_EOI {
+            return Ok(None);
+        }
+        if segment[1] == JPEG_APP1 && segment[4..len] == *EXIF_HEADER {
+            return Ok(Some(pos + segment.len() as u64));
+        }
+        pos += 2 + u16::from_be_bytes([segment[2], segment[3]]) as u64;
+    }
+    Ok(None)
+}
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    // the value itself if it fits, otherwise its offset from the TIFF header
+    value: [u8; 4],
+}
+
+fn find(entries: &[Entry], tag: u16) -> Option<&Entry> {
+    entries.iter().find(|entry| entry.tag == tag)
+}
+
+struct Tiff {
+    start: u64,
+    little_endian: bool,
+    first_ifd: u32,
+}
+
+impl Tiff {
+    fn open<R: Read + Seek>(reader: &mut R, start: u64) -> io::Result<Self> {
+        let mut header = [0; 8];
+        reader.seek(SeekFrom::Start(start))?;
+        reader.read_exact(&mut header)?;
+        let little_endian = header.starts_with(b"II");
+        let mut tiff = Self{start, little_endian, first_ifd: 0};
+        tiff.first_ifd = tiff.u32([header[4], header[5], header[6], header[7]]);
+        Ok(tiff)
+    }
+
+    fn u16(&self, bytes: [u8; 2]) -> u16 {
+        match self.little_endian {
+            true => u16::from_le_bytes(bytes),
+            false => u16::from_be_bytes(bytes),
+        }
+    }
+
+    fn u32(&self, bytes: [u8; 4]) -> u32 {
+        match self.little_endian {
+            true => u32::from_le_bytes(bytes),
+            false => u32::from_be_bytes(bytes),
+        }
+    }
+
+    fn entries<R: Read + Seek>(&self, reader: &mut R, offset: u32) -> io::Result<Vec<Entry>> {
+        let mut count = [0; 2];
+        reader.seek(SeekFrom::Start(self.start + offset as u64))?;
+        reader.read_exact(&mut count)?;
+        let count = self.u16(count);
+        if count > MAX_ENTRIES {
+            return Err(invalid("too many IFD entries"));
+        }
+        (0..count).map(|_| {
+            let mut entry = [0; 12];
+            reader.read_exact(&mut entry)?;
+            Ok(Entry{
+                tag: self.u16([entry[0], entry[1]]),
+                kind: self.u16([entry[2], entry[3]]),
+                count: self.u32([entry[4], entry[5], entry[6], entry[7]]),
+                value: [entry[8], entry[9], entry[10], entry[11]],
+            })
+        }).collect()
+    }
+
+    fn long(&self, entry: &Entry) -> io::Result<u32> {
+        match entry.kind {
+            TYPE_LONG => Ok(self.u32(entry.value)),
+            _ => Err(invalid("bad IFD pointer")),
+        }
+    }
+
+    // degrees, minutes and seconds as three rationals, the reference is N or S, E or W
+    fn coordinate<R: Read + Seek>(&self, reader: &mut R, entries: &[Entry], tag: u16, ref_tag: u16, negative: &str)
+        -> io::Result<Option<f64>> {
+
+        let entry = match find(entries, tag) {
+            Some(entry) if entry.kind == TYPE_RATIONAL && entry.count == 3 => entry,
+            _ => return Ok(None),
+        };
+        let mut values = [0; 24];
+        reader.seek(SeekFrom::Start(self.start + self.u32(entry.value) as u64))?;
+        reader.read_exact(&mut values)?;
+        let mut degrees = 0.0;
+        for (i, unit) in [1.0, 60.0, 3600.0].iter().enumerate() {
+            let numerator = self.u32([values[8 * i], values[8 * i + 1], values[8 * i + 2], values[8 * i + 3]]);
+            let denominator = self.u32([values[8 * i + 4], values[8 * i + 5], values[8 * i + 6], values[8 * i + 7]]);
+            if denominator == 0 {
+                return Ok(None);
+            }
+            degrees += numerator as f64 / denominator as f64 / unit;
+        }
+        let reference = match find(entries, ref_tag) {
+            Some(entry) => self.ascii(reader, entry)?,
+            None => None,
+        };
+        // about 0.1 m, finer than GPS resolves
+        let degrees = (degrees * 1e6).round() / 1e6;
+        match reference.as_deref() == Some(negative) {
+            true => Ok(Some(-degrees)),
+            false => Ok(Some(degrees)),
+        }
+    }
+
+    // up to the first NUL
+    fn ascii<R: Read + Seek>(&self, reader: &mut R, entry: &Entry) -> io::Result<Option<String>> {
+        if entry.kind != TYPE_ASCII || entry.count > MAX_ASCII_LEN {
+            return Ok(None);
+        }
+        let bytes = match entry.count {
+            0..=4 => entry.value[..entry.count as usize].to_vec(),
+            count => {
+                let mut bytes = vec![0; count as usize];
+                reader.seek(SeekFrom::Start(self.start + self.u32(entry.value) as u64))?;
+                reader.read_exact(&mut bytes)?;
+                bytes
+            }
+        };
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Ok(String::from_utf8(bytes[..end].to_vec()).ok())
+    }
+}
+
+// YYYY:MM:DD HH:MM:SS, cameras without a set clock write zeros or spaces
+fn date_time(value: &str) -> Option<String> {
+    let (date, time) = value.trim().split_once(' ')?;
+    let taken = format!("{}T{}", date.replace(':', "-"), time);
+    taken.parse::<crate::Timestamp>().ok().map(|_| taken)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
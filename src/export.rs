@@ -0,0 +1,126 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::manifest::Record;
+
+// the manifest as a table for pandas, DuckDB and the like: path, mime, ext, size, mtime (seconds since the epoch) and sha256
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(format!("invalid export format {}, valid formats are: csv, parquet", s)),
+        }
+    }
+}
+
+static CSV_HEADER: &str = "path,mime,ext,size,mtime,sha256";
+
+// RFC 4180, paths that are not valid UTF-8 are written lossily
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+fn write_csv(records: &[Record], out: &mut impl Write) -> std::io::Result<()> {
+    writeln!(out, "{}", CSV_HEADER)?;
+    for record in records {
+        writeln!(out, "{},{},{},{},{},{}",
+            csv_field(&record.path.to_string_lossy()),
+            csv_field(record.mime.as_deref().unwrap_or("")),
+            csv_field(record.ext.as_deref().unwrap_or("")),
+            record.size.map(|size| size.to_string()).unwrap_or_default(),
+            record.mtime.map(|mtime| mtime.to_string()).unwrap_or_default(),
+            record.sha256.as_deref().unwrap_or(""))?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "parquet-export")]
+mod parquet_file {
+    use std::error::Error;
+    use std::fs::File;
+    use std::sync::Arc;
+
+    use arrow_array::{ArrayRef, RecordBatch, StringArray, TimestampSecondArray, UInt64Array};
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use parquet::arrow::ArrowWriter;
+
+    use crate::manifest::Record;
+
+    // rows per record batch (and row group), bounds the memory of the arrow copy of the manifest
+    const BATCH_ROWS: usize = 64 * 1024;
+
+    pub fn write(records: &[Record], file: File) -> Result<(), Box<dyn Error>> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("path", DataType::Utf8, false),
+            Field::new("mime", DataType::Utf8, true),
+            Field::new("ext", DataType::Utf8, true),
+            Field::new("size", DataType::UInt64, true),
+            Field::new("mtime", DataType::Timestamp(TimeUnit::Second, Some("UTC".into())), true),
+            Field::new("sha256", DataType::Utf8, true),
+        ]));
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), None)?;
+        for chunk in records.chunks(BATCH_ROWS) {
+            let columns: Vec<ArrayRef> = vec![
+                Arc::new(StringArray::from_iter_values(chunk.iter().map(|r| r.path.to_string_lossy()))),
+                Arc::new(StringArray::from_iter(chunk.iter().map(|r| r.mime.as_deref()))),
+                Arc::new(StringArray::from_iter(chunk.iter().map(|r| r.ext.as_deref()))),
+                Arc::new(UInt64Array::from_iter(chunk.iter().map(|r| r.size))),
+                Arc::new(TimestampSecondArray::from_iter(chunk.iter().map(|r| r.mtime)).with_timezone("UTC")),
+                Arc::new(StringArray::from_iter(chunk.iter().map(|r| r.sha256.as_deref()))),
+            ];
+            writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+        }
+        writer.close()?;
+        Ok(())
+    }
+}
+
+// fails before the export file is created
+#[cfg(feature = "parquet-export")]
+fn check_format(_format: ExportFormat) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet-export"))]
+fn check_format(format: ExportFormat) -> Result<(), Box<dyn Error>> {
+    match format {
+        ExportFormat::Parquet => Err(Box::new(crate::ClassifierError(crate::ErrorKind::Config,
+            "export --format parquet requires classifiles to be built with the parquet-export feature".to_owned()
+        ))),
+        ExportFormat::Csv => Ok(()),
+    }
+}
+
+pub fn write(records: &[Record], format: ExportFormat, path: &Path) -> Result<(), Box<dyn Error>> {
+    check_format(format)?;
+    let file = File::create(path).map_err(|e| format!("could not create {}: {}", path.display(), e))?;
+    match format {
+        ExportFormat::Csv => {
+            let mut out = BufWriter::new(file);
+            write_csv(records, &mut out)?;
+            out.flush()?;
+            Ok(())
+        }
+        #[cfg(feature = "parquet-export")]
+        ExportFormat::Parquet => parquet_file::write(records, file),
+        #[cfg(not(feature = "parquet-export"))]
+        ExportFormat::Parquet => check_format(format),
+    }
+}
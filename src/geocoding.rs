@@ -0,0 +1,105 @@
+use std::error::Error;
+use std::path::{Component, Path};
+
+use crate::manifest::Record;
+
+// country and region of the GPS position in the EXIF data of photos, looked up offline in the GeoNames places
+// (more than 1000 inhabitants) bundled with the reverse_geocoder crate (geocoding feature)
+#[derive(Debug, Clone)]
+pub struct Geocoding {
+    // MIME patterns as for --only-mime of the files whose EXIF data is read
+    pub mime: Vec<String>,
+    // directories below the type directory with {gps_country} and {gps_region} replaced, photos without a position
+    // stay in the type directory
+    pub layout: Option<String>,
+}
+
+impl Default for Geocoding {
+    fn default() -> Self {
+        Self{mime: vec!["image/*".to_owned()], layout: None}
+    }
+}
+
+impl Geocoding {
+    pub fn wants(&self, mime: &str) -> bool {
+        self.mime.iter().any(|pattern| crate::mime_matches(pattern, mime))
+    }
+}
+
+// ISO 3166 code of the country (CZ) and name of its first-level division as GeoNames spells it (Praha), of the
+// nearest place
+pub struct Place {
+    pub country: String,
+    pub region: String,
+}
+
+// relative directories, the placeholders are replaced by single names
+pub fn check_geocoding_layout(layout: &str) -> Result<(), String> {
+    let path = Path::new(layout);
+    if layout.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(format!("invalid layout {}, expected relative directories like {{gps_country}}/{{gps_region}}",
+            layout));
+    }
+    Ok(())
+}
+
+// the directories of layout for a geotagged photo
+pub fn layout_dir(layout: &str, record: &Record) -> Option<String> {
+    let country = record.gps_country.as_deref()?;
+    let region = record.gps_region.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN);
+    // names of places may contain /
+    Some(layout.replace("{gps_country}", &country.replace('/', "-")).replace("{gps_region}", &region.replace('/', "-")))
+}
+
+#[cfg(feature = "geocoding")]
+pub struct Geocoder {
+    places: reverse_geocoder::ReverseGeocoder,
+}
+
+#[cfg(feature = "geocoding")]
+impl Geocoder {
+    // indexes the bundled places, takes a moment
+    pub fn new(_geocoding: &Geocoding) -> Result<Self, Box<dyn Error>> {
+        Ok(Self{places: reverse_geocoder::ReverseGeocoder::new()})
+    }
+
+    pub fn lookup(&self, gps: [f64; 2]) -> Place {
+        let place = self.places.search((gps[0], gps[1])).record;
+        // city states and some islands have no division
+        let region = match place.admin1.is_empty() {
+            true => &place.name,
+            false => &place.admin1,
+        };
+        Place{country: place.cc.clone(), region: region.clone()}
+    }
+}
+
+#[cfg(not(feature = "geocoding"))]
+pub enum Geocoder {}
+
+#[cfg(not(feature = "geocoding"))]
+impl Geocoder {
+    pub fn new(_geocoding: &Geocoding) -> Result<Self, Box<dyn Error>> {
+        Err(Box::new(crate::ClassifierError(crate::ErrorKind::Config,
+            "geocoding requires classifiles to be built with the geocoding feature".to_owned()
+        )))
+    }
+
+    pub fn lookup(&self, _gps: [f64; 2]) -> Place {
+        match *self {}
+    }
+}
+
+#[cfg(all(test, feature = "geocoding"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let geocoder = Geocoder::new(&Geocoding::default()).unwrap();
+        let place = geocoder.lookup([50.0875, 14.4214]);
+        assert_eq!((place.country.as_str(), place.region.as_str()), ("CZ", "Praha"));
+        let place = geocoder.lookup([-33.8568, 151.2153]);
+        assert_eq!((place.country.as_str(), place.region.as_str()), ("AU", "New South Wales"));
+    }
+}
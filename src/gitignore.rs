@@ -0,0 +1,51 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use fnv::FnvHashMap;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+
+// .gitignore files of the walked tree, each directory is checked for one once
+#[derive(Default)]
+pub struct GitIgnores {
+    dirs: FnvHashMap<PathBuf, Option<Gitignore>>,
+}
+
+fn load(dir: &Path) -> Option<Gitignore> {
+    let path = dir.join(".gitignore");
+    if !path.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(dir);
+    // invalid patterns are left out, the valid ones still apply
+    builder.add(path);
+    builder.build().ok()
+}
+
+impl GitIgnores {
+    // the deepest .gitignore between root and the entry with a matching pattern decides, as in git;
+    // entries of ignored directories are never walked
+    pub fn is_ignored(&mut self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        if path == root || !path.starts_with(root) {
+            return false;
+        }
+        for dir in path.ancestors().skip(1) {
+            let gitignore = self.dirs.entry(dir.to_owned()).or_insert_with(|| load(dir));
+            match gitignore.as_ref().map_or(Match::None, |gitignore| gitignore.matched(path, is_dir)) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => (),
+            }
+            if dir == root {
+                break;
+            }
+        }
+        false
+    }
+}
+
+// packed and loose objects of a repository (and git-lfs content), e.g. ~/src/project/.git/objects
+pub fn is_object_store(dir: &Path) -> bool {
+    let parent_name = dir.parent().and_then(Path::file_name);
+    matches!(dir.file_name().and_then(OsStr::to_str), Some("objects") | Some("lfs")) && parent_name == Some(OsStr::new(".git"))
+}
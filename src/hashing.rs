@@ -0,0 +1,21 @@
+use std::io;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::reader::FileReader;
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn sha256_bytes(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+// hex encoded SHA-256 of the file content
+pub fn sha256_file(path: &Path, reader: &mut FileReader) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    reader.read_chunks(path, &mut |chunk| hasher.update(chunk))?;
+    Ok(to_hex(&hasher.finalize()))
+}
@@ -0,0 +1,123 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Serialize, Deserialize};
+
+use crate::manifest::{self, Record};
+use crate::report::format_size;
+
+// composition of the output tree after each scan, one JSON line per run in OUTPUT_DIR/.classifiles/history.jsonl
+static HISTORY_FILE: &str = "history.jsonl";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Totals {
+    pub count: usize,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    // UTC, ISO 8601
+    pub time: String,
+    pub input: PathBuf,
+    pub total: Totals,
+    pub types: BTreeMap<String, Totals>,
+}
+
+impl Snapshot {
+    pub fn new(input: &Path) -> Self {
+        Self{time: crate::audit::utc_timestamp(SystemTime::now()), input: input.to_owned(), total: Totals::default(),
+            types: BTreeMap::new()}
+    }
+
+    pub fn add(&mut self, record: &Record) {
+        let size = record.size.unwrap_or(0);
+        self.total.count += 1;
+        self.total.size += size;
+        let mime = record.mime.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN);
+        let totals = self.types.entry(mime.to_owned()).or_default();
+        totals.count += 1;
+        totals.size += size;
+    }
+}
+
+pub fn history_path(output_root: &Path) -> PathBuf {
+    manifest::state_dir(output_root).join(HISTORY_FILE)
+}
+
+pub fn append(output_root: &Path, snapshot: &Snapshot) -> Result<(), Box<dyn Error>> {
+    let mut line = serde_json::to_vec(snapshot)?;
+    line.push(b'\n');
+    OpenOptions::new().create(true).append(true).open(history_path(output_root))?.write_all(&line)?;
+    Ok(())
+}
+
+// oldest first, empty for output trees scanned before the history was kept
+pub fn read(output_root: &Path) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+    let path = history_path(output_root);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("could not open {}: {}", path.display(), e).into()),
+    };
+    let mut snapshots = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            snapshots.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(snapshots)
+}
+
+fn signed(delta: i128, format: impl Fn(u64) -> String) -> String {
+    match delta {
+        0 => "=".to_owned(),
+        d if d > 0 => format!("+{}", format(d as u64)),
+        d => format!("-{}", format(d.unsigned_abs() as u64)),
+    }
+}
+
+pub fn write_history(snapshots: &[Snapshot], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "{:<24}  {:>10}  {:>12}  {:>6}  input", "time", "files", "size", "types")?;
+    for s in snapshots {
+        writeln!(out, "{:<24}  {:>10}  {:>12}  {:>6}  {}", s.time, s.total.count, format_size(s.total.size), s.types.len(),
+            s.input.display())?;
+    }
+    Ok(())
+}
+
+// count and size of each type in the last snapshots, with the change from the first to the last of them;
+// types are ordered by their size in the last snapshot
+pub fn write_trend(snapshots: &[Snapshot], out: &mut dyn Write) -> io::Result<()> {
+    let (first, last) = match (snapshots.first(), snapshots.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Ok(()),
+    };
+    let mimes: BTreeSet<&String> = snapshots.iter().flat_map(|s| s.types.keys()).collect();
+    let mut mimes: Vec<&String> = mimes.into_iter().collect();
+    mimes.sort_by_key(|mime| Reverse(last.types.get(*mime).map_or(0, |t| t.size)));
+
+    write!(out, "{:<40}", "type")?;
+    for s in snapshots {
+        // to the minute
+        write!(out, "  {:>21}", s.time.get(..16).unwrap_or(&s.time))?;
+    }
+    writeln!(out, "  {:>21}", "change")?;
+    for mime in mimes {
+        write!(out, "{:<40}", mime)?;
+        for s in snapshots {
+            let t = s.types.get(mime).copied().unwrap_or_default();
+            write!(out, "  {:>21}", format!("{} / {}", t.count, format_size(t.size)))?;
+        }
+        let (from, to) = (first.types.get(mime).copied().unwrap_or_default(), last.types.get(mime).copied().unwrap_or_default());
+        writeln!(out, "  {:>21}", format!("{} / {}", signed(to.count as i128 - from.count as i128, |c| c.to_string()),
+            signed(to.size as i128 - from.size as i128, format_size)))?;
+    }
+    Ok(())
+}
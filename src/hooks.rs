@@ -0,0 +1,57 @@
+use std::process::Command;
+
+use slog::{Logger, debug, warn};
+
+use crate::manifest::Record;
+
+// command run for every classified file matching the MIME pattern,
+// {path}, {mime}, {ext}, {tags} (comma-separated), {owner}, {group}, {perm_class}, {gps_country} and {gps_region}
+// in the arguments are replaced by the file's values
+#[derive(Debug, Clone)]
+pub struct Hook {
+    pub mime: String,
+    pub exec: Vec<String>,
+}
+
+impl Hook {
+    fn command(&self, record: &Record, mime: &str) -> Option<Command> {
+        let placeholders = [
+            ("{path}", record.path.to_string_lossy().into_owned()),
+            ("{mime}", mime.to_owned()),
+            ("{ext}", record.ext.clone().unwrap_or_default()),
+            ("{tags}", record.tags.join(",")),
+            ("{owner}", record.owner.clone().unwrap_or_default()),
+            ("{group}", record.group.clone().unwrap_or_default()),
+            ("{perm_class}", record.mode.map(crate::owners::perm_class).unwrap_or_default().to_owned()),
+            ("{gps_country}", record.gps_country.clone().unwrap_or_default()),
+            ("{gps_region}", record.gps_region.clone().unwrap_or_default()),
+        ];
+        let mut args = self.exec.iter()
+            .map(|arg| placeholders.iter().fold(arg.clone(), |arg, (key, value)| arg.replace(key, value)));
+
+        let mut command = Command::new(args.next()?);
+        command.args(args);
+        Some(command)
+    }
+}
+
+// hook failures are only logged, they never affect the classification
+pub fn run(hooks: &[Hook], record: &Record, log: &Logger) {
+    let mime = record.mime.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN);
+
+    for hook in hooks.iter().filter(|h| crate::mime_matches(&h.mime, mime)) {
+        let mut command = match hook.command(record, mime) {
+            Some(command) => command,
+            None => {
+                warn!(log, "Hook for {} has an empty command", hook.mime);
+                continue;
+            }
+        };
+        debug!(log, "Running hook {:?}", command);
+        match command.status() {
+            Ok(status) if status.success() => (),
+            Ok(status) => warn!(log, "Hook {} failed: {}", hook.exec[0], status),
+            Err(e) => warn!(log, "Could not run hook {}: {}", hook.exec[0], e),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::manifest;
+
+// where scan gets the files to classify from: the walk of the input tree (with its skip rules), a list of paths
+// or the records of a previous manifest; directories and symlinks among the entries are not classified
+pub trait InputSource: Sync {
+    // called twice per scan, once to count the files and once to classify them, unless the source is not countable
+    fn entries(&self) -> Box<dyn Iterator<Item = Result<InputEntry, InputError>> + '_>;
+
+    // false when counting costs as much as reading (an archive is decompressed by each call of entries), scan then
+    // shows no progress against a file count
+    fn countable(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug)]
+pub struct InputEntry {
+    path: PathBuf,
+    kind: EntryKind,
+}
+
+#[derive(Debug)]
+enum EntryKind {
+    File {
+        file_type: fs::FileType,
+        // metadata of the link target instead of the link
+        follow_links: bool,
+    },
+    Buffered(BufferedContent),
+}
+
+// content read into memory with what is known of the file it was read from, e.g. an archive entry; it is always
+// classified, under the path of its entry
+#[derive(Debug)]
+pub struct BufferedContent {
+    // all of the content or its leading part, detectors see nothing beyond it
+    pub leading: Vec<u8>,
+    pub size: u64,
+    pub mtime: Option<i64>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub mode: Option<u32>,
+    // of the whole content, with --hash
+    pub sha256: Option<String>,
+}
+
+impl InputEntry {
+    pub fn new(path: PathBuf, file_type: fs::FileType, follow_links: bool) -> Self {
+        Self{path, kind: EntryKind::File{file_type, follow_links}}
+    }
+
+    pub fn buffered(path: PathBuf, content: BufferedContent) -> Self {
+        Self{path, kind: EntryKind::Buffered(content)}
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // None for buffered content
+    pub fn file_type(&self) -> Option<fs::FileType> {
+        match &self.kind {
+            EntryKind::File{file_type, ..} => Some(*file_type),
+            EntryKind::Buffered(_) => None,
+        }
+    }
+
+    pub fn content(&self) -> Option<&BufferedContent> {
+        match &self.kind {
+            EntryKind::File{..} => None,
+            EntryKind::Buffered(content) => Some(content),
+        }
+    }
+
+    pub fn metadata(&self) -> io::Result<fs::Metadata> {
+        match &self.kind {
+            EntryKind::File{follow_links: true, ..} => fs::metadata(&self.path),
+            EntryKind::File{follow_links: false, ..} => fs::symlink_metadata(&self.path),
+            EntryKind::Buffered(_) => Err(io::Error::new(io::ErrorKind::NotFound, "buffered content is not on disk")),
+        }
+    }
+
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+}
+
+impl From<walkdir::DirEntry> for InputEntry {
+    fn from(entry: walkdir::DirEntry) -> Self {
+        let (file_type, follow_links) = (entry.file_type(), entry.path_is_symlink() && !entry.file_type().is_symlink());
+        Self::new(entry.into_path(), file_type, follow_links)
+    }
+}
+
+#[derive(Debug)]
+pub struct InputError {
+    pub path: Option<PathBuf>,
+    // a followed symlink leading back to one of its parent directories, skipped without an error
+    pub symlink_loop: bool,
+    message: String,
+}
+
+impl InputError {
+    pub fn new(path: &Path, e: &dyn Error) -> Self {
+        Self{path: Some(path.to_owned()), symlink_loop: false, message: format!("{}: {}", path.display(), e)}
+    }
+
+    // of the whole source at path, e names the path already
+    pub fn of_source(path: &Path, e: &dyn Error) -> Self {
+        Self{path: Some(path.to_owned()), symlink_loop: false, message: e.to_string()}
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for InputError {}
+
+impl From<walkdir::Error> for InputError {
+    fn from(e: walkdir::Error) -> Self {
+        Self{path: e.path().map(Path::to_owned), symlink_loop: e.loop_ancestor().is_some(), message: e.to_string()}
+    }
+}
+
+// the given paths as they are, none of the skip rules of the walk apply
+pub struct FileList {
+    paths: Vec<PathBuf>,
+}
+
+impl FileList {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self{paths}
+    }
+
+    // one path per line, or NUL-separated as written by find -print0; - reads stdin; relative paths are taken from
+    // the current directory and recorded as absolute ones
+    pub fn from_file(list: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut bytes = Vec::new();
+        match list == Path::new("-") {
+            true => io::stdin().read_to_end(&mut bytes)?,
+            false => fs::File::open(list)
+                .and_then(|mut file| file.read_to_end(&mut bytes))
+                .map_err(|e| format!("could not read {}: {}", list.display(), e))?,
+        };
+        let separator = match bytes.contains(&0) {
+            true => 0,
+            false => b'\n',
+        };
+        let cwd = std::env::current_dir()?;
+        let paths = bytes.split(|&b| b == separator)
+            .filter(|path| !path.is_empty())
+            .map(|path| cwd.join(OsStr::from_bytes(path)))
+            .collect();
+        Ok(Self::new(paths))
+    }
+
+    // the input paths recorded in the manifest of an output tree, e.g. to classify them again
+    pub fn from_manifest(output_root: &Path) -> Result<Self, Box<dyn Error>> {
+        let records = manifest::read_manifest(output_root)?;
+        Ok(Self::new(records.into_iter().map(|record| record.path).collect()))
+    }
+}
+
+impl InputSource for FileList {
+    fn entries(&self) -> Box<dyn Iterator<Item = Result<InputEntry, InputError>> + '_> {
+        Box::new(self.paths.iter().map(|path| match fs::symlink_metadata(path) {
+            Ok(meta) => Ok(InputEntry::new(path.clone(), meta.file_type(), false)),
+            Err(e) => Err(InputError::new(path, &e)),
+        }))
+    }
+}
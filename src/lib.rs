@@ -3,150 +3,902 @@ use std::path::{Path, PathBuf};
 use std::{fmt, fs};
 use std::os::unix::fs as unix_fs;
 use std::ffi::OsStr;
-use std::os::unix::ffi::OsStrExt;
+use std::sync::{mpsc, Arc};
+use std::cell::RefCell;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 mod mime_info;
 use mime_info::{Mime, MimeInfoDb};
 
-use magic::Cookie;
+mod mime_type;
+use mime_type::MimeType;
+
+mod manifest;
+use manifest::{Header, ManifestWriter};
+pub use manifest::Record;
+
+mod phash;
+#[cfg(feature = "previews")]
+mod previews;
+
+mod report;
+
+mod metrics;
+use metrics::ScanMetrics;
+
+mod review;
+
+mod overrides;
+use overrides::{OverrideRule, Signature};
+
+mod hooks;
+pub use hooks::Hook;
+
+mod routing;
+use routing::{Route, Router};
+
+mod uti;
+
+mod libmagic;
+use libmagic::{Cookie, Query};
+
+mod containers;
+
+mod plugins;
+use plugins::Plugin;
+
+mod detection;
+pub use detection::{Detector, DetectionStrategy};
+
+mod hashing;
+
+mod reader;
+use reader::FileReader;
+pub use reader::ReadBackend;
+
+mod profiles;
+pub use profiles::{Profile, PROFILE_NAMES};
+
+mod checksums;
+pub use checksums::ChecksumFormat;
+
+mod treemap;
+pub use treemap::TreemapFormat;
+
+mod export;
+pub use export::ExportFormat;
+
+mod tags;
+pub use tags::{check_tag, TagRule};
+
+mod search;
+pub use search::{SearchFilter, Timestamp};
+
+mod policy;
+pub use policy::PolicyRule;
+
+mod mount;
+
+mod owners;
+use owners::Owners;
+
+mod gitignore;
+use gitignore::GitIgnores;
+
+mod build_artifacts;
+
+mod checkpoint;
+use checkpoint::Checkpoint;
+
+mod history;
+
+mod email;
+pub use email::Email;
+
+mod notify;
+pub use notify::Notify;
+
+mod config_builder;
+pub use config_builder::{check_dir, check_file, check_hook_exec, check_min_entropy, check_policy_rule, check_positive,
+    check_webhook_url, ConfigBuilder};
+
+mod audit;
+use audit::{AuditLog, Event};
+
+mod sandbox;
+
+mod worker;
+use worker::{Outcome, Worker};
+
+mod upload;
+
+mod input;
+pub use input::{BufferedContent, FileList, InputEntry, InputError, InputSource};
+
+mod archive;
+pub use archive::ArchiveSource;
+
+mod volumes;
+
+mod carving;
+
+mod clamav;
+pub use clamav::Clamav;
+
+mod entropy;
+
+mod encrypted;
+
+mod split;
+
+mod companions;
+pub use companions::{check_companion_extensions, CompanionRule};
+
+mod exif;
+
+mod events;
+pub use events::PhotoEvents;
+
+mod geocoding;
+pub use geocoding::{check_geocoding_layout, Geocoding};
+use geocoding::Geocoder;
+
+mod sinks;
+pub use sinks::{ArchiveSink, CopySink, HardlinkSink, ManifestOnlySink, OutputSink, SymlinkSink};
+use sinks::{ContentSink, LinkRecordSink, StubSink};
+
+#[cfg(feature = "fuse")]
+mod sd_notify;
+
+mod capacity;
+
+mod lns;
+use lns::{IndexReader, IndexWriter, LinkInfo};
+
+mod stubs;
+use stubs::FileStub;
+
+mod case_fold;
+use case_fold::FoldedPaths;
+
 use walkdir::WalkDir;
+use fnv::FnvHashMap;
 
-use slog::{Logger, o, info};
+use slog::{Logger, o, info, debug, trace, warn};
 
 #[derive(Debug)]
 pub struct Config {
     pub mime_info_db_root: PathBuf,
-    pub libmagic_db_file: PathBuf,
+    // libmagic's own default database is used when none is configured or found
+    pub libmagic_db_file: Option<PathBuf>,
     pub libmagic_used_for: Vec<String>,
+    // create missing output roots instead of failing
+    pub create_output: bool,
+    // absolute paths never scanned unless given as the input root itself
+    pub skip_paths: Vec<PathBuf>,
+    // directory names skipped at any depth
+    pub skip_dir_names: Vec<String>,
+    // skip build output and caches (node_modules, __pycache__, target of cargo, CACHEDIR.TAG, ...)
+    pub skip_build_artifacts: bool,
+    // directory names skipped as build output on top of the built-in ones
+    pub artifact_dir_names: Vec<String>,
+    // corrections learned from the user, consulted before any detection
+    pub overrides_db: Option<PathBuf>,
+    // commands run after each file is classified
+    pub hooks: Vec<Hook>,
+    // tags attached to classified files by mime, path and size
+    pub tag_rules: Vec<TagRule>,
+    // expectations on the types of the scanned files, scan and sync fail when one is violated
+    pub policy: Vec<PolicyRule>,
+    // rhai script computing the output location per file (requires the scripting feature)
+    pub routing_script: Option<PathBuf>,
+    // directory of detector plugins consulted before the built-in detection (requires the plugins feature)
+    pub plugins_dir: Option<PathBuf>,
+    // detection chain, special files are always recognized first
+    pub detectors: Vec<Detector>,
+    pub detection_strategy: DetectionStrategy,
+    // bytes read at once when hashing, one buffer is reused for all files
+    pub read_buffer_size: usize,
+    // how file content is read for sniffing and hashing
+    pub read_backend: ReadBackend,
+    // files at least this large on read-only filesystems are memory mapped for hashing, ignored on 32-bit targets
+    pub mmap_min_size: Option<u64>,
+    // walked and hashed entries waiting for classification, the walk pauses when the queue is full
+    pub pipeline_depth: usize,
+    // leading bytes of each file handed to detector plugins
+    pub sniff_buffer_size: usize,
+    // files taking longer are recorded as unknown, detectors then run in a worker process as with Params::isolate
+    pub classify_timeout: Option<Duration>,
+    // output directories expected to get more entries are sharded into subdirectories by name hash
+    pub max_dir_entries: Option<usize>,
+    // how output names with control characters or invalid UTF-8 are rewritten, the manifest keeps the input paths
+    pub sanitize_names: NameSanitization,
+    // globs of the paths where report --security expects executables
+    pub executable_paths: Vec<String>,
+    // extensions of output names are lower-cased, e.g. IMG_0001.JPG is linked as IMG_0001.jpg
+    pub lowercase_extensions: bool,
+    // sent when scan or sync finishes
+    pub notify: Notify,
+    // files are submitted to clamd while classifying
+    pub clamav: Option<Clamav>,
+    // Shannon entropy of each file's leading sniff_buffer_size bytes is recorded in the manifest
+    pub entropy: bool,
+    // files of unknown type with at least this entropy (bits per byte) are laid out under encrypted/, implies entropy
+    pub encrypted_min_entropy: Option<f64>,
+    // zip, 7z and rar archives that need a password get the encrypted tag
+    pub detect_encrypted_archives: bool,
+    // volumes of split archives (NAME.7z.001, NAME.z01, NAME.part1.rar, ...) are recorded as one set and laid out
+    // together by the type of the first one
+    pub group_split_archives: bool,
+    // companion files (the JPEG of a camera raw file, subtitles, checksums) are laid out next to their primary file
+    pub companions: Vec<CompanionRule>,
+    // photos are laid out in event directories by their EXIF capture times
+    pub photo_events: Option<PhotoEvents>,
+    // country and region of geotagged photos are recorded and may lay them out (requires the geocoding feature)
+    pub geocoding: Option<Geocoding>,
 }
 
-#[derive(Debug)]
-pub struct Params {
-    pub input_path: PathBuf,
-    pub output_path: PathBuf,
+impl Default for Config {
+    fn default() -> Self {
+        Config{
+            mime_info_db_root: default_mime_info_db_root(),
+            libmagic_db_file: default_libmagic_db_file(),
+            libmagic_used_for: vec![
+                "application/zip".to_owned(),
+                //"application/x-sharedlib".to_owned()
+            ],
+            create_output: false,
+            skip_paths: ["/proc", "/sys", "/dev", "/run"].iter().map(PathBuf::from).collect(),
+            skip_dir_names: vec!["lost+found".to_owned()],
+            skip_build_artifacts: false,
+            artifact_dir_names: Vec::new(),
+            overrides_db: overrides::default_db_path(),
+            hooks: Vec::new(),
+            tag_rules: Vec::new(),
+            policy: Vec::new(),
+            routing_script: None,
+            plugins_dir: None,
+            detectors: Detector::default_chain(),
+            detection_strategy: DetectionStrategy::default(),
+            read_buffer_size: 64 * 1024,
+            read_backend: ReadBackend::default(),
+            mmap_min_size: None,
+            pipeline_depth: 256,
+            sniff_buffer_size: 8 * 1024,
+            classify_timeout: None,
+            max_dir_entries: None,
+            sanitize_names: NameSanitization::default(),
+            executable_paths: ["*/bin/*", "*/sbin/*", "*/lib/*", "*/lib64/*", "*/libexec/*"]
+                .iter().map(|glob| (*glob).to_owned()).collect(),
+            lowercase_extensions: false,
+            notify: Notify::default(),
+            clamav: None,
+            entropy: false,
+            encrypted_min_entropy: None,
+            detect_encrypted_archives: false,
+            group_split_archives: false,
+            companions: Vec::new(),
+            photo_events: None,
+            geocoding: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkMode {
+    #[default]
+    Symlink,
+    Hardlink,
+    Copy,
+}
+
+impl fmt::Display for LinkMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LinkMode::Symlink => "symlink",
+            LinkMode::Hardlink => "hardlink",
+            LinkMode::Copy => "copy",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for LinkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "symlink" => Ok(LinkMode::Symlink),
+            "hardlink" => Ok(LinkMode::Hardlink),
+            "copy" => Ok(LinkMode::Copy),
+            _ => Err(format!("invalid link mode {}, valid modes are: symlink, hardlink, copy", s)),
+        }
+    }
+}
+
+// what restore does with link targets that are absolute or lead out of the restore root
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkTargetPolicy {
+    // skip such links and report them as file errors
+    #[default]
+    Reject,
+    // re-root absolute targets at the restore root and drop .. components that would leave it
+    Rewrite,
+    // restore targets as they are, for trusted backups
+    Allow,
+}
+
+impl std::str::FromStr for LinkTargetPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(LinkTargetPolicy::Reject),
+            "rewrite" => Ok(LinkTargetPolicy::Rewrite),
+            "allow" => Ok(LinkTargetPolicy::Allow),
+            _ => Err(format!("invalid link target policy {}, valid policies are: reject, rewrite, allow", s)),
+        }
+    }
+}
+
+// output names that break web-based browsing of the output tree: control characters and bytes that are not valid UTF-8
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NameSanitization {
+    // names are used as they are
+    #[default]
+    Keep,
+    // each such character or invalid sequence becomes _
+    Replace,
+    // each of their bytes becomes %XX (and % itself %25), so that the original name can be recovered
+    Percent,
 }
 
-trait Contains<T> {
-    fn contains_ref(&self, val: T) -> bool;
+impl std::str::FromStr for NameSanitization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(NameSanitization::Keep),
+            "replace" => Ok(NameSanitization::Replace),
+            "percent" => Ok(NameSanitization::Percent),
+            _ => Err(format!("invalid name sanitization {}, valid sanitizations are: keep, replace, percent", s)),
+        }
+    }
+}
+
+// how new output entries are spread over several output roots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputSplit {
+    // one file after the other, the roots fill up evenly
+    #[default]
+    RoundRobin,
+    // each top-level type (image, video, ...) always goes to the same root, also across runs
+    MimeGroup,
 }
 
-impl<T, U> Contains<U> for [T] where T: PartialEq<U> {
-    fn contains_ref(&self, val: U) -> bool {
-        self.iter().any(|x| x == &val)
+impl std::str::FromStr for OutputSplit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(OutputSplit::RoundRobin),
+            "mime-group" => Ok(OutputSplit::MimeGroup),
+            _ => Err(format!("invalid output split {}, valid splits are: round-robin, mime-group", s)),
+        }
     }
 }
 
-fn guess_extension<'a>(mime_info_db: &'a mut MimeInfoDb, mime_type: &str) -> Option<&'a str> {
+#[derive(Debug, Default)]
+pub struct Params {
+    pub input_path: PathBuf,
+    pub output_path: PathBuf,
+    // store perceptual hashes of images in the manifest (requires the phash feature)
+    pub perceptual_hash: bool,
+    // write thumbnails of images (and videos with the video-previews feature) under OUTPUT_DIR/previews
+    pub previews: bool,
+    // serve prometheus metrics on this address while scanning, e.g. 127.0.0.1:9898
+    pub metrics_addr: Option<String>,
+    // create the output root (and its parents) if it does not exist
+    pub create_output: bool,
+    // skip an output root nested inside the input tree instead of failing
+    pub exclude_nested_output: bool,
+    // descend into symlinked directories and classify symlinked files (scan only)
+    pub follow_links: bool,
+    // visit each directory (by device and inode) only once, e.g. with bind mounts or followed links
+    pub unique_dirs: bool,
+    // do not descend into directories on other filesystems than the input root
+    pub one_file_system: bool,
+    // skip files excluded by .gitignore files in the input tree and .git/objects (scan only)
+    pub gitignore: bool,
+    // classify the paths listed in this file (one per line or NUL-separated, - for stdin) instead of walking
+    pub files_from: Option<PathBuf>,
+    // classify the input paths recorded in the manifest of this output tree instead of walking
+    pub from_manifest: Option<PathBuf>,
+    // input_path is a tar or zip archive, its entries are classified from memory and only recorded in the
+    // manifest (scan only)
+    pub input_archive: bool,
+    // record the progress of each stage in the output root so that an interrupted scan resumes where it stopped
+    pub checkpoint: bool,
+    // how scan materializes the output entries
+    pub link_mode: LinkMode,
+    // the output entries are written into this tar archive instead of the output tree, which gets the manifest
+    // (requires the archives feature)
+    pub output_archive: Option<PathBuf>,
+    // used instead of hardlinks when input and output are on different filesystems
+    pub cross_device_fallback: Option<LinkMode>,
+    // symlink targets relative to the link location instead of absolute paths
+    pub relative_links: bool,
+    // ask the user about unknown and ambiguous files between classification and linking
+    pub interactive: bool,
+    // file the interactive decisions are merged into as override rules
+    pub save_overrides: Option<PathBuf>,
+    // merge the interactive decisions into the override db used by future scans
+    pub learn: bool,
+    // materialize only files matching one of these MIME patterns (all files if empty), e.g. image/*
+    pub only_mime: Vec<String>,
+    // do not materialize files matching one of these MIME patterns
+    pub skip_mime: Vec<String>,
+    // store Apple Uniform Type Identifiers in the manifest
+    pub uti: bool,
+    // store SHA-256 hashes in the manifest, duplicates reuse the classification of the first copy
+    pub content_hash: bool,
+    // keep the entries of a previous scan into the same output and only add files not linked yet
+    pub merge: bool,
+    // put links directly into the type directories instead of mirroring the input directories
+    pub flat: bool,
+    // write the content hashes to OUTPUT_DIR/.classifiles/SHA256SUMS in this format (requires content_hash)
+    pub checksums: Option<ChecksumFormat>,
+    // hash-chained log of every classification and output change, appended to across runs
+    pub audit_log: Option<PathBuf>,
+    // the --log-file, which is rotated by renaming it
+    pub log_file: Option<PathBuf>,
+    // restrict writes to the output root and deny exec and network syscalls (Linux, requires the sandbox feature)
+    pub sandbox: bool,
+    // run the detectors in a child process that is restarted when it crashes
+    pub isolate: bool,
+    // options passed on to the classify-worker processes so that they load the same configuration
+    pub worker_args: Vec<String>,
+    // how restore treats link targets outside the restore root
+    pub link_targets: LinkTargetPolicy,
+    // record in the backup whether each link target exists and where it resolves to
+    pub check_links: bool,
+    // do not restore links recorded as broken by backup --check-links
+    pub skip_broken_links: bool,
+    // back up regular files as stubs with their size and mtime (and hash with content_hash), without content
+    pub stubs: bool,
+    // back up regular files with their content, unchanged files (size and mtime, or hash with content_hash) are skipped
+    pub content: bool,
+    // back up links into a single compressed index instead of one .lns file each
+    pub link_index: bool,
+    // object store URL (s3://bucket/prefix) the manifest and checksums are uploaded to after the scan
+    pub upload: Option<String>,
+    // further output roots sharing the output entries, e.g. on other filesystems with their own inode limits
+    pub extra_outputs: Vec<PathBuf>,
+    pub output_split: OutputSplit,
+    // do not compare the estimated output size with the free inodes and space before linking
+    pub skip_capacity_check: bool,
+    // compare output names case-folded even where the output roots do not look case-insensitive
+    pub case_insensitive: bool,
+}
+
+fn guess_extensions<'a>(mime_info_db: &'a mut MimeInfoDb, mime_type: &str) -> Option<&'a [String]> {
     let mime = mime_info_db.get(mime_type);
     match mime {
-        Mime::WithExt(ext) => Some(ext),
+        Mime::WithExt(exts) => Some(exts),
         _ => None,
     }
 }
 
-fn get_magic_cookie(libmagic_db_file: &Path, flags: magic::flags::CookieFlags) -> Result<Cookie, Box<dyn Error>> {
-    let cookie = Cookie::open(flags)?;
-    let databases = [libmagic_db_file];
+// shared-mime-info of Linux distributions and Homebrew on macOS (Apple silicon and Intel)
+static MIME_INFO_DB_ROOTS: &[&str] = &["/usr/share/mime", "/opt/homebrew/share/mime", "/usr/local/share/mime"];
 
-    match cookie.load(&databases) {
-        Ok(()) => Ok(cookie),
-        Err(e) => Err(Box::new(e)),
-    }
+fn default_mime_info_db_root() -> PathBuf {
+    MIME_INFO_DB_ROOTS.iter().map(PathBuf::from)
+        .find(|path| path.is_dir())
+        .unwrap_or_else(|| PathBuf::from(MIME_INFO_DB_ROOTS[0]))
+}
+
+// compiled databases of common distributions (Arch, Fedora, Debian, Homebrew on macOS)
+static LIBMAGIC_DB_FILES: &[&str] = &[
+    "/usr/share/file/misc/magic.mgc",
+    "/usr/share/misc/magic.mgc",
+    "/usr/lib/file/magic.mgc",
+    "/opt/homebrew/share/misc/magic.mgc",
+    "/usr/local/share/misc/magic.mgc",
+];
+
+fn default_libmagic_db_file() -> Option<PathBuf> {
+    LIBMAGIC_DB_FILES.iter().map(PathBuf::from).find(|path| path.is_file())
 }
 
-fn get_magic_cookie_opt(libmagic_db_file: &Path, flags: magic::flags::CookieFlags) -> Option<Cookie> {
-    match get_magic_cookie(libmagic_db_file, flags) {
+// without the libmagic feature, classification relies on tree_magic and the built-in container detectors
+fn get_magic_cookie_opt(libmagic_db_file: Option<&Path>, query: Query) -> Option<Cookie> {
+    match Cookie::open(libmagic_db_file, query) {
         Ok(cookie) => Some(cookie),
+        Err(_) if !cfg!(feature = "libmagic") => None,
         Err(e) => {
-            eprintln!("Warning: could not load magic cookie from {}: {}", libmagic_db_file.display(), e);
+            let db = libmagic_db_file.map_or("the default database".to_owned(), |db| db.display().to_string());
+            eprintln!("Warning: could not load magic cookie from {}: {}", db, e);
             None
         },
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    // output root does not exist or is not a directory
+    InvalidOutput,
+    // options or configuration cannot be used
+    Config,
+    // the run completed but some entries could not be processed
+    FileErrors,
+    // the run completed but the scanned files violate the configured policy
+    Policy,
+}
+
 #[derive(Debug)]
-struct ClassifierError(String);
+pub struct ClassifierError(ErrorKind, String);
+
+impl ClassifierError {
+    pub fn kind(&self) -> ErrorKind {
+        self.0
+    }
+}
 
 impl fmt::Display for ClassifierError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.1)
     }
 }
 
 impl Error for ClassifierError {}
 
+// per-entry failures that do not abort the run, reported together at the end
+#[derive(Default)]
+struct FileErrors {
+    errors: Vec<(PathBuf, String)>,
+}
+
+impl FileErrors {
+    fn record(&mut self, path: &Path, e: &dyn Error, log: &Logger) {
+        warn!(log, "{}", e);
+        self.errors.push((path.to_owned(), e.to_string()));
+    }
+
+    fn into_result(self) -> Result<(), Box<dyn Error>> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+
+        let mut counts: FnvHashMap<&str, usize> = FnvHashMap::default();
+        for (_, e) in &self.errors {
+            *counts.entry(e).or_insert(0) += 1;
+        }
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        let mut summary = format!("{} entries could not be processed\n{:>8}  error", self.errors.len(), "count");
+        for (e, count) in counts {
+            summary += &format!("\n{:>8}  {}", count, e);
+        }
+        Err(Box::new(ClassifierError(ErrorKind::FileErrors, summary)))
+    }
+}
+
 struct Classifier {
     config: Config,
     cookie_mime_opt: Option<Cookie>,
     cookie_ext_opt: Option<Cookie>,
+    // only loaded when some override rule is keyed by libmagic description
+    cookie_desc_opt: Option<Cookie>,
+    override_rules: Vec<OverrideRule>,
+    plugins: Vec<Plugin>,
+    // reads the leading bytes of each file, reusing its buffer
+    reader: RefCell<FileReader>,
     mime_info_db: MimeInfoDb,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct FileType {
     mime: Option<String>,
     ext: Option<String>,
+    // the other candidate extensions of the type
+    #[serde(default)]
+    alt_exts: Vec<String>,
 }
 
 impl FileType {
     fn unknown() -> Self {
-        Self{mime: None, ext: None}
+        Self{mime: None, ext: None, alt_exts: Vec::new()}
+    }
+}
+
+// what the detectors look at: a file, or content read into memory under the name it is known by (an archive entry)
+#[derive(Clone, Copy)]
+enum Content<'a> {
+    File(&'a Path),
+    Buffer(&'a Path, &'a [u8]),
+}
+
+impl<'a> Content<'a> {
+    fn name(self) -> &'a Path {
+        match self {
+            Content::File(path) | Content::Buffer(path, _) => path,
+        }
+    }
+
+    fn query(self, cookie: &Cookie) -> Result<String, Box<dyn Error>> {
+        match self {
+            Content::File(path) => cookie.file(path),
+            Content::Buffer(_, data) => cookie.buffer(data),
+        }
+    }
+
+    fn refine_zip(self) -> std::io::Result<Option<String>> {
+        match self {
+            Content::File(path) => containers::refine_zip(path),
+            Content::Buffer(_, data) => containers::refine_zip_reader(&mut std::io::Cursor::new(data)),
+        }
+    }
+
+    // unreadable archives are not taken for encrypted, extracting them will tell
+    fn is_encrypted_archive(self, log: &Logger) -> bool {
+        let result = match self {
+            Content::File(path) => fs::File::open(path).and_then(|mut file| encrypted::is_encrypted(&mut file)),
+            Content::Buffer(_, data) => encrypted::is_encrypted(&mut std::io::Cursor::new(data)),
+        };
+        match result {
+            Ok(is_encrypted) => is_encrypted,
+            Err(e) => {
+                trace!(log, "Could not check the archive for encryption: {}", e);
+                false
+            }
+        }
+    }
+
+    // photos without readable EXIF data have no capture time or position
+    fn exif(self, log: &Logger) -> Option<exif::Exif> {
+        let result = match self {
+            Content::File(path) => fs::File::open(path).and_then(|file| exif::read(&mut std::io::BufReader::new(file))),
+            Content::Buffer(_, data) => exif::read(&mut std::io::Cursor::new(data)),
+        };
+        match result {
+            Ok(exif) => exif,
+            Err(e) => {
+                trace!(log, "Could not read EXIF data: {}", e);
+                None
+            }
+        }
+    }
+}
+
+struct Detection {
+    detector: Detector,
+    mime: String,
+    // set when the detector decides the extension too (overrides, plugins)
+    ext: Option<Option<String>>,
+}
+
+fn special_file_type(meta: &fs::Metadata) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let file_type = meta.file_type();
+    if file_type.is_fifo() {
+        Some("inode/fifo")
+    } else if file_type.is_char_device() {
+        Some("inode/chardevice")
+    } else if file_type.is_block_device() {
+        Some("inode/blockdevice")
+    } else if file_type.is_socket() {
+        Some("inode/socket")
+    } else if file_type.is_file() && meta.len() == 0 {
+        Some("inode/x-empty")
+    } else {
+        None
     }
 }
 
+// everything but directories and (unfollowed) symlinks gets classified, buffered content always
+fn is_scanned(entry: &InputEntry) -> bool {
+    entry.file_type().is_none_or(|file_type| !file_type.is_dir() && !file_type.is_symlink())
+}
+
 impl Classifier {
-    fn new(config: Config) -> Self {
+    fn new(config: Config, plugins: Vec<Plugin>, reader: FileReader) -> Self {
         let mime_info_db = MimeInfoDb::new(&config.mime_info_db_root);
-        let cookie_mime_opt = get_magic_cookie_opt(&config.libmagic_db_file, magic::flags::MIME_TYPE);
-        let cookie_ext_opt = get_magic_cookie_opt(&config.libmagic_db_file, magic::flags::EXTENSION);
+        let cookie_mime_opt = get_magic_cookie_opt(config.libmagic_db_file.as_deref(), Query::MimeType);
+        let cookie_ext_opt = get_magic_cookie_opt(config.libmagic_db_file.as_deref(), Query::Extension);
+
+        let override_rules = match &config.overrides_db {
+            Some(db) => overrides::load_rules(db).unwrap_or_else(|e| {
+                eprintln!("Warning: could not load override rules from {}: {}", db.display(), e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        let cookie_desc_opt = if override_rules.iter().any(|r| r.description.is_some()) {
+            get_magic_cookie_opt(config.libmagic_db_file.as_deref(), Query::Description)
+        } else {
+            None
+        };
+
+        Classifier{config, cookie_mime_opt, cookie_ext_opt, cookie_desc_opt, override_rules, plugins, reader: RefCell::new(reader), mime_info_db}
+    }
+
+    fn learned_type(&self, content: Content) -> Option<FileType> {
+        if self.override_rules.is_empty() {
+            return None;
+        }
+
+        let description = self.cookie_desc_opt.as_ref().and_then(|cookie| content.query(cookie).ok());
+        let signature = match content {
+            Content::File(path) => Signature::of(path, description).ok()?,
+            Content::Buffer(_, data) => Signature::of_bytes(data, description),
+        };
+        self.override_rules.iter()
+            .find(|r| r.matches(&signature))
+            .map(|r| FileType{mime: Some(r.mime.clone()), ext: r.ext.clone(), alt_exts: Vec::new()})
+    }
+
+    fn plugin_type(&self, content: Content, log: &Logger) -> Option<FileType> {
+        if self.plugins.is_empty() {
+            return None;
+        }
+
+        let mut reader = self.reader.borrow_mut();
+        let buf = match content {
+            Content::File(path) => reader.read_leading(path).ok()?,
+            Content::Buffer(_, data) => data,
+        };
+        self.plugins.iter().find_map(|plugin| {
+            let (mime_type, ext) = plugin.detect(buf, content.name())?;
+            debug!(log, "File matches {} (plugin {})", mime_type, plugin.path().display(); "mime" => &mime_type);
+            Some(FileType{mime: Some(mime_type), ext, alt_exts: Vec::new()})
+        })
+    }
+
+    fn refine_builtin(&self, content: Content, mime_type: &str, log: &Logger) -> String {
+        let refined = match mime_type {
+            "application/zip" => content.refine_zip(),
+            _ => Ok(None),
+        };
+        match refined {
+            Ok(Some(mime_type2)) => {
+                trace!(log, "Match {} refined by the built-in detector", mime_type);
+                mime_type2
+            }
+            Ok(None) => mime_type.to_owned(),
+            Err(e) => {
+                trace!(log, "Built-in detector failed: {}", e);
+                mime_type.to_owned()
+            }
+        }
+    }
+
+    fn detect(&self, detector: Detector, content: Content, log: &Logger) -> Option<Detection> {
+        let (mime, ext) = match detector {
+            Detector::Overrides => {
+                let file_type = self.learned_type(content)?;
+                (file_type.mime?, Some(file_type.ext))
+            }
+            Detector::Plugins => {
+                let file_type = self.plugin_type(content, log)?;
+                (file_type.mime?, Some(file_type.ext))
+            }
+            Detector::Extension => {
+                let ext = content.name().extension()?.to_str()?;
+                (mime_db::lookup(ext)?.to_owned(), None)
+            }
+            Detector::TreeMagic => match content {
+                Content::File(path) => (tree_magic_mini::from_filepath(path)?.to_owned(), None),
+                Content::Buffer(_, data) => (tree_magic_mini::from_u8(data).to_owned(), None),
+            },
+            Detector::LibMagic => (content.query(self.cookie_mime_opt.as_ref()?).ok()?, None),
+            Detector::Container => (content.refine_zip().ok()??, None),
+        };
+        // parameters such as charset are not part of the type directory
+        let mime = mime_type::normalized(mime);
+        trace!(log, "Detector {} matches {}", detector, mime);
+        Some(Detection{detector, mime, ext})
+    }
+
+    fn run_detectors(&self, content: Content, log: &Logger) -> Option<Detection> {
+        let mut detections = self.config.detectors.iter().filter_map(|d| self.detect(*d, content, log));
+        match self.config.detection_strategy {
+            DetectionStrategy::FirstMatch => detections.next(),
+            DetectionStrategy::BestConfidence => detections.fold(None, |best: Option<Detection>, d| match best {
+                Some(b) if b.detector.confidence(&b.mime) >= d.detector.confidence(&d.mime) => Some(b),
+                _ => Some(d),
+            }),
+        }
+    }
+
+    // meta comes from the walk, so that files are not stat-ed twice
+    fn process_file(&mut self, input_path: &Path, meta: Option<&fs::Metadata>, log: &Logger) -> FileType {
+        if let Some(meta) = meta {
+            if let Some(mime_type) = special_file_type(meta) {
+                // never open these, reading a fifo or a device could block or have side effects
+                debug!(log, "File matches {}", mime_type; "mime" => mime_type);
+                return FileType{mime: Some(mime_type.to_owned()), ext: None, alt_exts: Vec::new()};
+            }
+        }
+        self.process_content(Content::File(input_path), log)
+    }
 
-        Classifier{config, cookie_mime_opt, cookie_ext_opt, mime_info_db}
+    // an archive entry, data holds its content or at least the leading part of it
+    fn process_buffer(&mut self, name: &Path, data: &[u8], log: &Logger) -> FileType {
+        if data.is_empty() {
+            debug!(log, "File matches inode/x-empty"; "mime" => "inode/x-empty");
+            return FileType{mime: Some("inode/x-empty".to_owned()), ext: None, alt_exts: Vec::new()};
+        }
+        self.process_content(Content::Buffer(name, data), log)
     }
 
-    fn process_file(&mut self, input_path: &Path, log: &Logger) -> FileType {
-        if let Some(mime_type) = tree_magic_mini::from_filepath(input_path) {
-            let mut libmagic_used = false;
+    fn process_content(&mut self, content: Content, log: &Logger) -> FileType {
+        if let Some(detection) = self.run_detectors(content, log) {
+            let mime_type = detection.mime.as_str();
+            if let Some(ext) = detection.ext {
+                debug!(log, "File matches {} ({})", mime_type, detection.detector; "mime" => mime_type);
+                return FileType{mime: Some(detection.mime), ext, alt_exts: Vec::new()};
+            }
+
+            let mut libmagic_used = detection.detector == Detector::LibMagic;
 
-            let mime_type_final = if self.config.libmagic_used_for.contains_ref(mime_type) {
+            let refine = detection.detector == Detector::TreeMagic && self.config.libmagic_used_for.iter()
+                .any(|pattern| mime_matches(pattern, mime_type));
+            let mime_type_final = if refine {
                 match &self.cookie_mime_opt {
                     Some(cookie) => {
-                        info!(log, "Match {} can be further refined", mime_type);
-                        match cookie.file(input_path) {
+                        trace!(log, "Match {} can be further refined", mime_type);
+                        match content.query(cookie) {
                             Ok(mime_type2) => {
                                 libmagic_used = true;
-                                mime_type2
+                                mime_type::normalized(mime_type2)
                             },
                             Err(_) => mime_type.to_owned(),
                         }
                     },
-                    None => mime_type.to_owned(),
+                    None => self.refine_builtin(content, mime_type, log),
                 }
             } else {
                 mime_type.to_owned()
             };
-            info!(log, "File matches {}", mime_type_final);
+            debug!(log, "File matches {}", mime_type_final; "mime" => &mime_type_final);
 
-            if let Some(ext) = guess_extension(&mut self.mime_info_db, &mime_type_final).map(str::to_owned).or_else(|| {
+            if let Some(mut exts) = guess_extensions(&mut self.mime_info_db, &mime_type_final).map(<[String]>::to_vec).or_else(|| {
                 match &self.cookie_ext_opt {
                     Some(cookie) if libmagic_used =>
-                        match cookie.file(input_path) {
+                        match content.query(cookie) {
                             Ok(exts) if exts.len() > 0 && exts != "???" => {
-                                let ext = exts.split('/').next().unwrap().to_owned();
+                                let exts: Vec<String> = exts.split('/').filter(|ext| !ext.is_empty()).map(str::to_owned).collect();
+                                if exts.is_empty() {
+                                    return None;
+                                }
                                 // libmagic cannot return both mime and extension in one operation
                                 // but we can cache the mapping to avoid matching each file twice
-                                self.mime_info_db.set(&mime_type_final, &ext);
-                                Some(ext)
+                                self.mime_info_db.set(&mime_type_final, exts.clone());
+                                Some(exts)
                             },
                             _ => None,
                         }
                     _ => None,
                 }
+            }).or_else(|| {
+                let (ancestor, exts) = self.mime_info_db.inherited(&mime_type_final)?;
+                trace!(log, "Inheriting extensions of {}", ancestor);
+                Some(exts)
             }) {
-                info!(log, "Guessed extension: {}", ext);
-                return FileType{mime: Some(mime_type_final), ext: Some(ext)};
+                // the candidate the file name already ends with is kept (photo.jpeg is not renamed to photo.jpeg.jpg)
+                let preferred = content.name().file_name()
+                    .and_then(|name| exts.iter().position(|ext| has_ext(name, ext)))
+                    .unwrap_or(0);
+                let ext = exts.remove(preferred);
+                trace!(log, "Guessed extension: {}", ext);
+                return FileType{mime: Some(mime_type_final), ext: Some(ext), alt_exts: exts};
             }
 
-            return FileType{mime: Some(mime_type_final), ext: None};
+            return FileType{mime: Some(mime_type_final), ext: None, alt_exts: Vec::new()};
         }
 
         FileType::unknown()
@@ -154,6 +906,9 @@ impl Classifier {
 }
 
 static OUTPUT_UNKNOWN: &str = "unknown";
+// recorded in the manifest for files the isolated detectors crashed on or did not finish in time
+static FAILURE_CRASHED: &str = "crashed-detector";
+static FAILURE_TIMEOUT: &str = "timeout";
 
 fn random_name(ext: &Option<String>) -> PathBuf {
     use rand::Rng;
@@ -170,268 +925,1919 @@ fn random_name(ext: &Option<String>) -> PathBuf {
     PathBuf::from(name)
 }
 
-fn append_ext_if_needed(file_name: &OsStr, ext: &Option<String>) -> PathBuf {
-    if let Some(ext) = ext {
-        let file_ext = Path::new(file_name).extension().unwrap_or(OsStr::new(""));
+// extensions of several parts which belong together, archive.tar.gz is not archive.tar with a gz extension
+static MULTI_PART_EXTS: &[&str] = &["tar.gz", "tar.bz2", "tar.xz", "tar.zst", "tar.lz", "tar.lz4", "tar.lzma"];
 
-        if file_ext != OsStr::new(ext) {
-            // if the file does not already have the guessed extension, append it
-            let mut new_file_name = file_name.to_owned();
-            new_file_name.push(".");
-            new_file_name.push(ext);
-            return PathBuf::from(new_file_name);
-        }
-    }
+// whether the name ends with .ext in any case, a name consisting of the extension alone (.gz) does not
+fn has_ext(file_name: &OsStr, ext: &str) -> bool {
+    use std::os::unix::ffi::OsStrExt;
 
-    PathBuf::from(file_name)
+    let name = file_name.as_bytes();
+    name.len() > ext.len() + 1 && name[name.len() - ext.len() - 1] == b'.'
+        && name[name.len() - ext.len()..].eq_ignore_ascii_case(ext.as_bytes())
 }
 
-fn link_to_output(input: &Path, input_root: &Path, output_root: &Path, file_type: &FileType) -> Result<(), Box<dyn Error>> {
-    let mut output_name = input.file_name()
-        .map(|s| append_ext_if_needed(s, &file_type.ext))
-        .unwrap_or(random_name(&file_type.ext));
+// name without the extension and the extension, a known multi-part extension is kept whole
+fn split_ext(file_name: &OsStr) -> (&OsStr, Option<&OsStr>) {
+    use std::os::unix::ffi::OsStrExt;
 
-    let mut output_link_dir = match &file_type.mime {
-        Some(mime_str) => output_root.join(mime_str),
-        None => output_root.join(OUTPUT_UNKNOWN),
-    };
-    if let Ok(input_rel) = input.strip_prefix(input_root) {
-        if let Some(input_rel_dir) = input_rel.parent() {
-            output_link_dir = output_link_dir.join(input_rel_dir);
-        }
+    if let Some(ext) = MULTI_PART_EXTS.iter().find(|ext| has_ext(file_name, ext)) {
+        let name = file_name.as_bytes();
+        let split = name.len() - ext.len();
+        return (OsStr::from_bytes(&name[..split - 1]), Some(OsStr::from_bytes(&name[split..])));
     }
+    let path = Path::new(file_name);
+    match path.extension() {
+        Some(ext) => (path.file_stem().unwrap_or(file_name), Some(ext)),
+        None => (file_name, None),
+    }
+}
 
-    fs::create_dir_all(&output_link_dir)?;
-
-    while fs::symlink_metadata(output_link_dir.join(&output_name)).is_ok() {
-        // path already exists so we have to use a different name
-        output_name = match output_name.file_stem() {
-            Some(stem) => {
-                let mut output_name_str = stem.to_owned(); // output_name without extension
-                output_name_str.push("-");
-                output_name_str.push(&random_name(&None)); // random string
-                match output_name.extension() {
-                    Some(out_ext) => {
-                        output_name_str.push(".");
-                        output_name_str.push(out_ext); // output_name extension
-                    }
-                    None => ()
-                }
+// the guessed extension is appended unless the name has it already in any case (IMG_0001.JPG for jpg),
+// a name ending in the last parts of a multi-part extension gets the missing ones (backup.gz becomes backup.tar.gz)
+fn append_ext_if_needed(file_name: &OsStr, ext: &Option<String>, lowercase_ext: bool) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
 
-                PathBuf::from(output_name_str)
+    let mut new_file_name = file_name.to_owned();
+    if let Some(ext) = ext {
+        let tails = std::iter::once(ext.as_str()).chain(ext.match_indices('.').map(|(i, _)| &ext[i + 1..]));
+        match tails.enumerate().find(|(_, tail)| has_ext(file_name, tail)) {
+            Some((0, _)) => (),
+            Some((_, tail)) => {
+                let name = file_name.as_bytes();
+                let split = name.len() - tail.len();
+                new_file_name = OsStr::from_bytes(&name[..split]).to_owned();
+                new_file_name.push(&ext[..ext.len() - tail.len()]);
+                new_file_name.push(OsStr::from_bytes(&name[split..]));
+            }
+            None => {
+                new_file_name.push(".");
+                new_file_name.push(ext);
             }
-            None => random_name(&file_type.ext)
         }
     }
-
-    unix_fs::symlink(input, output_link_dir.join(&output_name))?;
-    Ok(())
+    if lowercase_ext {
+        new_file_name = lowercased_ext(&new_file_name);
+    }
+    PathBuf::from(new_file_name)
 }
 
-struct BackupProcessor {
-    params: Params
+// extensions that are not valid UTF-8 are kept as they are
+fn lowercased_ext(file_name: &OsStr) -> std::ffi::OsString {
+    match split_ext(file_name) {
+        (stem, Some(ext)) => match ext.to_str() {
+            Some(ext) => {
+                let mut lowercased = stem.to_owned();
+                lowercased.push(".");
+                lowercased.push(ext.to_lowercase());
+                lowercased
+            }
+            None => file_name.to_owned(),
+        },
+        _ => file_name.to_owned(),
+    }
 }
 
-impl BackupProcessor {
-    fn new(params: Params) -> Self {
-        Self{params}
-    }
+fn sanitized_name(name: &OsStr, sanitization: NameSanitization) -> std::ffi::OsString {
+    use std::os::unix::ffi::OsStrExt;
 
-    fn input_root(&self) -> &Path {
-        &self.params.input_path
+    let escape = |bytes: &[u8], sanitized: &mut String| match sanitization {
+        NameSanitization::Percent => bytes.iter().for_each(|b| sanitized.push_str(&format!("%{:02X}", b))),
+        _ => sanitized.push('_'),
+    };
+    let mut sanitized = String::new();
+    for chunk in name.as_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c.is_control() || (c == '%' && sanitization == NameSanitization::Percent) {
+                escape(c.encode_utf8(&mut [0; 4]).as_bytes(), &mut sanitized);
+            } else {
+                sanitized.push(c);
+            }
+        }
+        if !chunk.invalid().is_empty() {
+            escape(chunk.invalid(), &mut sanitized);
+        }
     }
+    sanitized.into()
+}
 
-    fn output_root(&self) -> &Path {
-        &self.params.output_path
+fn sanitized_path(path: &Path, sanitization: NameSanitization) -> PathBuf {
+    if sanitization == NameSanitization::Keep {
+        return path.to_owned();
     }
+    path.components()
+        .map(|component| match component {
+            std::path::Component::Normal(name) => sanitized_name(name, sanitization),
+            other => other.as_os_str().to_owned(),
+        })
+        .collect()
+}
 
-    fn backup_item<F>(&self, src_path: &Path, creator: F) -> Result<(), Box<dyn Error>>
-        where F: Fn(&Path) -> std::io::Result<()> {
-
-        let src_rel_path = src_path.strip_prefix(self.input_root())?;
-        let dst_path = self.output_root().to_owned().join(src_rel_path);
-        creator(&dst_path)?;
+// longest file name (in bytes) of common filesystems and longest path the kernel resolves, without the terminating NUL
+const NAME_MAX: usize = 255;
+const PATH_MAX: usize = 4095;
+// longer extensions are not kept when a name is shortened
+const MAX_KEPT_EXT: usize = 16;
 
-        Ok(())
-    }
+// stable across runs, unlike the std hasher
+fn name_hash(bytes: &[u8]) -> u64 {
+    use std::hash::Hasher;
 
-    fn backup_dir(&self, src_path: &Path, log: &Logger)  -> Result<(), Box<dyn Error>> {
-        self.backup_item(src_path, |dst| {
-            // println!("read dir: {}, write to: {}", src_path.display(), dst.display());
-            info!(log, "{} -> {}", src_path.display(), dst.display());
-            fs::create_dir_all(dst)?;
-            Ok(())
-        })
-    }
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(bytes);
+    hasher.finish()
+}
 
-    fn backup_symlink(&self, src_path: &Path, log: &Logger)  -> Result<(), Box<dyn Error>> {
-        self.backup_item(src_path, |dst| {
-            let link_target = fs::read_link(src_path)?;
+// names longer than NAME_MAX keep their beginning and extension, the rest is replaced by a hash of the whole name
+fn clamped_name(name: &OsStr) -> std::ffi::OsString {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
 
-            let mut dst_str = dst.as_os_str().to_owned();
-            dst_str.push(".lns");
-            let dst_file = PathBuf::from(dst_str);
+    let bytes = name.as_bytes();
+    if bytes.len() <= NAME_MAX {
+        return name.to_owned();
+    }
+    let ext = Path::new(name).extension().map(OsStr::as_bytes).filter(|ext| ext.len() <= MAX_KEPT_EXT);
+    let suffix = format!("~{:016x}", name_hash(bytes));
+    let mut prefix_len = NAME_MAX - suffix.len() - ext.map_or(0, |ext| ext.len() + 1);
+    // not in the middle of a UTF-8 sequence
+    while prefix_len > 0 && bytes[prefix_len] & 0xc0 == 0x80 {
+        prefix_len -= 1;
+    }
 
-            // println!("read link from: {}, with target: {}, write to: {}",
-            //     src_path.display(), link_target.display(), dst_file.display());
-            info!(log, "{} -> {}", src_path.display(), dst_file.display());
-            let link_target_bytes = link_target.as_os_str().as_bytes();
-            fs::write(dst_file, [link_target_bytes, &[b'\n']].concat())?;
-            Ok(())
-        })
+    let mut clamped = bytes[..prefix_len].to_vec();
+    clamped.extend_from_slice(suffix.as_bytes());
+    if let Some(ext) = ext {
+        clamped.push(b'.');
+        clamped.extend_from_slice(ext);
     }
+    std::ffi::OsString::from_vec(clamped)
 }
 
-fn get_entry_log(log: &Logger, item: &Path, i: usize, item_count: usize) -> Logger {
-    let percent = (((i + 1) as f64 / item_count as f64) * 100.0) as u32;
-    log.new(o!("progress" => format!("{} % ({}/{})", percent, i + 1, item_count)))
-        .new(o!("item" => format!("{}", item.display())))
-}
+// directories too deep for PATH_MAX keep their leading components, the rest is replaced by one hashed component
+fn clamped_dir(output_root: &Path, dir: &Path, name: &Path) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
 
-pub fn run_backup(params: Params, log: &Logger) -> Result<(), Box<dyn Error>> {
-    if !params.output_path.is_dir() {
-        return Err(Box::new(ClassifierError(
-            format!("{} is not a directory", params.output_path.display())
-        )));
+    let len = |path: &Path| path.as_os_str().len();
+    // the separators and a collision suffix
+    let budget = PATH_MAX.saturating_sub(len(output_root) + len(name) + 2 + 8);
+    if len(dir) <= budget {
+        return output_root.join(dir);
     }
 
-    let b_proc = BackupProcessor::new(params);
-    let get_walker = || WalkDir::new(b_proc.input_root()).into_iter().filter_map(|e| e.ok());
-
-    let item_count = get_walker().count();
-    let walker = get_walker();
+    let hashed = format!("~{:016x}", name_hash(dir.as_os_str().as_bytes()));
+    let mut kept = PathBuf::new();
+    let mut components = dir.components();
+    while let Some(component) = components.clone().next() {
+        if len(&kept) + component.as_os_str().len() + 1 + hashed.len() + 1 > budget {
+            break;
+        }
+        kept.push(component);
+        components.next();
+    }
+    output_root.join(kept).join(hashed)
+}
 
-    for (i, entry) in walker.enumerate() {
-        let entry_log = get_entry_log(log, entry.path(), i, item_count);
+// names created by this run, kept when --case-insensitive is given or any of the output roots
+// is case-insensitive (exFAT, NTFS), where Foo.JPG would replace foo.jpg
+fn case_folding<'a>(params: &Params, output_roots: impl IntoIterator<Item = &'a Path>)
+    -> Result<Option<FoldedPaths>, Box<dyn Error>> {
 
-        if let Ok(entry_info) = fs::symlink_metadata(entry.path()) {
-            if entry_info.is_dir() {
-                // println!("Visiting {}", entry.path().display());
-                b_proc.backup_dir(entry.path(), &entry_log)?;
-            } else if entry_info.file_type().is_symlink() {
-                b_proc.backup_symlink(entry.path(), &entry_log)?;
-            }
+    if params.case_insensitive {
+        return Ok(Some(FoldedPaths::default()));
+    }
+    for output_root in output_roots {
+        let probed = case_fold::is_case_insensitive(output_root)
+            .map_err(|e| format!("could not probe case sensitivity of {}: {}", output_root.display(), e))?;
+        if probed {
+            return Ok(Some(FoldedPaths::default()));
         }
     }
-
-    Ok(())
+    Ok(None)
 }
 
-struct RestoreProcessor {
-    params: Params
+// an entry differing only in case from one created before on a case-insensitive output
+fn case_collision(path: &Path) -> Box<dyn Error> {
+    format!("{} differs only in case from an entry created before on a case-insensitive output", path.display()).into()
 }
 
-impl RestoreProcessor {
-    fn new(params: Params) -> Self {
-        Self{params}
-    }
+// directory (relative to the output root) and name of a new output entry:
+// a route from the routing script replaces the MIME type directory (and the mirrored input directories)
+// the input-relative directories are mirrored below the type directory unless input_root is None (flat layout),
+// both are sanitized and their components shortened to NAME_MAX in the end
+fn output_location(input: &Path, input_root: Option<&Path>, file_type: &FileType, photo_dir: Option<&Path>,
+    route: Option<Route>, sanitization: NameSanitization, lowercase_ext: bool) -> (PathBuf, PathBuf) {
 
-    fn input_root(&self) -> &Path {
-        &self.params.input_path
-    }
+    let mut output_name = input.file_name()
+        .map(|s| append_ext_if_needed(s, &file_type.ext, lowercase_ext))
+        .unwrap_or(random_name(&file_type.ext));
+
+    let output_link_dir = match route {
+        Some(Route{dir, name}) => {
+            if let Some(name) = name {
+                output_name = PathBuf::from(name);
+            }
+            dir
+        }
+        None => {
+            let mut output_link_dir = match &file_type.mime {
+                Some(mime_str) => mime_str.parse::<MimeType>().map_or_else(|_| PathBuf::from(mime_str), |mime| mime.dir()),
+                None => PathBuf::from(OUTPUT_UNKNOWN),
+            };
+            // the place and event of a photo come before the mirrored input directories, they may span several
+            if let Some(photo_dir) = photo_dir {
+                output_link_dir.push(photo_dir);
+            }
+            if let Some(Ok(input_rel)) = input_root.map(|root| input.strip_prefix(root)) {
+                if let Some(input_rel_dir) = input_rel.parent() {
+                    output_link_dir = output_link_dir.join(input_rel_dir);
+                }
+            }
+            output_link_dir
+        }
+    };
+    let clamped = |path: PathBuf| -> PathBuf {
+        path.components().map(|component| clamped_name(component.as_os_str())).collect()
+    };
+    (clamped(sanitized_path(&output_link_dir, sanitization)), clamped(sanitized_path(&output_name, sanitization)))
+}
+
+// name collisions get a random suffix, with folded also names differing only in case from ones linked before
+fn link_to_output(input: &Path, output_link_dir: &Path, mut output_name: PathBuf, file_type: &FileType,
+    sink: &mut dyn OutputSink, folded: Option<&mut FoldedPaths>, log: &Logger) -> Result<PathBuf, Box<dyn Error>> {
+
+    let taken = |path: &Path, folded: &Option<&mut FoldedPaths>| {
+        sink.exists(path) || folded.as_ref().is_some_and(|folded| folded.contains(path))
+    };
+    while taken(&output_link_dir.join(&output_name), &folded) {
+        // path already exists so we have to use a different name
+        output_name = match split_ext(output_name.as_os_str()) {
+            (stem, out_ext) if !stem.is_empty() => {
+                let mut output_name_str = stem.to_owned(); // output_name without extension
+                output_name_str.push("-");
+                output_name_str.push(random_name(&None)); // random string
+                if let Some(out_ext) = out_ext {
+                    output_name_str.push(".");
+                    output_name_str.push(out_ext); // output_name extension
+                }
+
+                PathBuf::from(clamped_name(&output_name_str))
+            }
+            _ => random_name(&file_type.ext)
+        }
+    }
+
+    let output_link = output_link_dir.join(&output_name);
+    sink.put(input, &output_link, log)?;
+    if let Some(folded) = folded {
+        folded.insert(&output_link);
+    }
+    Ok(output_link)
+}
+
+// the output root and the extra output roots, entries outside the output root are recorded with their absolute path
+struct OutputRoots {
+    roots: Vec<PathBuf>,
+    split: OutputSplit,
+    next: usize,
+}
+
+impl OutputRoots {
+    // extra roots given as relative paths are made absolute, the paths of their entries are recorded as they are
+    fn new(params: &Params) -> std::io::Result<Self> {
+        let mut roots = vec![params.output_path.clone()];
+        for extra_output in &params.extra_outputs {
+            roots.push(absolute_path(extra_output)?);
+        }
+        Ok(Self{roots, split: params.output_split, next: 0})
+    }
+
+    // the one holding the manifest
+    fn output_root(&self) -> &Path {
+        &self.roots[0]
+    }
+
+    // root for a new output entry
+    fn pick(&mut self, file_type: &FileType) -> &Path {
+        let i = match self.split {
+            OutputSplit::RoundRobin => {
+                let i = self.next;
+                self.next = (i + 1) % self.roots.len();
+                i
+            }
+            OutputSplit::MimeGroup => {
+                let mime = file_type.mime.as_deref().unwrap_or(OUTPUT_UNKNOWN);
+                let media = mime.parse::<MimeType>().map_or_else(|_| mime.to_owned(), |mime| mime.media().to_owned());
+                name_hash(media.as_bytes()) as usize % self.roots.len()
+            }
+        };
+        &self.roots[i]
+    }
+
+    // root an existing output entry was placed in
+    fn containing(&self, entry: &Path) -> &Path {
+        self.roots.iter().skip(1).find(|root| entry.starts_with(root)).unwrap_or(&self.roots[0])
+    }
+}
+
+// subdirectories 00 to ff by name hash for output directories that would get more than max_dir_entries entries,
+// one more level for each factor of 256 above
+struct Sharding {
+    levels: FnvHashMap<PathBuf, u32>,
+}
+
+impl Sharding {
+    // dirs are relative to the output root, one per new entry; spread is the number of roots sharing them
+    fn new<'a>(max_entries: Option<usize>, dirs: impl Iterator<Item = &'a Path>, output_root: &Path, spread: usize) -> Self {
+        let max_entries = match max_entries {
+            Some(max_entries) => max_entries,
+            None => return Self{levels: FnvHashMap::default()},
+        };
+        let mut counts: FnvHashMap<&Path, usize> = FnvHashMap::default();
+        for dir in dirs {
+            *counts.entry(dir).or_default() += 1;
+        }
+
+        let mut levels = FnvHashMap::default();
+        for (dir, count) in counts {
+            let output_dir = output_root.join(dir);
+            // a directory sharded by a previous run keeps its layout
+            let mut dir_levels = Self::existing_levels(&output_dir);
+            if dir_levels == 0 {
+                // entries of a previous run stay where they are, but count
+                let count = count.div_ceil(spread) + fs::read_dir(&output_dir).map_or(0, Iterator::count);
+                let mut capacity = max_entries;
+                while capacity < count {
+                    capacity = capacity.saturating_mul(256);
+                    dir_levels += 1;
+                }
+            }
+            if dir_levels > 0 {
+                levels.insert(dir.to_owned(), dir_levels);
+            }
+        }
+        Self{levels}
+    }
+
+    // a sharded directory holds nothing but shard directories
+    fn existing_levels(dir: &Path) -> u32 {
+        let entries: Vec<fs::DirEntry> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(Result::ok).collect(),
+            Err(_) => return 0,
+        };
+        let is_shard = |entry: &fs::DirEntry| {
+            let name = entry.file_name();
+            name.len() == 2 && name.to_str().is_some_and(|n| n.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')))
+                && entry.file_type().is_ok_and(|t| t.is_dir())
+        };
+        match entries.first() {
+            Some(first) if entries.iter().all(is_shard) => 1 + Self::existing_levels(&first.path()),
+            _ => 0,
+        }
+    }
+
+    fn shard(&self, dir: &Path, name: &Path) -> PathBuf {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut sharded = dir.to_owned();
+        if let Some(&levels) = self.levels.get(dir) {
+            // the same for every run, so that a name always lands in the same shard
+            let hash = name_hash(name.as_os_str().as_bytes());
+            for level in 0..levels {
+                sharded.push(format!("{:02x}", (hash >> (8 * level)) & 0xff));
+            }
+        }
+        sharded
+    }
+}
+
+#[cfg(feature = "phash")]
+fn image_hash(input: &Path, file_type: &FileType, log: &Logger) -> Option<u64> {
+    match &file_type.mime {
+        Some(mime) if mime.starts_with("image/") => match phash::dhash(input) {
+            Ok(hash) => Some(hash),
+            Err(e) => {
+                warn!(log, "Could not compute perceptual hash: {}", e);
+                None
+            }
+        },
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "phash"))]
+fn image_hash(_input: &Path, _file_type: &FileType, _log: &Logger) -> Option<u64> {
+    None
+}
+
+#[cfg(feature = "previews")]
+fn make_preview(input: &Path, output_root: &Path, link: &Path, file_type: &FileType, log: &Logger) -> Option<PathBuf> {
+    let mime = file_type.mime.as_ref()?;
+    let dst = previews::preview_path(output_root, link);
+    match previews::write_preview(input, mime, &dst) {
+        Ok(true) => Some(dst),
+        Ok(false) => None,
+        Err(e) => {
+            warn!(log, "Could not create preview: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "previews"))]
+fn make_preview(_input: &Path, _output_root: &Path, _link: &Path, _file_type: &FileType, _log: &Logger) -> Option<PathBuf> {
+    None
+}
+
+struct BackupProcessor {
+    params: Params,
+    // .lns files or the link index
+    links: RefCell<Box<dyn OutputSink>>,
+    // stubs or copies, regular files are left out without either
+    files: Option<RefCell<Box<dyn OutputSink>>>,
+    folded: Option<RefCell<FoldedPaths>>,
+}
+
+impl BackupProcessor {
+    fn new(params: Params, log: &Logger) -> Result<Self, Box<dyn Error>> {
+        if params.stubs && params.content {
+            return Err(Box::new(ClassifierError(ErrorKind::Config, "--stubs and --content cannot be combined".to_owned())));
+        }
+        // backup has no configuration, files are hashed with the default buffer size
+        let hash_reader = match (params.stubs || params.content) && params.content_hash {
+            true => Some(FileReader::new(ReadBackend::Blocking, 0, <Config as Default>::default().read_buffer_size,
+                None, log)?),
+            false => None,
+        };
+        let link_index = match params.link_index {
+            true => Some(IndexWriter::create(&params.output_path)
+                .map_err(|e| format!("could not create link index: {}", e))?),
+            false => None,
+        };
+        let links: Box<dyn OutputSink> = Box::new(LinkRecordSink::new(&params.output_path, params.check_links,
+            link_index));
+        let files: Option<Box<dyn OutputSink>> = match (params.stubs, params.content) {
+            (true, _) => Some(Box::new(StubSink{hash_reader})),
+            (_, true) => Some(Box::new(ContentSink{hash_reader})),
+            _ => None,
+        };
+        let folded = case_folding(&params, Some(params.output_path.as_path()))?.map(RefCell::new);
+        Ok(Self{params, links: RefCell::new(links), files: files.map(RefCell::new), folded})
+    }
+
+    fn finish(self) -> Result<(), Box<dyn Error>> {
+        self.links.into_inner().finish().map_err(|e| format!("could not write link index: {}", e))?;
+        if let Some(files) = self.files {
+            files.into_inner().finish()?;
+        }
+        Ok(())
+    }
+
+    fn input_root(&self) -> &Path {
+        &self.params.input_path
+    }
+
+    fn output_root(&self) -> &Path {
+        &self.params.output_path
+    }
+
+    fn backup_item<F>(&self, src_path: &Path, creator: F) -> Result<(), Box<dyn Error>>
+        where F: Fn(&Path) -> std::io::Result<()> {
+
+        let src_rel_path = src_path.strip_prefix(self.input_root())?;
+        let dst_path = self.output_root().to_owned().join(src_rel_path);
+        // the entry backed up later would replace the other one
+        if let Some(folded) = &self.folded {
+            if !folded.borrow_mut().insert(&dst_path) {
+                return Err(case_collision(&dst_path));
+            }
+        }
+        creator(&dst_path)?;
+
+        Ok(())
+    }
+
+    fn backup_dir(&self, src_path: &Path, log: &Logger)  -> Result<(), Box<dyn Error>> {
+        self.backup_item(src_path, |dst| {
+            // println!("read dir: {}, write to: {}", src_path.display(), dst.display());
+            debug!(log, "{} -> {}", src_path.display(), dst.display());
+            fs::create_dir_all(dst)?;
+            Ok(())
+        })
+    }
+
+    fn backup_symlink(&self, src_path: &Path, log: &Logger)  -> Result<(), Box<dyn Error>> {
+        self.backup_item(src_path, |dst| self.links.borrow_mut().put(src_path, dst, log))
+    }
+
+    fn backup_file(&self, src_path: &Path, log: &Logger)  -> Result<(), Box<dyn Error>> {
+        match &self.files {
+            Some(files) => self.backup_item(src_path, |dst| files.borrow_mut().put(src_path, dst, log)),
+            None => Ok(()),
+        }
+    }
+}
+
+// extensions of the records backup writes in place of links and files
+static RECORD_EXTENSIONS: &[&str] = &["lns", "stub"];
+// appended to backed up file names that would otherwise be taken for a record, and to names already ending in it
+static ESCAPE_EXTENSION: &str = "file";
+
+fn escaped_file_name(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(ext) if RECORD_EXTENSIONS.iter().chain(&[ESCAPE_EXTENSION]).any(|e| ext == OsStr::new(e)) => {
+            let mut escaped = path.as_os_str().to_owned();
+            escaped.push(".");
+            escaped.push(ESCAPE_EXTENSION);
+            PathBuf::from(escaped)
+        }
+        _ => path.to_owned(),
+    }
+}
+
+fn unescaped_file_name(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(ext) if ext == OsStr::new(ESCAPE_EXTENSION) => path.with_extension(""),
+        _ => path.to_owned(),
+    }
+}
+
+// quick check on size and mtime like rsync, or on content hashes when a reader is given;
+// the copy gets the mtime of the source so that the next run can skip it
+fn copy_if_changed(src: &Path, src_meta: &fs::Metadata, dst: &Path, hash_reader: Option<&mut FileReader>)
+    -> std::io::Result<bool> {
+
+    if let Ok(dst_meta) = fs::metadata(dst) {
+        let unchanged = dst_meta.is_file() && dst_meta.len() == src_meta.len() && match hash_reader {
+            Some(reader) => hashing::sha256_file(src, reader)? == hashing::sha256_file(dst, reader)?,
+            None => dst_meta.modified().ok() == src_meta.modified().ok(),
+        };
+        if unchanged {
+            return Ok(false);
+        }
+    }
+    fs::copy(src, dst)?;
+    fs::File::options().write(true).open(dst)?.set_modified(src_meta.modified()?)?;
+    Ok(true)
+}
+
+// absolute path without resolving the final components that do not exist yet
+fn absolute_path(path: &Path) -> std::io::Result<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    while fs::symlink_metadata(existing).is_err() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            }
+            _ => break,
+        }
+    }
+
+    let mut absolute = existing.canonicalize()?;
+    for name in missing.into_iter().rev() {
+        absolute.push(name);
+    }
+    Ok(absolute)
+}
+
+// absolute path with the parent directory canonicalized but the entry itself (possibly a symlink) kept
+fn absolute_entry_path(path: &Path) -> std::io::Result<PathBuf> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            Ok(parent.canonicalize()?.join(name))
+        }
+        _ => path.canonicalize(),
+    }
+}
+
+// path leading from the absolute directory from_dir to the absolute path to
+fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from_components: Vec<_> = from_dir.components().collect();
+    let to_components: Vec<_> = to.components().collect();
+    let common = from_components.iter()
+        .zip(&to_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut rel = PathBuf::new();
+    for _ in common..from_components.len() {
+        rel.push("..");
+    }
+    for component in &to_components[common..] {
+        rel.push(component);
+    }
+    rel
+}
+
+// returns the output subtree (as seen from the input root) to be skipped while walking the input
+fn prepare_output_root(params: &Params, create: bool) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    prepare_output_dir(params, &params.output_path, create)
+}
+
+// the excluded input paths of the extra output roots, which are prepared like the output root
+fn prepare_extra_outputs(params: &Params, create: bool) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut excluded = Vec::new();
+    for output_path in &params.extra_outputs {
+        excluded.extend(prepare_output_dir(params, output_path, create)?);
+    }
+    Ok(excluded)
+}
+
+fn prepare_output_dir(params: &Params, output_path: &Path, create: bool) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if create && !output_path.exists() {
+        let input_root = params.input_path.canonicalize()?;
+        let output_root = absolute_path(output_path)?;
+        if output_root.starts_with(&input_root) {
+            return Err(Box::new(ClassifierError(ErrorKind::InvalidOutput,
+                format!("refusing to create {} inside the input tree", output_path.display())
+            )));
+        }
+        fs::create_dir_all(output_path)?;
+    }
+
+    if !output_path.is_dir() {
+        return Err(Box::new(ClassifierError(ErrorKind::InvalidOutput,
+            format!("{} is not a directory", output_path.display())
+        )));
+    }
+
+    // otherwise the walk would visit the freshly created output entries
+    let input_root = params.input_path.canonicalize()?;
+    let output_root = output_path.canonicalize()?;
+    match output_root.strip_prefix(&input_root) {
+        Ok(output_rel) if output_rel.as_os_str().is_empty() => Err(Box::new(ClassifierError(ErrorKind::InvalidOutput,
+            "input and output are the same directory".to_owned()
+        ))),
+        Ok(output_rel) if params.exclude_nested_output => Ok(Some(params.input_path.join(output_rel))),
+        Ok(_) => Err(Box::new(ClassifierError(ErrorKind::InvalidOutput,
+            format!("{} is inside the input tree, use --exclude-output to skip it", output_path.display())
+        ))),
+        Err(_) => Ok(None),
+    }
+}
+
+#[derive(Default)]
+struct WalkOptions {
+    excluded: Vec<PathBuf>,
+    excluded_dir_names: Vec<String>,
+    follow_links: bool,
+    unique_dirs: bool,
+    one_file_system: bool,
+    // skip what .gitignore files in the tree exclude and the object stores of repositories
+    gitignore: bool,
+    // skip build output and caches, by the curated lists and these further directory names
+    build_artifacts: Option<Vec<String>>,
+}
+
+// maps skipped absolute paths located inside the input tree to paths as seen by the walker
+fn skipped_input_paths(input_path: &Path, skip_paths: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let input_root = input_path.canonicalize()?;
+
+    let skipped = skip_paths.iter()
+        .filter_map(|p| p.canonicalize().ok())
+        .filter_map(|p| match p.strip_prefix(&input_root) {
+            Ok(rel) if !rel.as_os_str().is_empty() => Some(input_path.join(rel)),
+            _ => None,
+        })
+        .collect();
+    Ok(skipped)
+}
+
+fn walk_input<'a>(input_root: &'a Path, opts: &'a WalkOptions) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> + 'a {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut visited_dirs = fnv::FnvHashSet::default();
+    let mut git_ignores = GitIgnores::default();
+
+    WalkDir::new(input_root)
+        .follow_links(opts.follow_links)
+        .same_file_system(opts.one_file_system)
+        .into_iter()
+        .filter_entry(move |e| {
+            if opts.excluded.iter().any(|p| p == e.path()) {
+                return false;
+            }
+            if e.file_type().is_dir() && e.depth() > 0
+                && opts.excluded_dir_names.iter().any(|n| OsStr::new(n) == e.file_name()) {
+                return false;
+            }
+            if e.file_type().is_dir() && e.depth() > 0 && opts.build_artifacts.as_ref()
+                .is_some_and(|extra_names| build_artifacts::is_artifact_dir(e.path(), extra_names)) {
+                return false;
+            }
+            if opts.gitignore && ((e.file_type().is_dir() && gitignore::is_object_store(e.path()))
+                || git_ignores.is_ignored(input_root, e.path(), e.file_type().is_dir())) {
+                return false;
+            }
+            if opts.unique_dirs && e.file_type().is_dir() {
+                if let Ok(meta) = e.metadata() {
+                    return visited_dirs.insert((meta.dev(), meta.ino()));
+                }
+            }
+            true
+        })
+}
+
+// the walk of scan, with the skip rules of the config and the options of the command line
+struct WalkSource<'a> {
+    input_root: &'a Path,
+    opts: WalkOptions,
+}
+
+impl InputSource for WalkSource<'_> {
+    fn entries(&self) -> Box<dyn Iterator<Item = Result<InputEntry, InputError>> + '_> {
+        Box::new(walk_input(self.input_root, &self.opts).map(|entry| entry.map(InputEntry::from).map_err(InputError::from)))
+    }
+}
+
+// values are only formatted when a record is actually logged, most entries never are
+fn get_entry_log(log: &Logger, item: &Path, i: usize, item_count: usize) -> Logger {
+    let item = item.to_owned();
+    log.new(o!(
+        "progress" => slog::FnValue(move |_| {
+            let percent = (((i + 1) as f64 / item_count as f64) * 100.0) as u32;
+            format!("{} % ({}/{})", percent, i + 1, item_count)
+        }),
+        "item" => slog::FnValue(move |_| item.display().to_string())
+    ))
+}
+
+pub fn run_backup(params: Params, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let walk_opts = WalkOptions{
+        excluded: prepare_output_root(&params, params.create_output)?.into_iter().collect(),
+        unique_dirs: params.unique_dirs,
+        one_file_system: params.one_file_system,
+        ..Default::default()
+    };
+
+    let b_proc = BackupProcessor::new(params, log)?;
+    let get_walker = || walk_input(b_proc.input_root(), &walk_opts);
+
+    let item_count = get_walker().filter_map(|e| e.ok()).count();
+    let mut file_errors = FileErrors::default();
+    let mut i = 0;
+
+    for entry in get_walker() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                file_errors.record(e.path().unwrap_or(b_proc.input_root()), &e, log);
+                continue;
+            }
+        };
+        let entry_log = get_entry_log(log, entry.path(), i, item_count);
+        i += 1;
+
+        if let Ok(entry_info) = fs::symlink_metadata(entry.path()) {
+            let result = if entry_info.is_dir() {
+                // println!("Visiting {}", entry.path().display());
+                b_proc.backup_dir(entry.path(), &entry_log)
+            } else if entry_info.file_type().is_symlink() {
+                b_proc.backup_symlink(entry.path(), &entry_log)
+            } else if entry_info.is_file() {
+                b_proc.backup_file(entry.path(), &entry_log)
+            } else {
+                Ok(())
+            };
+            if let Err(e) = result {
+                file_errors.record(entry.path(), &*e, &entry_log);
+            }
+        }
+    }
+
+    b_proc.finish()?;
+    info!(log, "Backed up {} items", item_count);
+    file_errors.into_result()
+}
+
+struct RestoreProcessor {
+    params: Params,
+    folded: Option<RefCell<FoldedPaths>>,
+}
+
+impl RestoreProcessor {
+    fn new(params: Params) -> Result<Self, Box<dyn Error>> {
+        let folded = case_folding(&params, Some(params.output_path.as_path()))?.map(RefCell::new);
+        Ok(Self{params, folded})
+    }
+
+    // copies and stubs would silently replace an entry restored before under a name differing only in case
+    fn claim(&self, dst: &Path) -> Result<(), Box<dyn Error>> {
+        match &self.folded {
+            Some(folded) if !folded.borrow_mut().insert(dst) => Err(case_collision(dst)),
+            _ => Ok(()),
+        }
+    }
+
+    fn input_root(&self) -> &Path {
+        &self.params.input_path
+    }
 
     fn output_root(&self) -> &Path {
         &self.params.output_path
     }
 
-    fn restore_item<F>(&self, src_path: &Path, creator: F) -> Result<(), Box<dyn Error>>
-        where F: Fn(&Path) -> Result<(), Box<dyn Error>> {
+    fn restore_item<F>(&self, src_path: &Path, creator: F) -> Result<(), Box<dyn Error>>
+        where F: Fn(&Path) -> Result<(), Box<dyn Error>> {
+
+        let src_rel_path = src_path.strip_prefix(self.input_root())?;
+        let dst_path = self.output_root().to_owned().join(src_rel_path);
+        creator(&dst_path)?;
+
+        Ok(())
+    }
+
+    fn restore_dir(&self, src_path: &Path, log: &Logger)  -> Result<(), Box<dyn Error>> {
+        self.restore_item(src_path, |dst| {
+            // println!("read dir: {}, write to: {}", src_path.display(), dst.display());
+            debug!(log, "{} -> {}", src_path.display(), dst.display());
+            self.claim(dst)?;
+            fs::create_dir_all(dst)?;
+            Ok(())
+        })
+    }
+
+    // the target to create for a link at dst, backups from untrusted sources may point anywhere
+    fn checked_target(&self, dst: &Path, link_target: &Path, log: &Logger) -> Result<PathBuf, Box<dyn Error>> {
+        let policy = self.params.link_targets;
+        if policy == LinkTargetPolicy::Allow {
+            return Ok(link_target.to_owned());
+        }
+        let link_dir = dst.parent()
+            .and_then(|parent| parent.strip_prefix(self.output_root()).ok())
+            .ok_or("could not extract parent path")?;
+        if target_in_root(link_dir, link_target, false).is_some() {
+            return Ok(link_target.to_owned());
+        }
+        match policy {
+            LinkTargetPolicy::Rewrite => {
+                let target = target_in_root(link_dir, link_target, true).unwrap_or_default();
+                let rewritten = match relative_path(link_dir, &target) {
+                    rel if rel.as_os_str().is_empty() => PathBuf::from("."),
+                    rel => rel,
+                };
+                warn!(log, "Rewriting link target {} to {}", link_target.display(), rewritten.display());
+                Ok(rewritten)
+            }
+            _ => Err(format!("refusing to restore link to {} outside the restore root", link_target.display()).into()),
+        }
+    }
+
+    fn restore_symlink(&self, src_path: &Path, log: &Logger)  -> Result<(), Box<dyn Error>> {
+        self.restore_item(src_path, |dst| {
+            if let Some(ext) = src_path.extension() {
+                if ext == OsStr::new("lns") {
+                    let info = LinkInfo::parse(&fs::read(src_path)?)
+                        .map_err(|e| format!("invalid link record: {}", e))?;
+
+                    let dst_file = match dst.file_stem() {
+                        Some(file_stem) => {
+                            let parent_path = dst.parent().ok_or("could not extract parent path")?;
+                            parent_path.join(file_stem)
+                        }
+                        None => dst.to_owned()
+                    };
+                    debug!(log, "{} -> {}", src_path.display(), dst_file.display());
+                    self.restore_link(&dst_file, &info, log)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn restore_link(&self, dst_file: &Path, info: &LinkInfo, log: &Logger) -> Result<(), Box<dyn Error>> {
+        if info.exists == Some(false) {
+            let resolved = info.resolved.as_deref().unwrap_or(&info.target);
+            if self.params.skip_broken_links {
+                info!(log, "Skipping link to {}, broken at backup time", resolved.display());
+                return Ok(());
+            }
+            warn!(log, "Restoring link to {}, broken at backup time", resolved.display());
+        }
+        let link_target = self.checked_target(dst_file, &info.target, log)?;
+        self.claim(dst_file)?;
+        unix_fs::symlink(link_target, dst_file)?;
+        Ok(())
+    }
+
+    // links of a backup --link-index, restored after the walk has created their directories
+    fn restore_link_index(&self, index_path: &Path, file_errors: &mut FileErrors, log: &Logger)
+        -> Result<usize, Box<dyn Error>> {
+
+        let index = IndexReader::open(index_path)
+            .map_err(|e| format!("could not read link index {}: {}", index_path.display(), e))?;
+        let mut link_count = 0;
+        for entry in index {
+            let (link, info) = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let e = format!("invalid link index {}: {}", index_path.display(), e);
+                    file_errors.record(index_path, &*Box::<dyn Error>::from(e), log);
+                    continue;
+                }
+            };
+            let dst_file = self.output_root().join(&link);
+            let entry_log = log.new(o!("item" => dst_file.display().to_string()));
+            debug!(entry_log, "{} -> {}", index_path.display(), dst_file.display());
+            // a link restored before must not redirect this one, the walk never follows links either
+            let result = match link.ancestors().skip(1)
+                .find(|dir| !dir.as_os_str().is_empty() && self.output_root().join(dir).is_symlink()) {
+                Some(dir) => Err(format!("link path leads through link {}", dir.display()).into()),
+                None => self.restore_link(&dst_file, &info, &entry_log),
+            };
+            if let Err(e) = result {
+                file_errors.record(&dst_file, &*e, &entry_log);
+            }
+            link_count += 1;
+        }
+        Ok(link_count)
+    }
+
+    // content of a backup --content
+    fn restore_file(&self, src_path: &Path, meta: &fs::Metadata, log: &Logger)  -> Result<(), Box<dyn Error>> {
+        self.restore_item(src_path, |dst| {
+            let dst_file = unescaped_file_name(dst);
+            self.claim(&dst_file)?;
+            match copy_if_changed(src_path, meta, &dst_file, None)? {
+                true => debug!(log, "{} -> {}", src_path.display(), dst_file.display()),
+                false => debug!(log, "{} unchanged", dst_file.display()),
+            }
+            Ok(())
+        })
+    }
+
+    // an empty file with the original mtime, the size and hash stay in the backup
+    fn restore_stub(&self, src_path: &Path, log: &Logger)  -> Result<(), Box<dyn Error>> {
+        self.restore_item(src_path, |dst| {
+            let stub = FileStub::parse(&fs::read(src_path)?)
+                .map_err(|e| format!("invalid file stub: {}", e))?;
+
+            let dst_file = dst.with_extension("");
+            debug!(log, "{} -> {}", src_path.display(), dst_file.display());
+            self.claim(&dst_file)?;
+            fs::File::create(&dst_file)?.set_modified(stub.mtime)?;
+            Ok(())
+        })
+    }
+}
+
+// where a link in link_dir points to, both relative to the restore root, resolved lexically;
+// None for absolute targets and targets leaving the root unless clamp keeps them inside
+fn target_in_root(link_dir: &Path, target: &Path, clamp: bool) -> Option<PathBuf> {
+    use std::path::Component;
+
+    let mut resolved: Vec<&OsStr> = match target.is_absolute() {
+        true if !clamp => return None,
+        true => Vec::new(),
+        false => link_dir.iter().collect(),
+    };
+    for component in target.components() {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::ParentDir => if resolved.pop().is_none() && !clamp {
+                return None;
+            },
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => (),
+        }
+    }
+    Some(resolved.iter().collect())
+}
+
+pub fn run_restore(params: Params, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let walk_opts = WalkOptions{
+        excluded: prepare_output_root(&params, params.create_output)?.into_iter().collect(),
+        unique_dirs: params.unique_dirs,
+        one_file_system: params.one_file_system,
+        ..Default::default()
+    };
+
+    let r_proc = RestoreProcessor::new(params)?;
+    let index_path = lns::index_path(r_proc.input_root());
+    let skipped = &index_path;
+    // the index is not part of the restored tree
+    let get_walker = || walk_input(r_proc.input_root(), &walk_opts)
+        .filter(move |e| e.as_ref().map_or(true, |e| e.path() != skipped));
+
+    let item_count = get_walker().filter_map(|e| e.ok()).count();
+    let mut file_errors = FileErrors::default();
+    let mut i = 0;
+
+    for entry in get_walker() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                file_errors.record(e.path().unwrap_or(r_proc.input_root()), &e, log);
+                continue;
+            }
+        };
+        let entry_log = get_entry_log(log, entry.path(), i, item_count);
+        i += 1;
+
+        if let Ok(entry_info) = fs::symlink_metadata(entry.path()) {
+            let result = if entry_info.is_dir() {
+                // println!("Visiting {}", entry.path().display());
+                r_proc.restore_dir(entry.path(), &entry_log)
+            } else if entry_info.is_file() && stubs::is_stub(entry.path()) {
+                r_proc.restore_stub(entry.path(), &entry_log)
+            } else if entry_info.is_file() && lns::is_link_record(entry.path()) {
+                r_proc.restore_symlink(entry.path(), &entry_log)
+            } else if entry_info.is_file() {
+                r_proc.restore_file(entry.path(), &entry_info, &entry_log)
+            } else {
+                Ok(())
+            };
+            if let Err(e) = result {
+                file_errors.record(entry.path(), &*e, &entry_log);
+            }
+        }
+    }
+
+    let mut link_count = 0;
+    if index_path.is_file() {
+        link_count = r_proc.restore_link_index(&index_path, &mut file_errors, log)?;
+    }
+
+    info!(log, "Restored {} items", item_count + link_count);
+    file_errors.into_result()
+}
+
+// result of the classification phase, records can be inspected, modified or filtered before linking
+pub struct ScanPlan {
+    pub records: Vec<Record>,
+    // records of a previous scan kept as they are in merge mode
+    merged: Vec<Record>,
+    file_errors: FileErrors,
+    metrics: Arc<ScanMetrics>,
+    router: Option<Router>,
+    audit: Option<AuditLog>,
+    // versions behind the classification, unknown for plans not classified by this run
+    header: Option<Header>,
+    max_dir_entries: Option<usize>,
+    sanitize_names: NameSanitization,
+    lowercase_extensions: bool,
+    encrypted_min_entropy: Option<f64>,
+    // directories of geotagged photos below their type directories, see Geocoding::layout
+    geo_layout: Option<String>,
+    // progress of the stages of scan --checkpoint
+    checkpoint: Option<Checkpoint>,
+}
+
+impl ScanPlan {
+    pub fn new(records: Vec<Record>) -> Self {
+        Self{records, merged: Vec::new(), file_errors: FileErrors::default(), metrics: Arc::new(ScanMetrics::default()),
+            router: None, audit: None, header: None, max_dir_entries: None, sanitize_names: NameSanitization::default(),
+            lowercase_extensions: false, encrypted_min_entropy: None, geo_layout: None, checkpoint: None}
+    }
+}
+
+// records of the existing manifest whose output entry is still there and their absolute input paths
+fn merged_records(output_root: &Path, log: &Logger) -> Result<(Vec<Record>, fnv::FnvHashSet<PathBuf>), Box<dyn Error>> {
+    let mut records = Vec::new();
+    let mut input_paths = fnv::FnvHashSet::default();
+    if !manifest::manifest_path(output_root).exists() {
+        return Ok((records, input_paths));
+    }
+
+    for record in manifest::read_manifest(output_root)? {
+        let linked = record.link.as_ref().is_some_and(|link| fs::symlink_metadata(output_root.join(link)).is_ok());
+        match absolute_entry_path(&record.path) {
+            Ok(path) if linked && !input_paths.contains(&path) => {
+                input_paths.insert(path);
+                records.push(record);
+            }
+            _ => debug!(log, "Dropping stale manifest record"; "item" => record.path.display().to_string()),
+        }
+    }
+    info!(log, "Merging into {} existing entries", records.len());
+    Ok((records, input_paths))
+}
+
+// an entry as handed from the walk stage to classification
+struct WalkedEntry {
+    entry: InputEntry,
+    meta: Option<fs::Metadata>,
+    sha256: Option<std::io::Result<String>>,
+}
+
+impl WalkedEntry {
+    // only regular files are hashed, reading a fifo or a device could block; buffered content comes with its hash
+    fn read(entry: InputEntry, hash_reader: Option<&mut FileReader>) -> Self {
+        if let Some(content) = entry.content() {
+            let sha256 = content.sha256.clone().map(Ok);
+            return Self{entry, meta: None, sha256};
+        }
+        let meta = entry.metadata().ok();
+        let sha256 = match (hash_reader, &meta) {
+            (Some(reader), Some(meta)) if meta.is_file() => Some(hashing::sha256_file(entry.path(), reader)),
+            _ => None,
+        };
+        Self{entry, meta, sha256}
+    }
+}
+
+// what is needed to explain classification differences between runs
+fn manifest_header(config: &Config) -> Header {
+    Header{
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        libmagic_version: libmagic::version(),
+        libmagic_db: config.libmagic_db_file.clone(),
+        libmagic_db_sha256: config.libmagic_db_file.as_deref()
+            .and_then(|db| fs::read(db).ok())
+            .map(|data| hashing::sha256_bytes(&data)),
+        mime_info_db: Some(config.mime_info_db_root.clone()),
+        // written by update-mime-database
+        shared_mime_info_version: fs::read_to_string(config.mime_info_db_root.join("version")).ok()
+            .map(|v| v.trim().to_owned()),
+    }
+}
+
+// starts a run in the audit log with the versions and databases behind the classification
+fn open_audit_log(path: &Path, header: &Header, params: &Params) -> Result<AuditLog, Box<dyn Error>> {
+    let mut audit = AuditLog::open(path)?;
+    audit.log(Event::Start{
+        command: std::env::args().collect::<Vec<_>>().join(" "),
+        input: params.input_path.clone(),
+        output: params.output_path.clone(),
+        versions: header.clone(),
+    })?;
+    Ok(audit)
+}
+
+// the classify-worker side of Params::isolate, answers the requests of the scan on stdin and stdout
+pub fn run_worker(config: Config, sandbox: bool, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let plugins = config.plugins_dir.as_deref().map(plugins::load_dir).transpose()?.unwrap_or_default();
+    let reader = FileReader::new(config.read_backend, config.sniff_buffer_size, config.read_buffer_size,
+        config.mmap_min_size, log)?;
+    let mut classifier = Classifier::new(config, plugins, reader);
+    // a worker only reads
+    if sandbox {
+        sandbox::enter(None, log)?;
+    }
+    worker::serve(|path| classifier.process_file(path, None, log))
+}
+
+// classifies the files of the input tree, of params.files_from or of the manifest at params.from_manifest, nothing
+// is written to the output tree yet
+pub fn classify(config: Config, params: &Params, log: &Logger) -> Result<ScanPlan, Box<dyn Error>> {
+    let source: Box<dyn InputSource + '_> = match (&params.files_from, &params.from_manifest) {
+        (Some(_), Some(_)) => return Err(Box::new(ClassifierError(ErrorKind::Config,
+            "--files-from and --from-manifest cannot be combined".to_owned()))),
+        (Some(list), None) => Box::new(FileList::from_file(list)?),
+        (None, Some(output_root)) => Box::new(FileList::from_manifest(output_root)?),
+        (None, None) => {
+            let mut excluded = skipped_input_paths(&params.input_path, &config.skip_paths)?;
+            excluded.extend(prepare_output_root(params, params.create_output || config.create_output)?);
+            excluded.extend(prepare_extra_outputs(params, params.create_output || config.create_output)?);
+            Box::new(WalkSource{input_root: &params.input_path, opts: WalkOptions{
+                excluded,
+                excluded_dir_names: config.skip_dir_names.clone(),
+                follow_links: params.follow_links,
+                unique_dirs: params.unique_dirs,
+                one_file_system: params.one_file_system,
+                gitignore: params.gitignore,
+                build_artifacts: match config.skip_build_artifacts {
+                    true => Some(config.artifact_dir_names.clone()),
+                    false => None,
+                },
+            }})
+        }
+    };
+    classify_from(config, params, &*source, log)
+}
+
+// as classify, with the files to classify coming from source, e.g. one of a library user
+pub fn classify_from(config: Config, params: &Params, source: &dyn InputSource, log: &Logger)
+    -> Result<ScanPlan, Box<dyn Error>> {
+
+    // done by classify already for the walk, which has to skip a nested output root; preparing again is a no-op
+    prepare_output_root(params, params.create_output || config.create_output)?;
+    prepare_extra_outputs(params, params.create_output || config.create_output)?;
+
+    let router = config.routing_script.as_deref().map(Router::load).transpose()?;
+    let max_dir_entries = config.max_dir_entries;
+    let sanitize_names = config.sanitize_names;
+    let lowercase_extensions = config.lowercase_extensions;
+    let encrypted_min_entropy = config.encrypted_min_entropy;
+    let measure_entropy = config.entropy || encrypted_min_entropy.is_some();
+    let sniff_buffer_size = config.sniff_buffer_size;
+    let detect_encrypted_archives = config.detect_encrypted_archives;
+    let group_split_archives = config.group_split_archives;
+    let companion_rules = config.companions.clone();
+    let photo_events = config.photo_events.clone();
+    let geocoding = config.geocoding.clone();
+    let geocoder = geocoding.as_ref().map(Geocoder::new).transpose()?;
+    let geo_layout = geocoding.as_ref().and_then(|geocoding| geocoding.layout.clone());
+    let wants_exif = |mime: &str| photo_events.as_ref().is_some_and(|events| events.wants(mime))
+        || geocoding.as_ref().is_some_and(|geocoding| geocoding.wants(mime));
+    let plugins = config.plugins_dir.as_deref().map(plugins::load_dir).transpose()?.unwrap_or_default();
+    let reader = FileReader::new(config.read_backend, config.sniff_buffer_size, config.read_buffer_size,
+        config.mmap_min_size, log)?;
+    // hashing runs in the walk stage with its own reader
+    let mut hash_reader = match params.content_hash {
+        true => Some(FileReader::new(config.read_backend, config.sniff_buffer_size, config.read_buffer_size,
+            config.mmap_min_size, log)?),
+        false => None,
+    };
+    let pipeline_depth = config.pipeline_depth;
+    let header = manifest_header(&config);
+    let mut audit = params.audit_log.as_deref().map(|path| open_audit_log(path, &header, params)).transpose()?;
+    let mut classifier = Classifier::new(config, plugins, reader);
+
+    if params.perceptual_hash && !cfg!(feature = "phash") {
+        return Err(Box::new(ClassifierError(ErrorKind::Config,
+            "perceptual hashing requires classifiles to be built with the phash feature".to_owned()
+        )));
+    }
+
+    if params.previews && !cfg!(feature = "previews") {
+        return Err(Box::new(ClassifierError(ErrorKind::Config,
+            "preview generation requires classifiles to be built with the previews feature".to_owned()
+        )));
+    }
+
+    let metrics = Arc::new(ScanMetrics::default());
+    if let Some(addr) = &params.metrics_addr {
+        metrics::serve(addr, metrics.clone())
+            .map_err(|e| format!("could not serve metrics on {}: {}", addr, e))?;
+        info!(log, "Serving metrics on http://{}/metrics", addr);
+    }
+
+    let (merged, merged_paths) = match params.merge {
+        true => merged_records(&params.output_path, log)?,
+        false => Default::default(),
+    };
+    let checkpoint = match params.checkpoint {
+        true => Some(Checkpoint::open(&params.output_path)?),
+        false => None,
+    };
+    let (resumed, mut classified_stage) = match &checkpoint {
+        Some(checkpoint) => checkpoint.resume(checkpoint::CLASSIFIED).map(|(records, stage)| (records, Some(stage)))?,
+        None => (Vec::new(), None),
+    };
+    if !resumed.is_empty() {
+        info!(log, "Resuming after {} files classified before", resumed.len());
+    }
+    let resumed_paths: fnv::FnvHashSet<PathBuf> = resumed.iter().map(|r| r.path.clone()).collect();
+    // a stuck detector can only be stopped in another process
+    let classify_timeout = classifier.config.classify_timeout;
+    let mut worker = match params.isolate || classify_timeout.is_some() {
+        true => {
+            let mut args = params.worker_args.clone();
+            if params.sandbox {
+                args.push("--sandbox".to_owned());
+            }
+            Some(Worker::new(args, classify_timeout))
+        }
+        false => None,
+    };
+    // everything read from outside the input tree or written outside the output root is open by now,
+    // workers still have to be started after a crash, so they get the seccomp filter instead
+    match (params.sandbox, &worker) {
+        (true, None) => sandbox::enter(Some(&params.output_path), log)?,
+        (true, Some(_)) => sandbox::restrict_writes(Some(&params.output_path), log)?,
+        (false, _) => (),
+    }
+    let input_root = params.input_path.canonicalize()?;
+    // not linked by a previous scan and not classified before an interruption
+    let is_pending = |path: &Path| {
+        let merged = !merged_paths.is_empty() && path.strip_prefix(&params.input_path)
+            .is_ok_and(|rel| merged_paths.contains(&input_root.join(rel)));
+        !merged && !resumed_paths.contains(path)
+    };
+
+    // an interrupted run is resumed over its walk list, files added to the input tree since are left to a later scan
+    let walk_list = match &checkpoint {
+        Some(checkpoint) => Some(match checkpoint.walk_list()? {
+            Some(paths) => paths,
+            None => {
+                let paths: Vec<PathBuf> = source.entries()
+                    .filter_map(|e| e.ok())
+                    .filter(is_scanned)
+                    .map(InputEntry::into_path)
+                    .collect();
+                checkpoint.write_walk_list(&paths)?;
+                paths
+            }
+        }),
+        None => None,
+    };
+    let file_count = match &walk_list {
+        // the walk list spares the counting walk
+        Some(paths) => Some(paths.iter().filter(|path| is_pending(path)).count()),
+        None if source.countable() => Some(source.entries()
+            .filter_map(|e| e.ok())
+            .filter(|e| is_scanned(e) && is_pending(e.path()))
+            .count()),
+        None => None,
+    };
+    let walk_list = walk_list.map(FileList::new);
+    let source: &dyn InputSource = match &walk_list {
+        Some(list) => list,
+        None => source,
+    };
+    let get_walker = || source.entries();
+
+    let mut records = resumed;
+    records.reserve(file_count.unwrap_or_default());
+    let mut types_seen = fnv::FnvHashSet::default();
+    let mut unknown_count = 0;
+    let mut file_errors = FileErrors::default();
+    let mut i = 0;
+    // classification of the first file with a given content hash
+    let mut type_cache: FnvHashMap<String, FileType> = FnvHashMap::default();
+    let mut owners = Owners::default();
+
+    // the walk (and hashing) runs ahead of classification by at most pipeline_depth entries
+    let (sender, receiver) = mpsc::sync_channel(pipeline_depth);
+    thread::scope(|scope| -> Result<(), Box<dyn Error>> {
+        let metrics = &metrics;
+        scope.spawn(move || {
+            for entry in get_walker() {
+                let walked = match entry {
+                    Ok(entry) if is_scanned(&entry) && is_pending(entry.path()) => Ok(WalkedEntry::read(entry, hash_reader.as_mut())),
+                    Ok(_) => continue,
+                    Err(e) => Err(e),
+                };
+                metrics.entry_queued();
+                // classification stopped early (audit log failure or panic)
+                if sender.send(walked).is_err() {
+                    break;
+                }
+            }
+        });
+
+        for walked in receiver {
+            metrics.entry_dequeued();
+            let WalkedEntry{entry, meta, sha256} = match walked {
+                Ok(walked) => walked,
+                Err(e) if e.symlink_loop => {
+                    debug!(log, "Skipping symlink loop: {}", e);
+                    continue;
+                }
+                Err(e) => {
+                    metrics.error();
+                    file_errors.record(e.path.as_deref().unwrap_or(&params.input_path), &e, log);
+                    continue;
+                }
+            };
+            let entry_log = match file_count {
+                Some(file_count) => {
+                    metrics.set_queue_depth(file_count.saturating_sub(i) as u64);
+                    get_entry_log(log, entry.path(), i, file_count)
+                }
+                None => log.new(o!("item" => entry.path().display().to_string())),
+            }.new(o!("queued" => metrics.pipeline_queued()));
+            i += 1;
+
+            let buffered = entry.content();
+            let content = match buffered {
+                Some(buffered) => Content::Buffer(entry.path(), &buffered.leading),
+                None => Content::File(entry.path()),
+            };
+            let (size, mtime, mode, owner, group) = match (buffered, &meta) {
+                (Some(buffered), _) => (Some(buffered.size), buffered.mtime, buffered.mode, buffered.owner.clone(),
+                    buffered.group.clone()),
+                (None, Some(meta)) => {
+                    use std::os::unix::fs::MetadataExt;
+                    let (owner, group) = owners.of(meta);
+                    (Some(meta.len()), Some(meta.mtime()), Some(meta.mode() & 0o7777), Some(owner), Some(group))
+                }
+                (None, None) => (None, None, None, None, None),
+            };
+            let sha256 = match sha256 {
+                Some(Ok(hash)) => Some(hash),
+                Some(Err(e)) => {
+                    warn!(entry_log, "Could not hash file: {}", e);
+                    None
+                }
+                None => None,
+            };
+
+            let cached_type = sha256.as_ref().and_then(|hash| type_cache.get(hash));
+            // special files are never opened and buffered content is already in memory, so they need no worker
+            let in_process = buffered.is_some() || meta.as_ref().is_some_and(|meta| special_file_type(meta).is_some());
+            let (file_type, failure) = match (cached_type, &mut worker) {
+                (Some(file_type), _) => {
+                    debug!(entry_log, "Duplicate content, reusing {}", file_type.mime.as_deref().unwrap_or(OUTPUT_UNKNOWN));
+                    (file_type.clone(), None)
+                }
+                (None, Some(worker)) if !in_process => match worker.classify(entry.path(), &entry_log)
+                    .map_err(|e| format!("could not start classify worker: {}", e))? {
+
+                    Outcome::Classified(file_type) => (file_type, None),
+                    Outcome::Crashed => (FileType::unknown(), Some(FAILURE_CRASHED.to_owned())),
+                    Outcome::TimedOut => (FileType::unknown(), Some(FAILURE_TIMEOUT.to_owned())),
+                },
+                (None, _) => match buffered {
+                    Some(buffered) => (classifier.process_buffer(entry.path(), &buffered.leading, &entry_log), None),
+                    None => (classifier.process_file(entry.path(), meta.as_ref(), &entry_log), None),
+                },
+            };
+            // copies of a file that crashed or stalled the detectors are tried (and recorded) again
+            if let (Some(hash), None) = (&sha256, &failure) {
+                type_cache.entry(hash.clone()).or_insert_with(|| file_type.clone());
+            }
+            metrics.file_classified(file_type.mime.as_deref().unwrap_or(OUTPUT_UNKNOWN), size.unwrap_or(0));
+            match &file_type.mime {
+                Some(mime) => { types_seen.insert(mime.clone()); },
+                None => unknown_count += 1,
+            }
+
+            let phash = if params.perceptual_hash && buffered.is_none() {
+                image_hash(entry.path(), &file_type, &entry_log)
+            } else {
+                None
+            };
+
+            // special files are never opened, see process_file
+            let is_file = buffered.is_some() || meta.as_ref().is_some_and(fs::Metadata::is_file);
+            // over the same leading bytes for buffered content as for files
+            let entropy = match (measure_entropy && is_file, buffered) {
+                (true, Some(buffered)) => {
+                    entropy::shannon(&buffered.leading[..buffered.leading.len().min(sniff_buffer_size)])
+                }
+                (true, None) => {
+                    classifier.reader.borrow_mut().read_leading(entry.path()).ok().and_then(entropy::shannon)
+                }
+                (false, _) => None,
+            };
+            let encrypted_archive = detect_encrypted_archives && is_file
+                && file_type.mime.as_deref().is_some_and(encrypted::applies_to)
+                && content.is_encrypted_archive(&entry_log);
+            let exif = match &file_type.mime {
+                Some(mime) if is_file && wants_exif(mime) => content.exif(&entry_log),
+                _ => None,
+            }.unwrap_or_default();
+            let place = exif.gps.and_then(|gps| Some(geocoder.as_ref()?.lookup(gps)));
+
+            let mut record = Record{
+                path: entry.path().to_owned(),
+                mime: file_type.mime,
+                ext: file_type.ext,
+                alt_exts: file_type.alt_exts,
+                link: None,
+                size,
+                mtime,
+                owner,
+                group,
+                mode,
+                phash,
+                preview: None,
+                uti: None,
+                sha256,
+                tags: Vec::new(),
+                failure,
+                clamav: None,
+                entropy,
+                split_set: None,
+                split_part: None,
+                companion_of: None,
+                taken: exif.taken,
+                event: None,
+                gps: exif.gps,
+                gps_country: place.as_ref().map(|place| place.country.clone()),
+                gps_region: place.map(|place| place.region),
+            };
+            if encrypted_archive {
+                tags::add(&mut record, encrypted::ENCRYPTED_TAG);
+            }
+            if let (Some(clamav), true, None) = (&classifier.config.clamav, is_file, buffered) {
+                check_clamav(clamav, &mut record, &mut file_errors, audit.as_mut(), &entry_log)?;
+            }
+            tags::apply(&classifier.config.tag_rules, &mut record);
+            hooks::run(&classifier.config.hooks, &record, &entry_log);
+            if let Some(audit) = &mut audit {
+                audit.log(Event::Classify{path: record.path.clone(), mime: record.mime.clone(), ext: record.ext.clone(),
+                    sha256: record.sha256.clone()})?;
+            }
+            if let Some(stage) = &mut classified_stage {
+                stage.append(&record)?;
+            }
+            records.push(record);
+        }
+        Ok(())
+    })?;
+
+    metrics.set_queue_depth(0);
+    info!(log, "Classified {} files into {} types, {} unknown", i, types_seen.len(), unknown_count);
+    if group_split_archives {
+        split::mark(&mut records, log);
+    }
+    if !companion_rules.is_empty() {
+        companions::mark(&companion_rules, &mut records, log);
+    }
+    if let Some(photo_events) = &photo_events {
+        events::mark(photo_events, &mut records, log);
+    }
+
+    Ok(ScanPlan{records, merged, file_errors, metrics, router, audit, header: Some(header), max_dir_entries,
+        sanitize_names, lowercase_extensions, encrypted_min_entropy, geo_layout, checkpoint})
+}
+
+// records the verdict of clamd, a detected file is quarantined before any hook gets its path; clamd failures are
+// file errors
+fn check_clamav(clamav: &Clamav, record: &mut Record, file_errors: &mut FileErrors, audit: Option<&mut AuditLog>,
+    log: &Logger) -> Result<(), Box<dyn Error>> {
+
+    if !clamav.wants(record.mime.as_deref().unwrap_or(OUTPUT_UNKNOWN)) {
+        return Ok(());
+    }
+    let verdict = match clamav::scan(clamav, &record.path) {
+        Ok(verdict) => verdict,
+        Err(e) => {
+            file_errors.record(&record.path, &*e, log);
+            return Ok(());
+        }
+    };
+    if verdict == clamav::CLEAN {
+        record.clamav = Some(verdict);
+        return Ok(());
+    }
+
+    warn!(log, "clamd found {}", verdict);
+    if let Some(root) = &clamav.quarantine {
+        match clamav::quarantine(root, &record.path) {
+            Ok(quarantined) => {
+                info!(log, "Quarantined as {}", quarantined.display());
+                if let Some(audit) = audit {
+                    audit.log(Event::Quarantine{path: record.path.clone(), quarantine: quarantined.clone(),
+                        signature: verdict.clone()})?;
+                }
+                record.path = quarantined;
+            }
+            Err(e) => {
+                let e: Box<dyn Error> = format!("could not quarantine: {}", e).into();
+                file_errors.record(&record.path, &*e, log);
+            }
+        }
+    }
+    record.clamav = Some(verdict);
+    Ok(())
+}
+
+// options of scan that need the input files on disk
+fn check_archive_params(params: &Params) -> Result<(), Box<dyn Error>> {
+    let conflicts = [
+        ("--files-from", params.files_from.is_some()),
+        ("--from-manifest", params.from_manifest.is_some()),
+        ("--merge", params.merge),
+        ("--checkpoint", params.checkpoint),
+        ("--interactive", params.interactive),
+        ("--isolate", params.isolate),
+        ("--sandbox", params.sandbox),
+        ("--phash", params.perceptual_hash),
+        ("--previews", params.previews),
+        ("--output-archive", params.output_archive.is_some()),
+    ];
+    match conflicts.iter().find(|(_, set)| *set) {
+        Some((option, _)) => Err(Box::new(ClassifierError(ErrorKind::Config,
+            format!("--input-archive cannot be used with {}", option)))),
+        None => Ok(()),
+    }
+}
+
+// output directory and name of a record
+type Location = (PathBuf, PathBuf);
+
+// creates the output entries and the manifest for a plan, records that already carry a link
+// (e.g. from a previous manifest) are placed there, the others get laid out by their type
+pub fn materialize(plan: ScanPlan, params: &Params, log: &Logger) -> Result<(), Box<dyn Error>> {
+    materialize_with_sink(plan, params, &mut *sinks::for_params(params)?, log)
+}
+
+// as materialize, with the output entries created by sink instead of the one of params.link_mode
+pub fn materialize_with_sink(plan: ScanPlan, params: &Params, sink: &mut dyn OutputSink, log: &Logger)
+    -> Result<(), Box<dyn Error>> {
 
-        let src_rel_path = src_path.strip_prefix(self.input_root())?;
-        let dst_path = self.output_root().to_owned().join(src_rel_path);
-        creator(&dst_path)?;
+    let ScanPlan{records, merged, mut file_errors, metrics, router, mut audit, header, max_dir_entries, sanitize_names,
+        lowercase_extensions, encrypted_min_entropy, geo_layout, checkpoint} = plan;
 
-        Ok(())
+    prepare_output_root(params, params.create_output)?;
+    prepare_extra_outputs(params, params.create_output)?;
+    if !params.skip_capacity_check {
+        capacity::check(&records, params, log)?;
     }
 
-    fn restore_dir(&self, src_path: &Path, log: &Logger)  -> Result<(), Box<dyn Error>> {
-        self.restore_item(src_path, |dst| {
-            // println!("read dir: {}, write to: {}", src_path.display(), dst.display());
-            info!(log, "{} -> {}", src_path.display(), dst.display());
-            fs::create_dir_all(dst)?;
-            Ok(())
-        })
+    let mut manifest = ManifestWriter::create(&params.output_path, header.as_ref())?;
+    let mut checksums = Vec::new();
+    let mut snapshot = history::Snapshot::new(&params.input_path);
+    for record in &merged {
+        manifest.write(record)?;
+        snapshot.add(record);
+        checksums.extend(checksums::entry(record));
     }
+    // records linked before an interruption are taken over as they are
+    let (mut linked_before, mut linked_stage) = match &checkpoint {
+        Some(checkpoint) => {
+            let (records, stage) = checkpoint.resume(checkpoint::LINKED)?;
+            (records.into_iter().map(|r| (r.path.clone(), r)).collect(), Some(stage))
+        }
+        None => (FnvHashMap::default(), None),
+    };
+    if !linked_before.is_empty() {
+        info!(log, "Resuming after {} files linked before", linked_before.len());
+    }
+    let mut output_roots = OutputRoots::new(params)?;
+    let mut folded = case_folding(params, output_roots.roots.iter().map(PathBuf::as_path))?;
+    let output_rel = |p: &Path| p.strip_prefix(&params.output_path).unwrap_or(p).to_owned();
+
+    let record_count = records.len();
+    let mut linked = 0;
+    let input_root = if params.flat { None } else { Some(params.input_path.as_path()) };
+
+    let split_types = split::layout_types(&records);
+    let primaries = companions::primaries(&records, &merged);
+    // the type of a split archive or encrypted/ stands in for the type directory, mirrored input directories stay
+    // below it
+    let layout_mime = |record: &Record| {
+        let split_type = record.split_set.as_deref()
+            .and_then(|set| split_types.get(&(record.path.parent()?, set)));
+        match (split_type, encrypted_min_entropy) {
+            (Some(mime), _) => mime.map(str::to_owned),
+            (None, Some(min)) if entropy::likely_encrypted(record.mime.as_deref(), record.entropy, min) => {
+                Some(entropy::OUTPUT_ENCRYPTED.to_owned())
+            }
+            _ => record.mime.clone(),
+        }
+    };
+    // new output locations are laid out before linking, so that crowded directories can be sharded
+    let mut locations: Vec<Option<Result<Location, Box<dyn Error>>>> = records.iter()
+        .map(|record| match &record.link {
+            Some(_) => None,
+            None => Some(router.as_ref().map_or(Ok(None), |r| r.route(record)).map(|route| {
+                // companions go where their primary file goes
+                let primary = record.companion_of.as_deref().and_then(|path| primaries.get(path)).copied();
+                let layout_record = primary.unwrap_or(record);
+                let mime = layout_mime(layout_record);
+                let file_type = FileType{mime, ext: record.ext.clone(), alt_exts: Vec::new()};
+                let place = geo_layout.as_deref().and_then(|layout| geocoding::layout_dir(layout, layout_record));
+                let photo_dir: PathBuf = place.iter().chain(&layout_record.event).collect();
+                let photo_dir = Some(photo_dir.as_path()).filter(|dir| !dir.as_os_str().is_empty());
+                output_location(&record.path, input_root, &file_type, photo_dir, route, sanitize_names,
+                    lowercase_extensions)
+            })),
+        })
+        .collect();
+    let spread = match params.output_split {
+        OutputSplit::RoundRobin => 1 + params.extra_outputs.len(),
+        OutputSplit::MimeGroup => 1,
+    };
+    let new_dirs = locations.iter().filter_map(|l| l.as_ref()?.as_ref().ok()).map(|(dir, _)| dir.as_path());
+    let sharding = Sharding::new(max_dir_entries, new_dirs, &params.output_path, spread);
 
-    fn restore_symlink(&self, src_path: &Path, log: &Logger)  -> Result<(), Box<dyn Error>> {
-        self.restore_item(src_path, |dst| {
-            if let Some(ext) = src_path.extension() {
-                if ext == OsStr::new("lns") {
-                    let src_bytes = fs::read(src_path)?;
-                    let link_bytes = if src_bytes[src_bytes.len() - 1] == b'\n' {
-                        &src_bytes[0..src_bytes.len()-1]
-                    } else {
-                        &src_bytes[..]
-                    };
-                    let link_target = Path::new(OsStr::from_bytes(link_bytes));
-
-                    let dst_file = match dst.file_stem() {
-                        Some(file_stem) => {
-                            let parent_path = dst.parent().ok_or("could not extract parent path")?;
-                            parent_path.join(file_stem)
-                        }
-                        None => dst.to_owned()
-                    };
-                    info!(log, "{} -> {}", src_path.display(), dst_file.display());
-                    unix_fs::symlink(link_target, dst_file)?;
+    for (i, mut record) in records.into_iter().enumerate() {
+        if let Some(done) = linked_before.remove(&record.path) {
+            if done.link.is_some() {
+                linked += 1;
+            }
+            manifest.write(&done)?;
+            snapshot.add(&done);
+            checksums.extend(checksums::entry(&done));
+            continue;
+        }
+        let entry_log = get_entry_log(log, &record.path, i, record_count);
+        let file_type = FileType{mime: record.mime.clone(), ext: record.ext.clone(), alt_exts: Vec::new()};
+
+        let result = match &record.link {
+            Some(link_rel) => {
+                let link = params.output_path.join(link_rel);
+                sink.put(&record.path, &link, &entry_log)
+                    .map(|_| link)
+                    .map_err(|e| -> Box<dyn Error> { e.into() })
+            }
+            None => locations[i].take().expect("location of a record without link").and_then(|(dir, name)| {
+                let output_link_dir = clamped_dir(output_roots.pick(&file_type), &sharding.shard(&dir, &name), &name);
+                link_to_output(&record.path, &output_link_dir, name, &file_type, sink, folded.as_mut(), &entry_log)
+            }),
+        };
+
+        match result {
+            Ok(link) => {
+                debug!(entry_log, "{} -> {}", record.path.display(), link.display());
+                if params.previews {
+                    record.preview = make_preview(&record.path, output_roots.containing(&link), &link, &file_type, &entry_log)
+                        .as_deref()
+                        .map(output_rel);
+                }
+                record.link = Some(output_rel(&link));
+                linked += 1;
+                if let Some(audit) = &mut audit {
+                    audit.log(Event::Link{path: record.path.clone(), link: output_rel(&link), mode: sink.name().to_owned()})?;
                 }
             }
-            Ok(())
-        })
+            Err(e) => {
+                metrics.error();
+                file_errors.record(&record.path, &*e, &entry_log);
+                record.link = None;
+            }
+        }
+        if params.uti {
+            record.uti = record.mime.as_deref().and_then(uti::from_mime).map(str::to_owned);
+        }
+        manifest.write(&record)?;
+        snapshot.add(&record);
+        if let Some(stage) = &mut linked_stage {
+            stage.append(&record)?;
+        }
+        checksums.extend(checksums::entry(&record));
+    }
+
+    sink.finish().map_err(|e| format!("could not finish the {} output: {}", sink.name(), e))?;
+    manifest.finish()?;
+    history::append(&params.output_path, &snapshot)?;
+    if let Some(format) = params.checksums {
+        checksums::write(&checksums::checksums_path(&params.output_path), &checksums, format)?;
+    }
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.remove()?;
     }
+    info!(log, "Linked {} of {} files into {}", linked, record_count, params.output_path.display());
+    file_errors.into_result()
 }
 
-pub fn run_restore(params: Params, log: &Logger) -> Result<(), Box<dyn Error>> {
-    if !params.output_path.is_dir() {
-        return Err(Box::new(ClassifierError(
-            format!("{} is not a directory", params.output_path.display())
-        )));
+// exact type, a prefix ending with * or a structured syntax suffix (*/*+zip), see MimeType::matches,
+// files without a detected type match as unknown
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    match mime.parse::<MimeType>() {
+        Ok(mime_type) => mime_type.matches(pattern),
+        Err(_) => match pattern.strip_suffix('*') {
+            Some(prefix) => mime.starts_with(prefix),
+            None => pattern == mime,
+        },
     }
+}
 
-    let r_proc = RestoreProcessor::new(params);
-    let get_walker = || WalkDir::new(r_proc.input_root()).into_iter().filter_map(|e| e.ok());
+impl ScanPlan {
+    // drops records not selected by --only-mime and --skip-mime, they stay classified but get no output entry
+    fn filter_mime(&mut self, only_mime: &[String], skip_mime: &[String], log: &Logger) {
+        if only_mime.is_empty() && skip_mime.is_empty() {
+            return;
+        }
 
-    let item_count = get_walker().count();
-    let walker = get_walker();
+        let record_count = self.records.len();
+        self.records.retain(|record| {
+            let mime = record.mime.as_deref().unwrap_or(OUTPUT_UNKNOWN);
+            (only_mime.is_empty() || only_mime.iter().any(|p| mime_matches(p, mime)))
+                && !skip_mime.iter().any(|p| mime_matches(p, mime))
+        });
+        info!(log, "Selected {} of {} files by type", self.records.len(), record_count);
+    }
+}
 
-    for (i, entry) in walker.enumerate() {
-        let entry_log = get_entry_log(log, entry.path(), i, item_count);
+// rules files the interactive decisions are merged into
+fn review_plan(plan: &mut ScanPlan, libmagic_db_file: Option<&Path>, save_to: &[&Path], log: &Logger) -> Result<(), Box<dyn Error>> {
+    let cookie_desc_opt = get_magic_cookie_opt(libmagic_db_file, Query::Description);
+    let describe = |path: &Path| cookie_desc_opt.as_ref().and_then(|cookie| cookie.file(path).ok());
 
-        if let Ok(entry_info) = fs::symlink_metadata(entry.path()) {
-            if entry_info.is_dir() {
-                // println!("Visiting {}", entry.path().display());
-                r_proc.restore_dir(entry.path(), &entry_log)?;
-            } else if entry_info.is_file() {
-                r_proc.restore_symlink(entry.path(), &entry_log)?;
-            }
+    let stdin = std::io::stdin();
+    let rules = review::review(&mut plan.records, &describe, &mut stdin.lock(), &mut std::io::stderr())?;
+
+    if !rules.is_empty() {
+        for rules_path in save_to {
+            overrides::save_rules(rules_path, rules.clone())?;
+            info!(log, "Saved override rules to {}", rules_path.display());
         }
     }
-
     Ok(())
 }
 
-pub fn run_scan(config: Config, params: Params, log: &Logger) -> Result<(), Box<dyn Error>> {
-    let mut classifier = Classifier::new(config);
+fn learn_db(config: &Config) -> Result<&Path, Box<dyn Error>> {
+    config.overrides_db.as_deref().ok_or_else(|| Box::new(ClassifierError(ErrorKind::Config,
+        "no override db configured and no home directory to put the default one in".to_owned()
+    )) as Box<dyn Error>)
+}
 
-    if !params.output_path.is_dir() {
-        return Err(Box::new(ClassifierError(
-            format!("{} is not a directory", params.output_path.display())
-        )));
+// runs a scan or sync and sends the configured notifications with its outcome, the totals are those of the snapshot
+// the run added to the history (none if it failed before writing the manifest)
+fn notify_after(verb: &str, notify: &Notify, params: &Params, log: &Logger,
+    run: impl FnOnce() -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+
+    if notify.is_empty() {
+        return run();
     }
+    let start = SystemTime::now();
+    let started = audit::utc_timestamp(start);
+    let result = run();
+    let snapshot = history::read(&params.output_path).ok()
+        .and_then(|mut snapshots| snapshots.pop())
+        .filter(|snapshot| snapshot.time >= started);
+    let stats = notify::stats(verb, &params.input_path, &params.output_path, start.elapsed().unwrap_or_default(),
+        snapshot.as_ref(), &result);
+    notify::send(notify, &params.output_path, &stats, log);
+    result
+}
+
+pub fn run_scan(config: Config, params: Params, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let sink = sinks::for_params(&params)?;
+    run_scan_with_sink(config, params, sink, log)
+}
 
-    let get_walker = || WalkDir::new(&params.input_path).into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file());
+// as run_scan, with the output entries created by sink, e.g. one of a library user
+pub fn run_scan_with_sink(config: Config, params: Params, mut sink: Box<dyn OutputSink>, log: &Logger)
+    -> Result<(), Box<dyn Error>> {
+
+    let params = absolute_input(params)?;
+    let notify = config.notify.clone();
+    notify_after("scan", &notify, &params, log, || scan(config, &params, &mut *sink, log))
+}
+
+// the input paths recorded in the manifest start with the input path, as an absolute one they stay valid for sync,
+// mount and whereis run from another directory
+fn absolute_input(mut params: Params) -> Result<Params, Box<dyn Error>> {
+    params.input_path = params.input_path.canonicalize()
+        .map_err(|e| format!("could not open input {}: {}", params.input_path.display(), e))?;
+    Ok(params)
+}
 
-    let file_count = get_walker().count();
-    let walker = get_walker();
+fn scan(config: Config, params: &Params, sink: &mut dyn OutputSink, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let libmagic_db_file = config.libmagic_db_file.clone();
+    let policy = config.policy.clone();
+    let mut save_to: Vec<PathBuf> = params.save_overrides.iter().cloned().collect();
+    if params.checksums.is_some() && !params.content_hash {
+        return Err(Box::new(ClassifierError(ErrorKind::Config, "--checksums requires --hash".to_owned())));
+    }
+    if params.sandbox && params.interactive {
+        return Err(Box::new(ClassifierError(ErrorKind::Config,
+            "--sandbox cannot be used with --interactive, the decisions are saved outside the output root".to_owned())));
+    }
+    if params.sandbox && !params.extra_outputs.is_empty() {
+        return Err(Box::new(ClassifierError(ErrorKind::Config,
+            "--sandbox cannot be used with --extra-output, writes are only allowed below the output root".to_owned())));
+    }
+    if let (true, Some(log_file)) = (params.sandbox, &params.log_file) {
+        if !absolute_path(log_file)?.starts_with(absolute_path(&params.output_path)?) {
+            return Err(Box::new(ClassifierError(ErrorKind::Config, "--sandbox cannot be used with --log-file outside \
+                the output root, rotating it is denied".to_owned())));
+        }
+    }
+    if params.output_archive.is_some() && !params.extra_outputs.is_empty() {
+        return Err(Box::new(ClassifierError(ErrorKind::Config,
+            "--output-archive cannot be used with --extra-output, the archive holds the output root".to_owned())));
+    }
+    if params.sandbox && !config.hooks.is_empty() {
+        return Err(Box::new(ClassifierError(ErrorKind::Config,
+            "--sandbox cannot be used with hooks, running programs is denied".to_owned())));
+    }
+    if let Some(clamav) = &config.clamav {
+        if params.sandbox {
+            return Err(Box::new(ClassifierError(ErrorKind::Config,
+                "--sandbox cannot be used with clamav, connecting to clamd is denied".to_owned())));
+        }
+        if params.input_archive {
+            warn!(log, "clamav does not apply to archive entries");
+        } else {
+            clamav::check(clamav)?;
+        }
+    }
+    if !config.notify.is_empty() {
+        if params.sandbox {
+            return Err(Box::new(ClassifierError(ErrorKind::Config,
+                "--sandbox cannot be used with notify, running programs and network access are denied".to_owned())));
+        }
+        notify::check(&config.notify)?;
+    }
+    if let Some(url) = &params.upload {
+        if params.sandbox {
+            return Err(Box::new(ClassifierError(ErrorKind::Config,
+                "--sandbox cannot be used with --upload, network access is denied".to_owned())));
+        }
+        upload::check_url(url)?;
+    }
+    if params.learn {
+        if !params.interactive {
+            return Err(Box::new(ClassifierError(ErrorKind::Config, "--learn requires --interactive".to_owned())));
+        }
+        save_to.push(learn_db(&config)?.to_owned());
+    }
 
-    for (i, entry) in walker.enumerate() {
-        let entry_log = get_entry_log(log, entry.path(), i, file_count);
+    // the entries of an archive have no path on disk to link to, they are laid out in the manifest only
+    let mut manifest_only = ManifestOnlySink::default();
+    let (mut plan, sink): (_, &mut dyn OutputSink) = match params.input_archive {
+        true => {
+            check_archive_params(params)?;
+            if config.classify_timeout.is_some() {
+                warn!(log, "classify_timeout does not apply to archive entries");
+            }
+            let source = ArchiveSource::open(&params.input_path, params.content_hash)?;
+            (classify_from(config, params, &source, log)?, &mut manifest_only)
+        }
+        false => (classify(config, params, log)?, sink),
+    };
 
-        let file_type = classifier.process_file(entry.path(), &entry_log);
-        link_to_output(entry.path(), &params.input_path, &params.output_path, &file_type)?;
+    if params.interactive {
+        let save_to: Vec<&Path> = save_to.iter().map(PathBuf::as_path).collect();
+        review_plan(&mut plan, libmagic_db_file.as_deref(), &save_to, log)?;
+    }
+    // over all files of the output tree, including those --only-mime and --skip-mime leave out
+    let policy_result = check_policy(&policy, &plan, log);
+    plan.filter_mime(&params.only_mime, &params.skip_mime, log);
+    materialize_with_sink(plan, params, sink, log)?;
+    if let Some(url) = &params.upload {
+        upload_state(&params.output_path, url, log)?;
     }
+    policy_result?;
 
     // let mime = mime_info_db.get("application/zip");
     // println!("{:?}", mime);
@@ -460,3 +2866,626 @@ pub fn run_scan(config: Config, params: Params, log: &Logger) -> Result<(), Box<
 
     Ok(())
 }
+
+// the output is created regardless, the error is returned once it is complete
+fn check_policy(policy: &[PolicyRule], plan: &ScanPlan, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let violations = policy::check(policy, plan.records.iter().chain(&plan.merged));
+    if violations.is_empty() {
+        return Ok(());
+    }
+    for violation in &violations {
+        warn!(log, "Policy {} violated by {} files", violation.rule, violation.records.len());
+    }
+    Err(Box::new(ClassifierError(ErrorKind::Policy, policy::summary(&violations))))
+}
+
+// the files describing the scan, the output tree itself only makes sense on the scanned machine
+fn upload_state(output_root: &Path, url: &str, log: &Logger) -> Result<(), Box<dyn Error>> {
+    for path in &[manifest::manifest_path(output_root), checksums::checksums_path(output_root)] {
+        if path.is_file() {
+            let name = path.file_name().expect("state file name").to_string_lossy();
+            upload::upload(url, &name, fs::read(path)?, log)?;
+        }
+    }
+    Ok(())
+}
+
+// removes now empty directories between a removed entry and the output root
+fn remove_empty_parents(entry: &Path, output_root: &Path) {
+    // an entry of an extra output root that is no longer given has nothing to stop at
+    for dir in entry.ancestors().skip(1).take_while(|dir| *dir != output_root && dir.starts_with(output_root)) {
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+// drops the records of input files that no longer exist and removes their output symlinks (and previews),
+// copies and hardlinks may be the last copy of the data and are kept
+fn prune_output(output_roots: &OutputRoots, mut audit: Option<&mut AuditLog>, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let output_root = output_roots.output_root();
+    if !manifest::manifest_path(output_root).exists() {
+        return Ok(());
+    }
+
+    let (header, records) = manifest::read_manifest_file_with_header(&manifest::manifest_path(output_root))?;
+    let record_count = records.len();
+    let mut kept = Vec::with_capacity(record_count);
+    let mut removed = 0;
+
+    for record in records {
+        if fs::symlink_metadata(&record.path).is_ok() {
+            kept.push(record);
+            continue;
+        }
+        let entry_log = log.new(o!("item" => record.path.display().to_string()));
+        let link_rel = match &record.link {
+            Some(link_rel) => link_rel.clone(),
+            None => continue,
+        };
+        let link = output_root.join(&link_rel);
+        match fs::symlink_metadata(&link) {
+            Ok(meta) if meta.file_type().is_symlink() => match fs::remove_file(&link) {
+                Ok(()) => {
+                    debug!(entry_log, "Input file is gone, removed {}", link.display());
+                    if let Some(audit) = audit.as_deref_mut() {
+                        audit.log(Event::Remove{path: record.path.clone(), link: link_rel})?;
+                    }
+                    remove_empty_parents(&link, output_roots.containing(&link));
+                    if let Some(preview) = record.preview.as_ref().map(|p| output_root.join(p)) {
+                        if fs::remove_file(&preview).is_ok() {
+                            remove_empty_parents(&preview, output_roots.containing(&preview));
+                        }
+                    }
+                    removed += 1;
+                }
+                Err(e) => {
+                    warn!(entry_log, "Could not remove {}: {}", link.display(), e);
+                    kept.push(record);
+                }
+            },
+            Ok(_) => {
+                warn!(entry_log, "Input file is gone, keeping its output copy {}", link.display());
+                kept.push(record);
+            }
+            // the output entry is gone as well
+            Err(_) => (),
+        }
+    }
+
+    let mut manifest = ManifestWriter::create(output_root, header.as_ref())?;
+    for record in &kept {
+        manifest.write(record)?;
+    }
+    manifest.finish()?;
+    info!(log, "Removed {} of {} output entries whose input files are gone", removed, record_count);
+    Ok(())
+}
+
+// brings an existing output tree up to date: entries of deleted input files are removed, new files are added
+// like with scan --merge
+pub fn run_sync(config: Config, params: Params, log: &Logger) -> Result<(), Box<dyn Error>> {
+    if params.input_archive {
+        return Err(Box::new(ClassifierError(ErrorKind::Config, "--input-archive is only supported by scan".to_owned())));
+    }
+    if params.output_archive.is_some() {
+        return Err(Box::new(ClassifierError(ErrorKind::Config, "--output-archive is only supported by scan".to_owned())));
+    }
+    let mut params = absolute_input(params)?;
+    params.merge = true;
+    let notify = config.notify.clone();
+    notify_after("sync", &notify, &params, log, || {
+        prepare_output_root(&params, params.create_output)?;
+        let mut audit = params.audit_log.as_deref()
+            .map(|path| open_audit_log(path, &manifest_header(&config), &params)).transpose()?;
+        prune_output(&OutputRoots::new(&params)?, audit.as_mut(), log)?;
+        drop(audit);
+        scan(config, &params, &mut *sinks::for_params(&params)?, log)
+    })
+}
+
+// rehashes the files listed in OUTPUT_DIR/.classifiles/SHA256SUMS (relative to OUTPUT_DIR), mismatches and unreadable
+// files are file errors
+pub fn run_verify(config: Config, output_path: &Path, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let checksums = checksums::read(&checksums::checksums_path(output_path))?;
+    let mut reader = FileReader::new(config.read_backend, config.sniff_buffer_size, config.read_buffer_size,
+        config.mmap_min_size, log)?;
+    let mut file_errors = FileErrors::default();
+    let file_count = checksums.len();
+
+    for (i, (path, expected)) in checksums.iter().enumerate() {
+        let path = &output_path.join(path);
+        let entry_log = get_entry_log(log, path, i, file_count);
+        match hashing::sha256_file(path, &mut reader) {
+            Ok(hash) if hash == *expected => debug!(entry_log, "OK"),
+            Ok(_) => file_errors.record(path, &*Box::<dyn Error>::from("checksum mismatch"), &entry_log),
+            Err(e) => file_errors.record(path, &e, &entry_log),
+        }
+    }
+
+    info!(log, "Verified {} files, {} failed", file_count, file_errors.errors.len());
+    file_errors.into_result()
+}
+
+// entry count of an intact audit log, an error pointing at the first tampered entry otherwise
+pub fn run_verify_audit_log(path: &Path) -> Result<usize, Box<dyn Error>> {
+    audit::verify(path)
+}
+
+pub fn run_dupes(output_path: &Path, max_distance: u32) -> Result<Vec<Vec<PathBuf>>, Box<dyn Error>> {
+    let records: Vec<Record> = manifest::read_manifest(output_path)?
+        .into_iter()
+        .filter(|r| r.phash.is_some())
+        .collect();
+
+    if records.is_empty() {
+        return Err(Box::new(ClassifierError(ErrorKind::Config,
+            "manifest contains no perceptual hashes, run scan with --phash first".to_owned()
+        )));
+    }
+
+    let hashes: Vec<u64> = records.iter().filter_map(|r| r.phash).collect();
+    let groups = phash::group_similar(&hashes, max_distance)
+        .into_iter()
+        .map(|g| g.into_iter().map(|i| records[i].path.clone()).collect())
+        .collect();
+
+    Ok(groups)
+}
+
+// with treemap, the disk usage of the input tree by type is exported instead of the report;
+// with executable_paths, setuid, setgid and world-writable files and executables outside these path globs are listed too
+pub fn run_report(output_path: &Path, html: bool, treemap: Option<TreemapFormat>, executable_paths: Option<&[String]>,
+    upload: Option<&str>, log: &Logger) -> Result<Option<PathBuf>, Box<dyn Error>> {
+
+    if html && treemap.is_some() {
+        return Err(Box::new(ClassifierError(ErrorKind::Config, "--html and --treemap cannot be combined".to_owned())));
+    }
+    if executable_paths.is_some() && treemap.is_some() {
+        return Err(Box::new(ClassifierError(ErrorKind::Config, "--security and --treemap cannot be combined".to_owned())));
+    }
+    if let Some(url) = upload {
+        upload::check_url(url)?;
+    }
+    let (header, records) = manifest::read_manifest_file_with_header(&manifest::manifest_path(output_path))?;
+
+    if let Some(format) = treemap {
+        let treemap_path = output_path.join(format.file_name());
+        let mut out = std::io::BufWriter::new(fs::File::create(&treemap_path)?);
+        treemap::write(&records, format, &mut out)?;
+        std::io::Write::flush(&mut out)?;
+        if let Some(url) = upload {
+            upload::upload(url, format.file_name(), fs::read(&treemap_path)?, log)?;
+        }
+        return Ok(Some(treemap_path));
+    }
+
+    let summary = report::summarize(&records);
+    let findings = executable_paths.map(|executable_paths| report::security_findings(&records, executable_paths));
+    if html {
+        // placed in the output root so that the relative links into the tree work
+        let report_path = output_path.join("report.html");
+        let mut out = std::io::BufWriter::new(fs::File::create(&report_path)?);
+        report::write_html(&summary, header.as_ref(), findings.as_ref(), &mut out)?;
+        std::io::Write::flush(&mut out)?;
+        if let Some(url) = upload {
+            upload::upload(url, "report.html", fs::read(&report_path)?, log)?;
+        }
+        Ok(Some(report_path))
+    } else {
+        let mut text = Vec::new();
+        report::write_text(&summary, header.as_ref(), findings.as_ref(), &mut text)?;
+        std::io::Write::write_all(&mut std::io::stdout(), &text)?;
+        if let Some(url) = upload {
+            upload::upload(url, "report.txt", text, log)?;
+        }
+        Ok(None)
+    }
+}
+
+// composition of the output tree after each scan, oldest first
+pub fn run_history(output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let snapshots = history::read(output_path)?;
+    if snapshots.is_empty() {
+        return Err(format!("no scan history in {}", output_path.display()).into());
+    }
+    history::write_history(&snapshots, &mut std::io::stdout())?;
+    Ok(())
+}
+
+// counts and sizes per type over the last scans
+pub fn run_trend(output_path: &Path, last: usize) -> Result<(), Box<dyn Error>> {
+    let snapshots = history::read(output_path)?;
+    if snapshots.is_empty() {
+        return Err(format!("no scan history in {}", output_path.display()).into());
+    }
+    history::write_trend(&snapshots[snapshots.len().saturating_sub(last)..], &mut std::io::stdout())?;
+    Ok(())
+}
+
+// the manifest of an output directory (or a manifest file) as a table, returns the number of rows
+pub fn run_export(manifest_path: &Path, format: ExportFormat, export_path: &Path) -> Result<usize, Box<dyn Error>> {
+    let manifest_file = if manifest_path.is_dir() {
+        manifest::manifest_path(manifest_path)
+    } else {
+        manifest_path.to_owned()
+    };
+    let records = manifest::read_manifest_file(&manifest_file)?;
+    export::write(&records, format, export_path)?;
+    Ok(records.len())
+}
+
+// read from devices and streams by classify --stream, enough for the volume signatures and for what libmagic
+// looks at by default
+const STREAM_SNIFF_LEN: usize = 1024 * 1024;
+
+// what classify reports for a file, device or stream
+#[derive(Debug, serde::Serialize)]
+pub struct Identification {
+    pub path: PathBuf,
+    pub mime: Option<String>,
+    pub ext: Option<String>,
+    // partition table, filesystem or volume format found in the leading bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume: Option<String>,
+    // libmagic's description, e.g. with the label and UUID of a filesystem
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl fmt::Display for Identification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.mime.as_deref().unwrap_or(OUTPUT_UNKNOWN))?;
+        if let Some(volume) = &self.volume {
+            write!(f, ", {}", volume)?;
+        }
+        if let Some(description) = &self.description {
+            write!(f, " ({})", description)?;
+        }
+        Ok(())
+    }
+}
+
+// without stream, special files are recognized from their metadata as in a scan
+fn identify(classifier: &mut Classifier, cookie_desc: Option<&Cookie>, path: &Path, stream: bool, log: &Logger)
+    -> Result<Identification, Box<dyn Error>> {
+
+    use std::io::Read;
+
+    let read_error = |e: std::io::Error| format!("could not read {}: {}", path.display(), e);
+    let mut data = Vec::new();
+    let file_type = if stream {
+        let read = match path == Path::new("-") {
+            true => std::io::stdin().lock().take(STREAM_SNIFF_LEN as u64).read_to_end(&mut data),
+            false => fs::File::open(path).and_then(|file| file.take(STREAM_SNIFF_LEN as u64).read_to_end(&mut data)),
+        };
+        read.map_err(read_error)?;
+        classifier.process_buffer(path, &data, log)
+    } else {
+        let meta = fs::metadata(path).map_err(read_error)?;
+        // disk images are regular files
+        if meta.is_file() {
+            fs::File::open(path).and_then(|file| file.take(STREAM_SNIFF_LEN as u64).read_to_end(&mut data))
+                .map_err(read_error)?;
+        }
+        classifier.process_file(path, Some(&meta), log)
+    };
+
+    Ok(Identification{
+        path: path.to_owned(),
+        mime: file_type.mime,
+        ext: file_type.ext,
+        volume: volumes::identify(&data).map(str::to_owned),
+        description: cookie_desc.filter(|_| !data.is_empty()).and_then(|cookie| cookie.buffer(&data).ok()),
+    })
+}
+
+// prints the type of each path, a line of text or JSON each; with stream, block and character devices, fifos and
+// - (stdin) are read too, so that disk images and partitions can be identified before mounting or carving them
+pub fn run_classify(config: Config, paths: &[PathBuf], stream: bool, json: bool, log: &Logger)
+    -> Result<(), Box<dyn Error>> {
+
+    use std::io::Write;
+
+    let cookie_desc_opt = get_magic_cookie_opt(config.libmagic_db_file.as_deref(), Query::Description);
+    let plugins = config.plugins_dir.as_deref().map(plugins::load_dir).transpose()?.unwrap_or_default();
+    let reader = FileReader::new(config.read_backend, config.sniff_buffer_size, config.read_buffer_size,
+        config.mmap_min_size, log)?;
+    let mut classifier = Classifier::new(config, plugins, reader);
+    let mut file_errors = FileErrors::default();
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for path in paths {
+        let entry_log = log.new(o!("item" => path.display().to_string()));
+        match identify(&mut classifier, cookie_desc_opt.as_ref(), path, stream, &entry_log) {
+            Ok(identification) if json => writeln!(out, "{}", serde_json::to_string(&identification)?)?,
+            Ok(identification) => writeln!(out, "{}", identification)?,
+            Err(e) => file_errors.record(path, &*e, &entry_log),
+        }
+    }
+    file_errors.into_result()
+}
+
+// where carve-map found a known format to start inside a blob
+#[derive(Debug, serde::Serialize)]
+pub struct CarveHit {
+    pub offset: u64,
+    pub signature: &'static str,
+    // of the bytes from offset on, None if the detectors do not confirm the signature
+    pub mime: Option<String>,
+    pub ext: Option<String>,
+}
+
+impl fmt::Display for CarveHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#014x}  {:<8}  {}", self.offset, self.signature, self.mime.as_deref().unwrap_or(OUTPUT_UNKNOWN))
+    }
+}
+
+// a map of a disk image, device or memory dump for recovery work, not carving: where known formats start, each
+// match classified by the detection chain from its offset on; matches it does not confirm are only listed with all
+pub fn run_carve_map(config: Config, path: &Path, all: bool, json: bool, log: &Logger) -> Result<(), Box<dyn Error>> {
+    use std::io::Write;
+    use std::os::unix::fs::FileExt;
+
+    let mut window = vec![0; config.sniff_buffer_size];
+    let plugins = config.plugins_dir.as_deref().map(plugins::load_dir).transpose()?.unwrap_or_default();
+    let reader = FileReader::new(config.read_backend, config.sniff_buffer_size, config.read_buffer_size,
+        config.mmap_min_size, log)?;
+    let mut classifier = Classifier::new(config, plugins, reader);
+
+    let mut file = fs::File::open(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    // read at the offsets of matches while the scan reads on
+    let window_file = file.try_clone()?;
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    let (mut found, mut confirmed) = (0, 0);
+    let len = carving::scan(&mut file, &mut |offset, signature| {
+        found += 1;
+        let mut filled = 0;
+        while filled < window.len() {
+            match window_file.read_at(&mut window[filled..], offset + filled as u64)? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        // the offset as name, the extension of the blob says nothing about what is inside
+        let name = PathBuf::from(format!("{:#x}", offset));
+        let hit_log = log.new(o!("item" => format!("{}@{:#x}", path.display(), offset)));
+        let file_type = classifier.process_buffer(&name, &window[..filled], &hit_log);
+        let mime = file_type.mime.filter(|mime| mime != "application/octet-stream");
+        if mime.is_some() {
+            confirmed += 1;
+        } else if !all {
+            return Ok(());
+        }
+
+        let hit = CarveHit{offset, signature: signature.name, mime, ext: file_type.ext};
+        match json {
+            true => writeln!(out, "{}", serde_json::to_string(&hit)?)?,
+            false => writeln!(out, "{}", hit)?,
+        }
+        Ok(())
+    })?;
+    info!(log, "Found {} signatures in {} bytes, {} confirmed by the detectors", found, len, confirmed);
+    Ok(())
+}
+
+// input paths of the records of an output directory matching the filter, or their output links
+// (records without a link are left out then)
+pub fn run_find(output_path: &Path, filter: &SearchFilter, links: bool) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    Ok(manifest::read_manifest(output_path)?
+        .into_iter()
+        .filter(|r| filter.matches(r))
+        .filter_map(|r| match links {
+            true => r.link.map(|link| output_path.join(link)),
+            false => Some(r.path),
+        })
+        .collect())
+}
+
+// serves the by-mime, by-date and by-tag view of the manifest of an output directory at mountpoint until unmounted
+// (requires the fuse feature)
+pub fn run_mount(config: &Config, output_path: &Path, mountpoint: &Path, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let records = manifest::read_manifest(output_path)?;
+    mount::mount(&records, config.lowercase_extensions, mountpoint, log)
+}
+
+// output root of an output entry, the closest directory above the entry that holds a manifest,
+// and the entry relative to it
+fn locate_entry(output_entry: &Path) -> Result<(PathBuf, PathBuf), Box<dyn Error>> {
+    let entry = absolute_entry_path(output_entry)?;
+    let output_root = entry.ancestors()
+        .skip(1)
+        .find(|dir| manifest::manifest_path(dir).is_file())
+        .ok_or_else(|| format!("{} is not inside a classifiles output tree", output_entry.display()))?;
+    Ok((output_root.to_owned(), entry.strip_prefix(output_root)?.to_owned()))
+}
+
+// input file behind an output entry (link, copy or preview)
+pub fn run_whereis(output_entry: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let (output_root, entry_rel) = locate_entry(output_entry)?;
+    let entry_rel = entry_rel.as_path();
+
+    manifest::read_manifest(&output_root)?
+        .into_iter()
+        .find(|r| r.link.as_deref() == Some(entry_rel) || r.preview.as_deref() == Some(entry_rel))
+        .map(|r| r.path)
+        .ok_or_else(|| format!("{} is not recorded in the manifest of {}", output_entry.display(), output_root.display()).into())
+}
+
+// output entry and its tags
+type EntryTags = (PathBuf, Vec<String>);
+
+// adds and removes tags of output entries of one output tree, returns the tags of each entry afterwards
+pub fn run_tag(output_entries: &[PathBuf], add: &[String], remove: &[String])
+    -> Result<Vec<EntryTags>, Box<dyn Error>> {
+
+    for tag in add.iter().chain(remove) {
+        tags::check_tag(tag).map_err(|e| ClassifierError(ErrorKind::Config, e))?;
+    }
+    let mut output_root: Option<PathBuf> = None;
+    let mut entries_rel = Vec::new();
+    for output_entry in output_entries {
+        let (root, entry_rel) = locate_entry(output_entry)?;
+        match &output_root {
+            Some(output_root) if *output_root != root => {
+                return Err(format!("{} is not in the output tree {}", output_entry.display(), output_root.display()).into());
+            }
+            Some(_) => (),
+            None => output_root = Some(root),
+        }
+        entries_rel.push(entry_rel);
+    }
+    let output_root = match output_root {
+        Some(output_root) => output_root,
+        None => return Ok(Vec::new()),
+    };
+
+    let (header, mut records) = manifest::read_manifest_file_with_header(&manifest::manifest_path(&output_root))?;
+    let by_link: FnvHashMap<PathBuf, usize> = records.iter().enumerate()
+        .filter_map(|(i, record)| Some((record.link.clone()?, i)))
+        .collect();
+    let mut entry_tags = Vec::new();
+    for (output_entry, entry_rel) in output_entries.iter().zip(&entries_rel) {
+        let record = by_link.get(entry_rel).map(|&i| &mut records[i])
+            .ok_or_else(|| format!("{} is not recorded in the manifest of {}", output_entry.display(), output_root.display()))?;
+        for tag in add {
+            tags::add(record, tag);
+        }
+        for tag in remove {
+            tags::remove(record, tag);
+        }
+        entry_tags.push((output_entry.clone(), record.tags.clone()));
+    }
+
+    if !add.is_empty() || !remove.is_empty() {
+        let mut manifest = ManifestWriter::create(&output_root, header.as_ref())?;
+        for record in &records {
+            manifest.write(record)?;
+        }
+        manifest.finish()?;
+    }
+    Ok(entry_tags)
+}
+
+// points a link at the same path under new_prefix if its target lies under old_prefix, both absolute
+fn relink_symlink(link: &Path, old_prefix: &Path, new_prefix: &Path, log: &Logger) -> Result<bool, Box<dyn Error>> {
+    let target = fs::read_link(link)?;
+    let new_target = match target.is_absolute() {
+        true => match target.strip_prefix(old_prefix) {
+            Ok(target_rel) => new_prefix.join(target_rel),
+            Err(_) => return Ok(false),
+        },
+        // relative targets (--relative-links) are matched by the path they lead to, resolved lexically as the old
+        // tree is gone, and stay relative
+        false => {
+            let link_dir = absolute_entry_path(link)?.parent().map(Path::to_owned).unwrap_or_default();
+            let resolved = target_in_root(&link_dir, &target, true).unwrap_or_default();
+            match resolved.strip_prefix(old_prefix) {
+                Ok(target_rel) => relative_path(&link_dir, &new_prefix.join(target_rel)),
+                Err(_) => return Ok(false),
+            }
+        }
+    };
+
+    // replace the link atomically so that an interrupted run never loses it
+    let mut tmp_link = link.as_os_str().to_owned();
+    tmp_link.push(".relink-tmp");
+    let tmp_link = PathBuf::from(tmp_link);
+    unix_fs::symlink(&new_target, &tmp_link)?;
+    fs::rename(&tmp_link, link)?;
+
+    debug!(log, "{} -> {}", target.display(), new_target.display());
+    Ok(true)
+}
+
+pub fn run_relink(output_path: &Path, old_prefix: &Path, new_prefix: &Path, log: &Logger) -> Result<(), Box<dyn Error>> {
+    if !output_path.is_dir() {
+        return Err(Box::new(ClassifierError(ErrorKind::InvalidOutput,
+            format!("{} is not a directory", output_path.display())
+        )));
+    }
+    // link targets and manifest paths are absolute, like the scan records them
+    let old_prefix = &absolute_path(old_prefix)?;
+    let new_prefix = &absolute_path(new_prefix)?;
+
+    let walk_opts = WalkOptions{
+        excluded: vec![manifest::state_dir(output_path)],
+        ..Default::default()
+    };
+    let get_walker = || walk_input(output_path, &walk_opts);
+
+    let item_count = get_walker().filter_map(|e| e.ok()).filter(|e| e.path_is_symlink()).count();
+    let mut file_errors = FileErrors::default();
+    let mut relinked = 0;
+
+    let mut i = 0;
+    for entry in get_walker() {
+        let entry = match entry {
+            Ok(entry) if entry.path_is_symlink() => entry,
+            Ok(_) => continue,
+            Err(e) => {
+                file_errors.record(e.path().unwrap_or(output_path), &e, log);
+                continue;
+            }
+        };
+        let entry_log = get_entry_log(log, entry.path(), i, item_count);
+        i += 1;
+
+        match relink_symlink(entry.path(), old_prefix, new_prefix, &entry_log) {
+            Ok(true) => relinked += 1,
+            Ok(false) => (),
+            Err(e) => file_errors.record(entry.path(), &*e, &entry_log),
+        }
+    }
+
+    // keep the manifest consistent with the moved source tree
+    if manifest::manifest_path(output_path).is_file() {
+        let (header, records) = manifest::read_manifest_file_with_header(&manifest::manifest_path(output_path))?;
+        let mut manifest = ManifestWriter::create(output_path, header.as_ref())?;
+        for mut record in records {
+            if let Ok(path_rel) = record.path.strip_prefix(old_prefix) {
+                record.path = new_prefix.join(path_rel);
+            }
+            manifest.write(&record)?;
+        }
+        manifest.finish()?;
+    }
+
+    info!(log, "Relinked {} of {} links", relinked, item_count);
+    file_errors.into_result()
+}
+
+// input_path is either a manifest file or an output tree containing one
+pub fn run_apply_manifest(params: Params, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let manifest_file = if params.input_path.is_dir() {
+        manifest::manifest_path(&params.input_path)
+    } else {
+        params.input_path.clone()
+    };
+    let (header, records) = manifest::read_manifest_file_with_header(&manifest_file)?;
+
+    info!(log, "Applying {} records from {}", records.len(), manifest_file.display());
+    // the records keep the classification of the original run
+    let plan = ScanPlan{header, ..ScanPlan::new(records)};
+    materialize(plan, &params, log)
+}
+
+// merges an override rules file (e.g. written by scan --save-overrides) into the override db
+pub fn run_learn(config: &Config, rules_file: &Path, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let db = learn_db(config)?;
+    if !rules_file.exists() {
+        return Err(format!("override rules file {} does not exist", rules_file.display()).into());
+    }
+    let rules = overrides::load_rules(rules_file)?;
+
+    info!(log, "Learning {} override rules from {} into {}", rules.len(), rules_file.display(), db.display());
+    overrides::save_rules(db, rules)
+}
+
+// MIME types of the configured shared-mime-info db
+pub fn known_mime_types(config: &Config) -> Vec<String> {
+    MimeInfoDb::new(&config.mime_info_db_root).known_types()
+}
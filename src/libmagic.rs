@@ -0,0 +1,70 @@
+use std::error::Error;
+use std::path::Path;
+
+// what a cookie reports for a file
+#[derive(Debug, Clone, Copy)]
+pub enum Query {
+    MimeType,
+    Extension,
+    Description,
+}
+
+#[cfg(feature = "libmagic")]
+pub struct Cookie(magic::Cookie);
+
+#[cfg(feature = "libmagic")]
+impl Cookie {
+    // without a database file, libmagic loads its compiled-in default
+    pub fn open(db_file: Option<&Path>, query: Query) -> Result<Self, Box<dyn Error>> {
+        let flags = match query {
+            Query::MimeType => magic::flags::MIME_TYPE,
+            Query::Extension => magic::flags::EXTENSION,
+            Query::Description => magic::flags::NONE,
+        };
+        let cookie = magic::Cookie::open(flags)?;
+        let databases: Vec<&Path> = db_file.into_iter().collect();
+        cookie.load(&databases)?;
+        Ok(Self(cookie))
+    }
+
+    pub fn file(&self, path: &Path) -> Result<String, Box<dyn Error>> {
+        Ok(self.0.file(path)?)
+    }
+
+    pub fn buffer(&self, data: &[u8]) -> Result<String, Box<dyn Error>> {
+        Ok(self.0.buffer(data)?)
+    }
+}
+
+// e.g. 5.45, libmagic reports it as 545
+#[cfg(feature = "libmagic")]
+pub fn version() -> Option<String> {
+    extern "C" {
+        fn magic_version() -> libc::c_int;
+    }
+    let version = unsafe { magic_version() };
+    Some(format!("{}.{:02}", version / 100, version % 100))
+}
+
+#[cfg(not(feature = "libmagic"))]
+pub fn version() -> Option<String> {
+    None
+}
+
+#[cfg(not(feature = "libmagic"))]
+pub enum Cookie {}
+
+#[cfg(not(feature = "libmagic"))]
+impl Cookie {
+    pub fn open(_db_file: Option<&Path>, _query: Query) -> Result<Self, Box<dyn Error>> {
+        Err("classifiles was built without the libmagic feature".into())
+    }
+
+    pub fn file(&self, _path: &Path) -> Result<String, Box<dyn Error>> {
+        match *self {}
+    }
+
+    pub fn buffer(&self, _data: &[u8]) -> Result<String, Box<dyn Error>> {
+        match *self {}
+    }
+}
@@ -0,0 +1,336 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::manifest;
+
+// records written by backup are a header line followed by fields written as KEY LENGTH, a newline,
+// LENGTH raw bytes and another newline, so that any byte may occur in a path while ordinary values stay readable
+
+// a symlink as stored by backup in NAME.lns
+#[derive(Debug, Default)]
+pub struct LinkInfo {
+    pub target: PathBuf,
+    // false for dangling links and link loops (backup --check-links)
+    pub exists: Option<bool>,
+    // absolute target with all links resolved, as far as they could be (backup --check-links)
+    pub resolved: Option<PathBuf>,
+}
+
+static HEADER: &[u8] = b"classifiles-link 1\n";
+static EXTENSION: &str = "lns";
+
+// annotations of the previous format, key=value lines after the target
+static LEGACY_EXISTS_KEY: &[u8] = b"exists=";
+static LEGACY_RESOLVED_KEY: &[u8] = b"resolved=";
+
+// backup --link-index: all records in one zstd stream, each a link field holding the path and the fields of a .lns
+static INDEX_HEADER: &[u8] = b"classifiles-link-index 1\n";
+static INDEX_FILE: &str = "links.zst";
+
+pub fn is_link_record(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == EXTENSION)
+}
+
+pub fn push_field(bytes: &mut Vec<u8>, key: &str, value: &[u8]) {
+    bytes.extend_from_slice(format!("{} {}\n", key, value.len()).as_bytes());
+    bytes.extend_from_slice(value);
+    bytes.push(b'\n');
+}
+
+fn to_path(bytes: &[u8]) -> PathBuf {
+    Path::new(OsStr::from_bytes(bytes)).to_owned()
+}
+
+// the next field and what follows it
+fn parse_field(bytes: &[u8]) -> Result<(&str, &[u8], &[u8]), String> {
+    let line_end = bytes.iter().position(|&b| b == b'\n').ok_or("truncated field")?;
+    let line = std::str::from_utf8(&bytes[..line_end]).map_err(|_| "invalid field header")?;
+    let (key, len) = line.split_once(' ').ok_or_else(|| format!("invalid field header {}", line))?;
+    let len: usize = len.parse().map_err(|_| format!("invalid length of field {}", key))?;
+
+    let value_start = line_end + 1;
+    let value_end = value_start.checked_add(len).filter(|&end| bytes.get(end) == Some(&b'\n'))
+        .ok_or_else(|| format!("truncated field {}", key))?;
+    Ok((key, &bytes[value_start..value_end], &bytes[value_end + 1..]))
+}
+
+// all fields of a record following its header
+pub fn parse_fields(bytes: &[u8]) -> Result<Vec<(&str, &[u8])>, String> {
+    let mut fields = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let (key, value, next) = parse_field(rest)?;
+        fields.push((key, value));
+        rest = next;
+    }
+    Ok(fields)
+}
+
+impl LinkInfo {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = HEADER.to_vec();
+        self.push_fields(&mut bytes);
+        bytes
+    }
+
+    fn push_fields(&self, bytes: &mut Vec<u8>) {
+        push_field(bytes, "target", self.target.as_os_str().as_bytes());
+        if let Some(exists) = self.exists {
+            push_field(bytes, "exists", if exists { b"1" } else { b"0" });
+        }
+        if let Some(resolved) = &self.resolved {
+            push_field(bytes, "resolved", resolved.as_os_str().as_bytes());
+        }
+    }
+
+    // older backups are read as well: the bare target, possibly followed by key=value annotations
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        match bytes.strip_prefix(HEADER) {
+            Some(rest) => Self::from_fields(parse_fields(rest)?),
+            None => Ok(Self::parse_legacy(bytes)),
+        }
+    }
+
+    fn from_fields(fields: Vec<(&str, &[u8])>) -> Result<Self, String> {
+        let mut info = LinkInfo::default();
+        let mut target = None;
+        for (key, value) in fields {
+            match key {
+                "target" => target = Some(to_path(value)),
+                "exists" => info.exists = Some(value == b"1"),
+                "resolved" => info.resolved = Some(to_path(value)),
+                // written by a newer version
+                _ => (),
+            }
+        }
+        info.target = target.ok_or("missing link target")?;
+        Ok(info)
+    }
+
+    fn parse_legacy(bytes: &[u8]) -> Self {
+        let mut info = LinkInfo::default();
+        let mut rest = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+
+        // annotations are taken from the end, the target itself may contain newlines
+        while let Some(split) = rest.iter().rposition(|&b| b == b'\n') {
+            let line = &rest[split + 1..];
+            if let (Some(value), None) = (line.strip_prefix(LEGACY_RESOLVED_KEY), &info.resolved) {
+                info.resolved = Some(to_path(value));
+            } else if let (Some(b"1"), None) = (line.strip_prefix(LEGACY_EXISTS_KEY), info.exists) {
+                info.exists = Some(true);
+            } else if let (Some(b"0"), None) = (line.strip_prefix(LEGACY_EXISTS_KEY), info.exists) {
+                info.exists = Some(false);
+            } else {
+                break;
+            }
+            rest = &rest[..split];
+        }
+        info.target = to_path(rest);
+        info
+    }
+}
+
+pub fn index_path(backup_root: &Path) -> PathBuf {
+    manifest::state_dir(backup_root).join(INDEX_FILE)
+}
+
+pub struct IndexWriter {
+    out: zstd::Encoder<'static, BufWriter<File>>,
+}
+
+impl IndexWriter {
+    // replaces the index of a previous backup into the same directory
+    pub fn create(backup_root: &Path) -> io::Result<Self> {
+        fs::create_dir_all(manifest::state_dir(backup_root))?;
+        // level 0 is the zstd default
+        let mut out = zstd::Encoder::new(BufWriter::new(File::create(index_path(backup_root))?), 0)?;
+        out.write_all(INDEX_HEADER)?;
+        Ok(Self{out})
+    }
+
+    // link relative to the backup root
+    pub fn add(&mut self, link: &Path, info: &LinkInfo) -> io::Result<()> {
+        let mut record = Vec::new();
+        push_field(&mut record, "path", link.as_os_str().as_bytes());
+        info.push_fields(&mut record);
+
+        let mut bytes = Vec::new();
+        push_field(&mut bytes, "link", &record);
+        self.out.write_all(&bytes)
+    }
+
+    // the index is unreadable without the end of the zstd frame
+    pub fn finish(self) -> io::Result<()> {
+        self.out.finish()?.flush()
+    }
+}
+
+// records are read one at a time, the whole index would not fit in memory for the trees it is meant for
+pub struct IndexReader {
+    input: BufReader<zstd::Decoder<'static, BufReader<File>>>,
+    // nothing can be read after a corrupted field
+    failed: bool,
+}
+
+// key and value
+type Field = (String, Vec<u8>);
+
+// the next field of a stream, none at its end
+fn read_field(input: &mut impl BufRead) -> Result<Option<Field>, Box<dyn Error>> {
+    let mut line = Vec::new();
+    if input.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    let line = line.strip_suffix(b"\n").ok_or("truncated field")?;
+    let line = std::str::from_utf8(line).map_err(|_| "invalid field header")?;
+    let (key, len) = line.split_once(' ').ok_or_else(|| format!("invalid field header {}", line))?;
+    let len: u64 = len.parse().map_err(|_| format!("invalid length of field {}", key))?;
+
+    // not allocated up front, the length may be garbage
+    let mut value = Vec::new();
+    input.take(len.saturating_add(1)).read_to_end(&mut value)?;
+    if value.len() as u64 != len.saturating_add(1) || value.pop() != Some(b'\n') {
+        return Err(format!("truncated field {}", key).into());
+    }
+    Ok(Some((key.to_owned(), value)))
+}
+
+impl IndexReader {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut input = BufReader::new(zstd::Decoder::new(File::open(path)?)?);
+        let mut header = vec![0; INDEX_HEADER.len()];
+        input.read_exact(&mut header).map_err(|_| "not a link index")?;
+        if header != INDEX_HEADER {
+            return Err("not a link index".into());
+        }
+        Ok(Self{input, failed: false})
+    }
+
+    fn parse_record(record: &[u8]) -> Result<(PathBuf, LinkInfo), Box<dyn Error>> {
+        let mut fields = parse_fields(record)?;
+        let path_field = fields.iter().position(|&(key, _)| key == "path").ok_or("missing link path")?;
+        let path = to_path(fields.remove(path_field).1);
+        // an index from an untrusted source must not place links outside the restore root
+        if path.as_os_str().is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(format!("invalid link path {}", path.display()).into());
+        }
+        Ok((path, LinkInfo::from_fields(fields)?))
+    }
+}
+
+impl Iterator for IndexReader {
+    // links relative to the backup root, ends after an error in the stream itself
+    type Item = Result<(PathBuf, LinkInfo), Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            match read_field(&mut self.input) {
+                Ok(Some((key, record))) if key == "link" => return Some(Self::parse_record(&record)),
+                // written by a newer version
+                Ok(Some(_)) => continue,
+                Ok(None) => return None,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // names a line-based format would split or misread
+    fn hostile_paths() -> Vec<PathBuf> {
+        vec![
+            to_path(b"plain/target"),
+            to_path(b"two\nlines"),
+            to_path(b"trailing newline\n"),
+            to_path(b"\nleading newline"),
+            to_path(b"a -> b"),
+            to_path(b"target 3\nabc"),
+            to_path(b"x\nexists=1"),
+            to_path(b"x\nresolved=/etc/passwd"),
+            to_path(b"latin1 \xe9\xff\xfe"),
+            to_path(b"classifiles-link 1\nnot a header"),
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        for target in hostile_paths() {
+            for (exists, resolved) in [(None, None), (Some(true), Some(to_path(b"/r\n-> \xff"))), (Some(false), None)] {
+                let info = LinkInfo{target: target.clone(), exists, resolved: resolved.clone()};
+                let parsed = LinkInfo::parse(&info.to_bytes()).unwrap();
+                assert_eq!(parsed.target, target);
+                assert_eq!(parsed.exists, exists);
+                assert_eq!(parsed.resolved, resolved);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = LinkInfo{target: to_path(b"some\ntarget"), ..Default::default()}.to_bytes();
+        for len in HEADER.len() + 1..bytes.len() {
+            assert!(LinkInfo::parse(&bytes[..len]).is_err(), "{:?}", &bytes[..len]);
+        }
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let mut bytes = HEADER.to_vec();
+        push_field(&mut bytes, "mode", b"\n\n");
+        push_field(&mut bytes, "target", b"t\xff");
+        let info = LinkInfo::parse(&bytes).unwrap();
+        assert_eq!(info.target, to_path(b"t\xff"));
+        assert!(LinkInfo::parse(HEADER).is_err());
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let root = std::env::temp_dir().join(format!("classifiles-lns-test-{}", std::process::id()));
+        let mut index = IndexWriter::create(&root).unwrap();
+        for (i, target) in hostile_paths().into_iter().enumerate() {
+            let link = Path::new("dir\n -> x").join(i.to_string()).join(target.file_name().unwrap());
+            index.add(&link, &LinkInfo{target, exists: Some(i % 2 == 0), resolved: None}).unwrap();
+        }
+        index.finish().unwrap();
+
+        let read: Vec<(PathBuf, LinkInfo)> = IndexReader::open(&index_path(&root)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(read.len(), hostile_paths().len());
+        for (i, ((link, info), target)) in read.into_iter().zip(hostile_paths()).enumerate() {
+            assert_eq!(link, Path::new("dir\n -> x").join(i.to_string()).join(target.file_name().unwrap()));
+            assert_eq!(info.target, target);
+            assert_eq!(info.exists, Some(i % 2 == 0));
+        }
+    }
+
+    #[test]
+    fn legacy() {
+        let info = LinkInfo::parse(b"../some target\n").unwrap();
+        assert_eq!(info.target, to_path(b"../some target"));
+        assert_eq!((info.exists, info.resolved), (None, None));
+
+        let info = LinkInfo::parse(b"two\nlines -> \xff\nexists=0\nresolved=/abs/\xfe\n").unwrap();
+        assert_eq!(info.target, to_path(b"two\nlines -> \xff"));
+        assert_eq!(info.exists, Some(false));
+        assert_eq!(info.resolved, Some(to_path(b"/abs/\xfe")));
+
+        // only the last of repeated annotations is taken, the others belong to the target
+        let info = LinkInfo::parse(b"t\nexists=1\nexists=0").unwrap();
+        assert_eq!(info.target, to_path(b"t\nexists=1"));
+        assert_eq!(info.exists, Some(false));
+    }
+}
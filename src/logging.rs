@@ -0,0 +1,123 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use slog::{o, Drain, Level, LevelFilter, Logger};
+
+type BoxedDrain = Box<dyn Drain<Ok = (), Err = slog::Never> + Send>;
+
+// log files are rotated once they grow past this size, keeping LOG_FILE_KEEP old ones (FILE.1 is the newest)
+const LOG_FILE_MAX_SIZE: u64 = 10 * 1024 * 1024;
+const LOG_FILE_KEEP: usize = 5;
+
+// where the term backend writes to, stderr for verbs whose stdout is read by another program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminal {
+    Stdout,
+    Stderr,
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    // once rotating fails (e.g. a sandbox denies the renames), the current file keeps growing
+    rotation_failed: bool,
+}
+
+impl RotatingFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self{path: path.to_owned(), file, size, rotation_failed: false})
+    }
+
+    fn rotated_path(&self, i: usize) -> PathBuf {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", i));
+        PathBuf::from(rotated)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for i in (1..LOG_FILE_KEEP).rev() {
+            let older = self.rotated_path(i);
+            if older.exists() {
+                fs::rename(&older, self.rotated_path(i + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    // the decorator flushes after every record, so rotating here never splits a line; its errors are not returned,
+    // they would reach the fused drain and kill the logging thread
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.size > LOG_FILE_MAX_SIZE && !self.rotation_failed {
+            if let Err(e) = self.rotate() {
+                eprintln!("Warning: could not rotate log file {}, appending to it: {}", self.path.display(), e);
+                self.rotation_failed = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn term_drain(terminal: Terminal) -> BoxedDrain {
+    let decorator = match terminal {
+        Terminal::Stdout => slog_term::TermDecorator::new().stdout().build(),
+        Terminal::Stderr => slog_term::TermDecorator::new().stderr().build(),
+    };
+    Box::new(slog_term::CompactFormat::new(decorator).build().fuse())
+}
+
+fn file_drain(path: &Path) -> Result<BoxedDrain, Box<dyn Error>> {
+    let file = RotatingFile::open(path)
+        .map_err(|e| format!("could not open log file {}: {}", path.display(), e))?;
+    let decorator = slog_term::PlainDecorator::new(file);
+    Ok(Box::new(slog_term::FullFormat::new(decorator).build().fuse()))
+}
+
+// key-value pairs (item, mime, ...) end up as message fields with syslog and as journal fields with journald
+fn backend_drain(backend: &str, terminal: Terminal) -> Result<BoxedDrain, Box<dyn Error>> {
+    let drain: BoxedDrain = match backend {
+        "term" => term_drain(terminal),
+        #[cfg(feature = "syslog")]
+        "syslog" => Box::new(slog_syslog::unix_3164(slog_syslog::Facility::LOG_USER)?.ignore_res()),
+        #[cfg(feature = "journald")]
+        "journald" => Box::new(slog_journald::JournaldDrain.ignore_res()),
+        #[cfg(not(feature = "syslog"))]
+        "syslog" => return Err("classifiles was built without the syslog feature".into()),
+        #[cfg(not(feature = "journald"))]
+        "journald" => return Err("classifiles was built without the journald feature".into()),
+        _ => return Err(format!("invalid log backend {}, valid backends are: term, syslog, journald", backend).into()),
+    };
+    Ok(drain)
+}
+
+// a log file replaces the terminal output, other backends keep receiving events alongside the file
+pub fn root_logger(backend: &str, terminal: Terminal, log_file: Option<&Path>, level: Level)
+    -> Result<Logger, Box<dyn Error>> {
+
+    let drain: BoxedDrain = match log_file {
+        Some(path) if backend == "term" => file_drain(path)?,
+        Some(path) => Box::new(slog::Duplicate::new(backend_drain(backend, terminal)?, file_drain(path)?).fuse()),
+        None => backend_drain(backend, terminal)?,
+    };
+
+    let drain = LevelFilter::new(drain, level).fuse();
+    let async_drain = slog_async::Async::new(drain).build().fuse();
+    Ok(Logger::root(async_drain, o!()))
+}
@@ -1,35 +1,1169 @@
 use std::{env, error::Error, fs, path::{Path, PathBuf}, process};
-use classifiles::{Config, Params};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use classifiles::{check_companion_extensions, check_dir, check_file, check_geocoding_layout, check_hook_exec, check_min_entropy, check_policy_rule, check_positive, check_webhook_url, ChecksumFormat, Clamav, ClassifierError, CompanionRule, Config, DetectionStrategy, Detector, Email, ErrorKind, ExportFormat, Geocoding, Hook, LinkMode, NameSanitization, Notify, Params, PhotoEvents, PolicyRule, Profile, ReadBackend, SearchFilter, TagRule, PROFILE_NAMES};
 
-use slog::{o, Drain};
+mod logging;
+mod completions;
+mod service;
 
 mod yaml_conf {
+    use std::collections::BTreeMap;
+
     use serde::{Serialize, Deserialize};
 
     #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
     pub struct Config {
         pub mime_info_db: InfoDbConfig,
         pub libmagic: LibMagicConfig,
+        #[serde(default)]
+        pub create_output: bool,
+        #[serde(default)]
+        pub skip: Option<SkipConfig>,
+        #[serde(default)]
+        pub overrides_db: Option<String>,
+        #[serde(default)]
+        pub hooks: Vec<HookConfig>,
+        #[serde(default)]
+        pub tags: Vec<TagConfig>,
+        #[serde(default)]
+        pub policy: Vec<PolicyConfig>,
+        #[serde(default)]
+        pub routing_script: Option<String>,
+        #[serde(default)]
+        pub plugins_dir: Option<String>,
+        #[serde(default)]
+        pub detectors: Option<Vec<String>>,
+        #[serde(default)]
+        pub detection_strategy: Option<String>,
+        #[serde(default)]
+        pub read_buffer_size: Option<usize>,
+        #[serde(default)]
+        pub read_backend: Option<String>,
+        #[serde(default)]
+        pub mmap_min_size: Option<u64>,
+        #[serde(default)]
+        pub pipeline_depth: Option<usize>,
+        #[serde(default)]
+        pub sniff_buffer_size: Option<usize>,
+        #[serde(default)]
+        pub classify_timeout_ms: Option<u64>,
+        #[serde(default)]
+        pub max_dir_entries: Option<usize>,
+        #[serde(default)]
+        pub sanitize_names: Option<String>,
+        #[serde(default)]
+        pub lowercase_extensions: bool,
+        #[serde(default)]
+        pub executable_paths: Option<Vec<String>>,
+        #[serde(default)]
+        pub entropy: bool,
+        #[serde(default)]
+        pub encrypted_min_entropy: Option<f64>,
+        #[serde(default)]
+        pub detect_encrypted_archives: bool,
+        #[serde(default)]
+        pub group_split_archives: bool,
+        #[serde(default)]
+        pub companions: Vec<CompanionConfig>,
+        #[serde(default)]
+        pub photo_events: Option<PhotoEventsConfig>,
+        #[serde(default)]
+        pub geocoding: Option<GeocodingConfig>,
+        #[serde(default)]
+        pub notify: NotifyConfig,
+        #[serde(default)]
+        pub clamav: Option<ClamavConfig>,
+        #[serde(default)]
+        pub profiles: BTreeMap<String, ProfileConfig>,
+    }
+
+    // a named profile selected with --profile NAME, applied on top of the rest of the file
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct ProfileConfig {
+        // built-in profile applied first
+        #[serde(default)]
+        pub base: Option<String>,
+        // config keys as for --set, lists may be given as YAML lists
+        #[serde(default)]
+        pub set: BTreeMap<String, serde_yaml::Value>,
+        // replace the hooks and tags of the file
+        #[serde(default)]
+        pub hooks: Option<Vec<HookConfig>>,
+        #[serde(default)]
+        pub tags: Option<Vec<TagConfig>>,
+        // as --only-mime and --skip-mime of scan and sync, unless those are given
+        #[serde(default)]
+        pub only_mime: Vec<String>,
+        #[serde(default)]
+        pub skip_mime: Vec<String>,
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct NotifyConfig {
+        #[serde(default)]
+        pub exec: Vec<String>,
+        #[serde(default)]
+        pub webhook: Option<String>,
+        #[serde(default)]
+        pub desktop: bool,
+        #[serde(default)]
+        pub email: Option<EmailConfig>,
     }
 
     #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct EmailConfig {
+        pub smtp: String,
+        pub from: String,
+        pub to: Vec<String>,
+        #[serde(default)]
+        pub html: bool,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct ClamavConfig {
+        pub socket: String,
+        #[serde(default)]
+        pub mime: Vec<String>,
+        #[serde(default)]
+        pub quarantine: Option<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct HookConfig {
+        pub mime: String,
+        pub exec: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct TagConfig {
+        pub tag: String,
+        #[serde(default)]
+        pub mime: Option<String>,
+        #[serde(default)]
+        pub path: Option<String>,
+        #[serde(default)]
+        pub min_size: Option<u64>,
+        #[serde(default)]
+        pub max_size: Option<u64>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct PolicyConfig {
+        #[serde(default)]
+        pub name: Option<String>,
+        #[serde(default)]
+        pub path: Option<String>,
+        #[serde(default)]
+        pub deny: Vec<String>,
+        #[serde(default)]
+        pub allow: Vec<String>,
+        #[serde(default)]
+        pub max_count: usize,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct CompanionConfig {
+        pub primary: String,
+        pub extensions: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct PhotoEventsConfig {
+        #[serde(default)]
+        pub mime: Option<Vec<String>>,
+        #[serde(default)]
+        pub max_gap_minutes: Option<u64>,
+        #[serde(default)]
+        pub min_photos: Option<usize>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct GeocodingConfig {
+        #[serde(default)]
+        pub mime: Option<Vec<String>>,
+        #[serde(default)]
+        pub layout: Option<String>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct SkipConfig {
+        pub paths: Vec<String>,
+        pub dir_names: Vec<String>,
+        #[serde(default)]
+        pub build_artifacts: bool,
+        #[serde(default)]
+        pub artifact_dir_names: Vec<String>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
     pub struct InfoDbConfig {
         pub root: String,
     }
 
     #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
     pub struct LibMagicConfig {
-        pub db_file: String,
+        #[serde(default)]
+        pub db_file: Option<String>,
         pub used_for: Vec<String>,
     }
 }
 
+// maximum number of differing bits between perceptual hashes of near-duplicate images
+const DEFAULT_MAX_DISTANCE: u32 = 10;
+
+static CONFIG_FILE: &str = "config.yaml";
+// written by the init verb
+static CONFIG_TEMPLATE: &str = include_str!("config_template.yaml");
+
+fn config_home() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+}
+
+fn user_config_dir() -> Option<PathBuf> {
+    config_home().map(|config_home| config_home.join("classifiles"))
+}
+
+// config.yaml in the working directory, then in the user config dir
+fn config_file_path() -> Option<PathBuf> {
+    let local = PathBuf::from(CONFIG_FILE);
+    if local.exists() {
+        return Some(local);
+    }
+    user_config_dir().map(|dir| dir.join(CONFIG_FILE)).filter(|path| path.exists())
+}
+
+// syntax errors, unknown keys and missing fields are reported by serde with their line and column
 fn config_from_yaml(cfg_path: impl AsRef<Path>) -> Result<yaml_conf::Config, Box<dyn Error>> {
     let conf_str = fs::read_to_string(cfg_path)?;
     let conf: yaml_conf::Config = serde_yaml::from_str(&conf_str)?;
     Ok(conf)
 }
 
+// checks the referenced paths, all problems are reported at once
+fn validate_config(conf: &yaml_conf::Config) -> Result<(), String> {
+    let mut problems = Vec::new();
+    let mut check = |key: &str, result: Result<(), String>| {
+        if let Err(e) = result {
+            problems.push(format!("{}: {}", key, e));
+        }
+    };
+
+    check("mime_info_db.root", check_dir(Path::new(&conf.mime_info_db.root)));
+    if let Some(db_file) = &conf.libmagic.db_file {
+        check("libmagic.db_file", check_file(Path::new(db_file)));
+    }
+    if let Some(script) = &conf.routing_script {
+        check("routing_script", check_file(Path::new(script)));
+    }
+    if let Some(dir) = &conf.plugins_dir {
+        check("plugins_dir", check_dir(Path::new(dir)));
+    }
+    for name in conf.detectors.iter().flatten() {
+        check("detectors", name.parse::<Detector>().map(|_| ()));
+    }
+    if let Some(strategy) = &conf.detection_strategy {
+        check("detection_strategy", strategy.parse::<DetectionStrategy>().map(|_| ()));
+    }
+    if let Some(backend) = &conf.read_backend {
+        check("read_backend", backend.parse::<ReadBackend>().map(|_| ()));
+    }
+    if let Some(sanitization) = &conf.sanitize_names {
+        check("sanitize_names", sanitization.parse::<NameSanitization>().map(|_| ()));
+    }
+    if let Some(entropy) = conf.encrypted_min_entropy {
+        check("encrypted_min_entropy", check_min_entropy(entropy));
+    }
+    let sizes = [("read_buffer_size", conf.read_buffer_size.map(|size| size as u64)),
+        ("mmap_min_size", conf.mmap_min_size), ("pipeline_depth", conf.pipeline_depth.map(|depth| depth as u64)),
+        ("sniff_buffer_size", conf.sniff_buffer_size.map(|size| size as u64)),
+        ("classify_timeout_ms", conf.classify_timeout_ms), ("max_dir_entries", conf.max_dir_entries.map(|n| n as u64))];
+    for (key, size) in sizes.iter() {
+        if let Some(size) = size {
+            check(key, check_positive(*size));
+        }
+    }
+    for (i, rule) in conf.policy.iter().enumerate() {
+        check(&format!("policy[{}]", i), check_policy_rule(&rule.deny, &rule.allow));
+    }
+    for (i, rule) in conf.companions.iter().enumerate() {
+        check(&format!("companions[{}].extensions", i), check_companion_extensions(&rule.extensions));
+    }
+    if let Some(events) = &conf.photo_events {
+        if let Some(minutes) = events.max_gap_minutes {
+            let result = match minutes.checked_mul(60) {
+                Some(seconds) => check_positive(seconds),
+                None => Err(format!("{} is too large", minutes)),
+            };
+            check("photo_events.max_gap_minutes", result);
+        }
+        if let Some(min_photos) = events.min_photos {
+            check("photo_events.min_photos", check_positive(min_photos as u64));
+        }
+    }
+    if let Some(layout) = conf.geocoding.as_ref().and_then(|geocoding| geocoding.layout.as_deref()) {
+        check("geocoding.layout", check_geocoding_layout(layout));
+    }
+    if let Some(url) = &conf.notify.webhook {
+        check("notify.webhook", check_webhook_url(url));
+    }
+    if conf.clamav.as_ref().is_some_and(|clamav| clamav.socket.is_empty()) {
+        check("clamav.socket", Err("empty path".to_owned()));
+    }
+    if let Some(email) = &conf.notify.email {
+        if !email.smtp.starts_with("smtp://") && !email.smtp.starts_with("smtps://") {
+            check("notify.email.smtp", Err("expected an smtp:// or smtps:// URL".to_owned()));
+        }
+        if email.to.is_empty() {
+            check("notify.email.to", Err("no recipients".to_owned()));
+        }
+    }
+    validate_rules("", &conf.hooks, &conf.tags, &mut problems);
+
+    for (name, profile) in &conf.profiles {
+        let prefix = format!("profiles.{}.", name);
+        if let Some(Err(e)) = profile.base.as_deref().map(str::parse::<Profile>) {
+            problems.push(format!("{}base: {}", prefix, e));
+        }
+        // the values are checked by setting them on the defaults
+        #[allow(deprecated)]
+        let mut config = Config::default();
+        for (key, value) in &profile.set {
+            let result = yaml_value_string(value)
+                .map_err(|e| format!("{}: {}", key, e))
+                .and_then(|value| set_config_value(&mut config, key, &value));
+            if let Err(e) = result {
+                problems.push(format!("{}set: {}", prefix, e));
+            }
+        }
+        validate_rules(&prefix, profile.hooks.as_deref().unwrap_or_default(), profile.tags.as_deref().unwrap_or_default(),
+            &mut problems);
+    }
+
+    match problems.len() {
+        0 => Ok(()),
+        _ => Err(problems.join("\n")),
+    }
+}
+
+fn validate_rules(prefix: &str, hooks: &[yaml_conf::HookConfig], tags: &[yaml_conf::TagConfig], problems: &mut Vec<String>) {
+    for (i, hook) in hooks.iter().enumerate() {
+        if let Err(e) = check_hook_exec(&hook.exec) {
+            problems.push(format!("{}hooks[{}].exec: {}", prefix, i, e));
+        }
+    }
+    for (i, rule) in tags.iter().enumerate() {
+        if let Err(e) = classifiles::check_tag(&rule.tag) {
+            problems.push(format!("{}tags[{}].tag: {}", prefix, i, e));
+        }
+    }
+}
+
+// a profile value in the form of --set, lists comma separated
+fn yaml_value_string(value: &serde_yaml::Value) -> Result<String, String> {
+    use serde_yaml::Value;
+
+    let scalar = |value: &Value| match value {
+        Value::Null => Ok(String::new()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::String(s) => Ok(s.clone()),
+        _ => Err("expected a value or a list of values".to_owned()),
+    };
+    match value {
+        Value::Sequence(values) => Ok(values.iter().map(scalar).collect::<Result<Vec<_>, _>>()?.join(",")),
+        value => scalar(value),
+    }
+}
+
+// process exit codes
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_CONFIG: i32 = 3;
+const EXIT_INVALID_OUTPUT: i32 = 4;
+const EXIT_FILE_ERRORS: i32 = 5;
+const EXIT_POLICY: i32 = 6;
+
+fn min_entropy(key: &str, entropy: f64) -> Result<f64, String> {
+    check_min_entropy(entropy).map(|()| entropy).map_err(|e| format!("{}: {}", key, e))
+}
+
+fn buffer_size(key: &str, value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(format!("{}: expected a positive number of bytes", key)),
+    }
+}
+
+// sets a config value by its config.yaml key, lists are comma separated
+fn set_config_value(config: &mut Config, key: &str, value: &str) -> Result<(), String> {
+    let list = || value.split(',').filter(|v| !v.is_empty()).map(str::to_owned).collect::<Vec<_>>();
+    match key {
+        "mime_info_db.root" => config.mime_info_db_root = PathBuf::from(value),
+        "libmagic.db_file" => config.libmagic_db_file = Some(PathBuf::from(value)),
+        "libmagic.used_for" => config.libmagic_used_for = list(),
+        "create_output" => config.create_output = value.parse().map_err(|_| format!("{}: expected true or false", key))?,
+        "skip.paths" => config.skip_paths = list().into_iter().map(PathBuf::from).collect(),
+        "skip.dir_names" => config.skip_dir_names = list(),
+        "skip.build_artifacts" => config.skip_build_artifacts = value.parse()
+            .map_err(|_| format!("{}: expected true or false", key))?,
+        "skip.artifact_dir_names" => config.artifact_dir_names = list(),
+        "overrides_db" => config.overrides_db = Some(PathBuf::from(value)),
+        "routing_script" => config.routing_script = Some(PathBuf::from(value)),
+        "plugins_dir" => config.plugins_dir = Some(PathBuf::from(value)),
+        "detectors" => config.detectors = list().iter().map(|d| d.parse()).collect::<Result<_, _>>()?,
+        "detection_strategy" => config.detection_strategy = value.parse()?,
+        "read_buffer_size" => config.read_buffer_size = buffer_size(key, value)?,
+        "read_backend" => config.read_backend = value.parse()?,
+        "mmap_min_size" => config.mmap_min_size = Some(buffer_size(key, value)? as u64),
+        "pipeline_depth" => config.pipeline_depth = value.parse().ok().filter(|&depth| depth > 0)
+            .ok_or_else(|| format!("{}: expected a positive number", key))?,
+        "sniff_buffer_size" => config.sniff_buffer_size = buffer_size(key, value)?,
+        "classify_timeout_ms" => config.classify_timeout = value.parse().ok().filter(|&ms| ms > 0)
+            .map(|ms| Some(Duration::from_millis(ms)))
+            .ok_or_else(|| format!("{}: expected a positive number of milliseconds", key))?,
+        "max_dir_entries" => config.max_dir_entries = value.parse().ok().filter(|&entries| entries > 0)
+            .map(Some)
+            .ok_or_else(|| format!("{}: expected a positive number", key))?,
+        "sanitize_names" => config.sanitize_names = value.parse()?,
+        "lowercase_extensions" => config.lowercase_extensions = value.parse()
+            .map_err(|_| format!("{}: expected true or false", key))?,
+        "executable_paths" => config.executable_paths = list(),
+        "entropy" => config.entropy = value.parse().map_err(|_| format!("{}: expected true or false", key))?,
+        "encrypted_min_entropy" => config.encrypted_min_entropy = match value {
+            "" => None,
+            _ => Some(value.parse().map_err(|_| format!("{}: expected bits per byte from 0 to 8", key))
+                .and_then(|entropy| min_entropy(key, entropy))?),
+        },
+        "detect_encrypted_archives" => config.detect_encrypted_archives = value.parse()
+            .map_err(|_| format!("{}: expected true or false", key))?,
+        "group_split_archives" => config.group_split_archives = value.parse()
+            .map_err(|_| format!("{}: expected true or false", key))?,
+        "notify.exec" => config.notify.exec = list(),
+        "notify.webhook" => config.notify.webhook = Some(value.to_owned()).filter(|url| !url.is_empty()),
+        "notify.desktop" => config.notify.desktop = value.parse().map_err(|_| format!("{}: expected true or false", key))?,
+        "notify.email.smtp" => config.notify.email.get_or_insert_with(Email::default).smtp = value.to_owned(),
+        "notify.email.from" => config.notify.email.get_or_insert_with(Email::default).from = value.to_owned(),
+        "notify.email.to" => config.notify.email.get_or_insert_with(Email::default).to = list(),
+        "notify.email.html" => config.notify.email.get_or_insert_with(Email::default).html = value.parse()
+            .map_err(|_| format!("{}: expected true or false", key))?,
+        "clamav.socket" => config.clamav.get_or_insert_with(Clamav::default).socket = PathBuf::from(value),
+        "clamav.mime" => config.clamav.get_or_insert_with(Clamav::default).mime = list(),
+        "clamav.quarantine" => config.clamav.get_or_insert_with(Clamav::default).quarantine =
+            Some(PathBuf::from(value)).filter(|dir| !dir.as_os_str().is_empty()),
+        "photo_events.mime" => config.photo_events.get_or_insert_with(PhotoEvents::default).mime = list(),
+        "photo_events.max_gap_minutes" => config.photo_events.get_or_insert_with(PhotoEvents::default).max_gap =
+            value.parse().ok().filter(|&minutes| minutes > 0).and_then(|minutes: u64| minutes.checked_mul(60))
+                .map(Duration::from_secs).ok_or_else(|| format!("{}: expected a positive number of minutes", key))?,
+        "photo_events.min_photos" => config.photo_events.get_or_insert_with(PhotoEvents::default).min_photos =
+            value.parse().ok().filter(|&photos| photos > 0)
+                .ok_or_else(|| format!("{}: expected a positive number", key))?,
+        "geocoding.mime" => config.geocoding.get_or_insert_with(Geocoding::default).mime = list(),
+        "geocoding.layout" => config.geocoding.get_or_insert_with(Geocoding::default).layout = match value {
+            "" => None,
+            _ => Some(check_geocoding_layout(value).map(|_| value.to_owned()).map_err(|e| format!("{}: {}", key, e))?),
+        },
+        _ => return Err(format!("unknown config key {}", key)),
+    }
+    Ok(())
+}
+
+// environment variables overriding config values, e.g. for containers without a config file
+static CONFIG_ENV_VARS: &[(&str, &str)] = &[
+    ("CLASSIFILES_MIME_ROOT", "mime_info_db.root"),
+    ("CLASSIFILES_MAGIC_DB", "libmagic.db_file"),
+    ("CLASSIFILES_MAGIC_USED_FOR", "libmagic.used_for"),
+    ("CLASSIFILES_CREATE_OUTPUT", "create_output"),
+    ("CLASSIFILES_SKIP_PATHS", "skip.paths"),
+    ("CLASSIFILES_SKIP_DIR_NAMES", "skip.dir_names"),
+    ("CLASSIFILES_SKIP_BUILD_ARTIFACTS", "skip.build_artifacts"),
+    ("CLASSIFILES_SKIP_ARTIFACT_DIR_NAMES", "skip.artifact_dir_names"),
+    ("CLASSIFILES_OVERRIDES_DB", "overrides_db"),
+    ("CLASSIFILES_ROUTING_SCRIPT", "routing_script"),
+    ("CLASSIFILES_PLUGINS_DIR", "plugins_dir"),
+    ("CLASSIFILES_DETECTORS", "detectors"),
+    ("CLASSIFILES_DETECTION_STRATEGY", "detection_strategy"),
+    ("CLASSIFILES_READ_BUFFER_SIZE", "read_buffer_size"),
+    ("CLASSIFILES_READ_BACKEND", "read_backend"),
+    ("CLASSIFILES_MMAP_MIN_SIZE", "mmap_min_size"),
+    ("CLASSIFILES_PIPELINE_DEPTH", "pipeline_depth"),
+    ("CLASSIFILES_SNIFF_BUFFER_SIZE", "sniff_buffer_size"),
+    ("CLASSIFILES_CLASSIFY_TIMEOUT_MS", "classify_timeout_ms"),
+    ("CLASSIFILES_MAX_DIR_ENTRIES", "max_dir_entries"),
+    ("CLASSIFILES_SANITIZE_NAMES", "sanitize_names"),
+    ("CLASSIFILES_LOWERCASE_EXTENSIONS", "lowercase_extensions"),
+    ("CLASSIFILES_EXECUTABLE_PATHS", "executable_paths"),
+    ("CLASSIFILES_ENTROPY", "entropy"),
+    ("CLASSIFILES_ENCRYPTED_MIN_ENTROPY", "encrypted_min_entropy"),
+    ("CLASSIFILES_DETECT_ENCRYPTED_ARCHIVES", "detect_encrypted_archives"),
+    ("CLASSIFILES_GROUP_SPLIT_ARCHIVES", "group_split_archives"),
+    ("CLASSIFILES_NOTIFY_EXEC", "notify.exec"),
+    ("CLASSIFILES_NOTIFY_WEBHOOK", "notify.webhook"),
+    ("CLASSIFILES_NOTIFY_DESKTOP", "notify.desktop"),
+    ("CLASSIFILES_NOTIFY_EMAIL_SMTP", "notify.email.smtp"),
+    ("CLASSIFILES_NOTIFY_EMAIL_FROM", "notify.email.from"),
+    ("CLASSIFILES_NOTIFY_EMAIL_TO", "notify.email.to"),
+    ("CLASSIFILES_NOTIFY_EMAIL_HTML", "notify.email.html"),
+    ("CLASSIFILES_CLAMAV_SOCKET", "clamav.socket"),
+    ("CLASSIFILES_CLAMAV_MIME", "clamav.mime"),
+    ("CLASSIFILES_CLAMAV_QUARANTINE", "clamav.quarantine"),
+    ("CLASSIFILES_PHOTO_EVENTS_MIME", "photo_events.mime"),
+    ("CLASSIFILES_PHOTO_EVENTS_MAX_GAP_MINUTES", "photo_events.max_gap_minutes"),
+    ("CLASSIFILES_PHOTO_EVENTS_MIN_PHOTOS", "photo_events.min_photos"),
+    ("CLASSIFILES_GEOCODING_MIME", "geocoding.mime"),
+    ("CLASSIFILES_GEOCODING_LAYOUT", "geocoding.layout"),
+];
+
+fn apply_env_overrides(config: &mut Config) {
+    for (var, key) in CONFIG_ENV_VARS {
+        if let Ok(value) = env::var(var) {
+            set_config_value(config, key, &value).unwrap_or_else(|e| {
+                eprintln!("Error: invalid {}: {}", var, e);
+                process::exit(EXIT_CONFIG)
+            });
+        }
+    }
+}
+
+// --profile NAME, a profile of config.yaml or a built-in one
+enum SelectedProfile {
+    BuiltIn(Profile),
+    Named(String, yaml_conf::ProfileConfig),
+}
+
+impl SelectedProfile {
+    fn select(name: &str, mut profiles: BTreeMap<String, yaml_conf::ProfileConfig>) -> Self {
+        if let Some(profile) = profiles.remove(name) {
+            return SelectedProfile::Named(name.to_owned(), profile);
+        }
+        name.parse().map(SelectedProfile::BuiltIn).unwrap_or_else(|_| {
+            let names: Vec<&str> = PROFILE_NAMES.iter().copied().chain(profiles.keys().map(String::as_str)).collect();
+            eprintln!("Error: invalid --profile: invalid profile {}, valid profiles are: {}", name, names.join(", "));
+            process::exit(EXIT_USAGE)
+        })
+    }
+
+    // checked by validate_config
+    fn base(&self) -> Option<Profile> {
+        match self {
+            SelectedProfile::BuiltIn(profile) => Some(*profile),
+            SelectedProfile::Named(_, profile) => profile.base.as_deref().and_then(|base| base.parse().ok()),
+        }
+    }
+
+    fn apply_config(&self, config: &mut Config) {
+        if let Some(base) = self.base() {
+            base.apply_config(config);
+        }
+        let (name, profile) = match self {
+            SelectedProfile::BuiltIn(_) => return,
+            SelectedProfile::Named(name, profile) => (name, profile),
+        };
+        if let Some(hooks) = &profile.hooks {
+            config.hooks = hooks_from(hooks.clone());
+        }
+        if let Some(tags) = &profile.tags {
+            config.tag_rules = tag_rules_from(tags.clone());
+        }
+        for (key, value) in &profile.set {
+            if let Err(e) = yaml_value_string(value).and_then(|value| set_config_value(config, key, &value)) {
+                eprintln!("Error: invalid profile {}: {}", name, e);
+                process::exit(EXIT_CONFIG);
+            }
+        }
+    }
+
+    // the filters only apply when none are given on the command line
+    fn apply_params(&self, params: &mut Params) {
+        if let Some(base) = self.base() {
+            base.apply_params(params);
+        }
+        if let SelectedProfile::Named(_, profile) = self {
+            if params.only_mime.is_empty() && params.skip_mime.is_empty() {
+                params.only_mime = profile.only_mime.clone();
+                params.skip_mime = profile.skip_mime.clone();
+            }
+        }
+    }
+}
+
+// config.yaml if present, defaults otherwise, with environment overrides and then --set KEY=VALUE on top;
+// an invalid config.yaml is fatal unless --config-fallback is given
+// precedence: config.yaml, environment, --profile, --set
+fn load_config(args: &VerbArgs) -> Config {
+    load_config_with_profile(args).0
+}
+
+// the configuration with the selected profile applied, and that profile for the options of scan and sync
+fn load_config_with_profile(args: &VerbArgs) -> (Config, Option<SelectedProfile>) {
+    let (mut config, profiles) = load_config_file(args);
+    apply_env_overrides(&mut config);
+    let profile = args.value("--profile").map(|name| SelectedProfile::select(name, profiles));
+    if let Some(profile) = &profile {
+        profile.apply_config(&mut config);
+    }
+
+    for assignment in args.values("--set") {
+        let result = match assignment.split_once('=') {
+            Some((key, value)) => set_config_value(&mut config, key.trim(), value),
+            None => Err(format!("expected KEY=VALUE, got {}", assignment)),
+        };
+        if let Err(e) = result {
+            eprintln!("Error: invalid --set: {}", e);
+            process::exit(EXIT_USAGE);
+        }
+    }
+    (config, profile)
+}
+
+fn hooks_from(hooks: Vec<yaml_conf::HookConfig>) -> Vec<Hook> {
+    hooks.into_iter().map(|h| Hook{mime: h.mime, exec: h.exec}).collect()
+}
+
+fn tag_rules_from(tags: Vec<yaml_conf::TagConfig>) -> Vec<TagRule> {
+    tags.into_iter().map(|t| TagRule{tag: t.tag, mime: t.mime, path: t.path, min_size: t.min_size, max_size: t.max_size})
+        .collect()
+}
+
+fn policy_from(policy: Vec<yaml_conf::PolicyConfig>) -> Vec<PolicyRule> {
+    policy.into_iter().map(|p| PolicyRule{name: p.name, path: p.path, deny: p.deny, allow: p.allow, max_count: p.max_count})
+        .collect()
+}
+
+fn companions_from(companions: Vec<yaml_conf::CompanionConfig>) -> Vec<CompanionRule> {
+    companions.into_iter().map(|c| CompanionRule{primary: c.primary, extensions: c.extensions}).collect()
+}
+
+// the configuration and the named profiles of config.yaml
+fn load_config_file(args: &VerbArgs) -> (Config, BTreeMap<String, yaml_conf::ProfileConfig>) {
+    let config_path = match config_file_path() {
+        Some(path) => path,
+        None => {
+            eprintln!("Using default configuration");
+            #[allow(deprecated)]
+            return (Config::default(), BTreeMap::new());
+        }
+    };
+
+    let conf = config_from_yaml(&config_path).and_then(|conf| {
+        validate_config(&conf)?;
+        Ok(conf)
+    });
+
+    match conf {
+        Ok(conf) => {
+            eprintln!("Using configuration from {}", config_path.display());
+            #[allow(deprecated)]
+            let defaults = Config::default();
+            let (skip_paths, skip_dir_names, skip_build_artifacts, artifact_dir_names) = match conf.skip {
+                Some(skip) => (skip.paths.into_iter().map(PathBuf::from).collect(), skip.dir_names, skip.build_artifacts,
+                    skip.artifact_dir_names),
+                None => (defaults.skip_paths, defaults.skip_dir_names, defaults.skip_build_artifacts,
+                    defaults.artifact_dir_names),
+            };
+            let config = Config{
+                mime_info_db_root: PathBuf::from(conf.mime_info_db.root),
+                libmagic_db_file: conf.libmagic.db_file.map(PathBuf::from).or(defaults.libmagic_db_file),
+                libmagic_used_for: conf.libmagic.used_for,
+                create_output: conf.create_output,
+                skip_paths,
+                skip_dir_names,
+                skip_build_artifacts,
+                artifact_dir_names,
+                overrides_db: conf.overrides_db.map(PathBuf::from).or(defaults.overrides_db),
+                hooks: hooks_from(conf.hooks),
+                tag_rules: tag_rules_from(conf.tags),
+                policy: policy_from(conf.policy),
+                routing_script: conf.routing_script.map(PathBuf::from),
+                plugins_dir: conf.plugins_dir.map(PathBuf::from),
+                // names were checked by validate_config
+                detectors: conf.detectors.map_or(defaults.detectors, |names| {
+                    names.iter().filter_map(|name| name.parse().ok()).collect()
+                }),
+                detection_strategy: conf.detection_strategy.and_then(|s| s.parse().ok()).unwrap_or_default(),
+                read_buffer_size: conf.read_buffer_size.unwrap_or(defaults.read_buffer_size),
+                read_backend: conf.read_backend.and_then(|s| s.parse().ok()).unwrap_or_default(),
+                mmap_min_size: conf.mmap_min_size,
+                pipeline_depth: conf.pipeline_depth.unwrap_or(defaults.pipeline_depth),
+                sniff_buffer_size: conf.sniff_buffer_size.unwrap_or(defaults.sniff_buffer_size),
+                classify_timeout: conf.classify_timeout_ms.map(Duration::from_millis),
+                max_dir_entries: conf.max_dir_entries,
+                sanitize_names: conf.sanitize_names.and_then(|s| s.parse().ok()).unwrap_or_default(),
+                lowercase_extensions: conf.lowercase_extensions,
+                executable_paths: conf.executable_paths.unwrap_or(defaults.executable_paths),
+                entropy: conf.entropy,
+                encrypted_min_entropy: conf.encrypted_min_entropy,
+                detect_encrypted_archives: conf.detect_encrypted_archives,
+                group_split_archives: conf.group_split_archives,
+                companions: companions_from(conf.companions),
+                photo_events: conf.photo_events.map(|e| {
+                    let defaults = PhotoEvents::default();
+                    PhotoEvents{
+                        mime: e.mime.unwrap_or(defaults.mime),
+                        // checked by validate_config
+                        max_gap: e.max_gap_minutes.and_then(|minutes| minutes.checked_mul(60)).map(Duration::from_secs)
+                            .unwrap_or(defaults.max_gap),
+                        min_photos: e.min_photos.unwrap_or(defaults.min_photos),
+                    }
+                }),
+                geocoding: conf.geocoding.map(|g| Geocoding{mime: g.mime.unwrap_or_else(|| Geocoding::default().mime),
+                    layout: g.layout}),
+                notify: Notify{exec: conf.notify.exec, webhook: conf.notify.webhook, desktop: conf.notify.desktop,
+                    email: conf.notify.email.map(|e| Email{smtp: e.smtp, from: e.from, to: e.to, html: e.html})},
+                clamav: conf.clamav.map(|c| Clamav{socket: PathBuf::from(c.socket), mime: c.mime,
+                    quarantine: c.quarantine.map(PathBuf::from)}),
+            };
+            (config, conf.profiles)
+        }
+        Err(e) if args.flag("--config-fallback") => {
+            eprintln!("Warning: invalid {}, using default configuration:\n{}", config_path.display(), e);
+            #[allow(deprecated)]
+            (Config::default(), BTreeMap::new())
+        }
+        Err(e) => {
+            eprintln!("Error: invalid {}:\n{}", config_path.display(), e);
+            process::exit(EXIT_CONFIG)
+        }
+    }
+}
+
+// system units for root, user units ($XDG_CONFIG_HOME/systemd/user) otherwise
+fn default_unit_dir() -> Option<PathBuf> {
+    match unsafe { libc::geteuid() } {
+        0 => Some(PathBuf::from("/etc/systemd/system")),
+        _ => config_home().map(|config_home| config_home.join("systemd").join("user")),
+    }
+}
+
+// writes the units of a scheduled sync (and a mount of the output with --mount) with the config in effect now
+fn install_service(args: &VerbArgs, input_path: &Path, output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let cwd = env::current_dir()?;
+    let mountpoint = args.value("--mount").map(|mountpoint| cwd.join(mountpoint));
+    let program = env::current_exe()?;
+    let (input, output) = (cwd.join(input_path), cwd.join(output_path));
+    for path in [&program, &input, &output].iter().copied().chain(&mountpoint) {
+        if path.to_str().is_none() {
+            return Err(format!("{} is not valid UTF-8", path.display()).into());
+        }
+    }
+
+    // a config.yaml found in the working directory stays there, the user config is found through XDG_CONFIG_HOME
+    // also when the unit runs as another user
+    let mut env_vars = Vec::new();
+    let working_dir = match config_file_path() {
+        Some(path) if path.is_relative() => Some(cwd.clone()),
+        Some(path) => {
+            if let Some(config_home) = path.parent().and_then(Path::parent) {
+                env_vars.push(("XDG_CONFIG_HOME".to_owned(), config_home.display().to_string()));
+            }
+            None
+        }
+        None => None,
+    };
+    env_vars.extend(CONFIG_ENV_VARS.iter().filter_map(|(var, _)| env::var(var).ok().map(|value| ((*var).to_owned(), value))));
+
+    let unit_dir = args.value("--unit-dir").map(PathBuf::from).or_else(default_unit_dir)
+        .ok_or("no home directory to put the units in, pass --unit-dir")?;
+    let service = service::Service{
+        name: args.value("--name").unwrap_or("classifiles").to_owned(),
+        program,
+        input,
+        output,
+        schedule: args.value("--schedule").unwrap_or("weekly").to_owned(),
+        mountpoint,
+        args: args.forwarded(&["--config-fallback", "--set"]),
+        working_dir,
+        env: env_vars,
+    };
+    let units = service.units();
+    for (name, _) in &units {
+        let path = unit_dir.join(name);
+        if path.exists() && !args.flag("--force") {
+            return Err(format!("{} already exists, pass --force to overwrite it", path.display()).into());
+        }
+    }
+    fs::create_dir_all(&unit_dir)?;
+    for (name, contents) in &units {
+        let path = unit_dir.join(name);
+        fs::write(&path, contents).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+        eprintln!("Unit written to {}", path.display());
+    }
+    let systemctl = match unit_dir.starts_with("/etc") {
+        true => "systemctl",
+        false => "systemctl --user",
+    };
+    eprintln!("Enable with: {} daemon-reload && {} enable --now {}-sync.timer", systemctl, systemctl, service.name);
+    Ok(())
+}
+
+fn exit_with_error(e: Box<dyn Error>) -> ! {
+    eprintln!("Error: {}", e);
+    let code = match e.downcast_ref::<ClassifierError>().map(ClassifierError::kind) {
+        Some(ErrorKind::InvalidOutput) => EXIT_INVALID_OUTPUT,
+        Some(ErrorKind::Config) => EXIT_CONFIG,
+        Some(ErrorKind::FileErrors) => EXIT_FILE_ERRORS,
+        Some(ErrorKind::Policy) => EXIT_POLICY,
+        None => EXIT_FAILURE,
+    };
+    process::exit(code)
+}
+
+// what the shell completion scripts offer for an option value or a positional argument
+#[derive(Clone, Copy)]
+enum ValueKind {
+    Path,
+    Words(&'static [&'static str]),
+    // types of the configured mime info db, listed by the mime-types verb
+    MimeType,
+    Any,
+}
+
+// command line of a verb, shared by the argument parser and the completion scripts
+struct VerbSpec {
+    name: &'static str,
+    flags: &'static [&'static str],
+    value_opts: &'static [(&'static str, ValueKind)],
+    positional: ValueKind,
+}
+
+static LINK_MODES: &[&str] = &["symlink", "hardlink", "copy"];
+static SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+// options accepted by every verb
+static COMMON_FLAGS: &[&str] = &["-q", "--quiet", "-v", "-vv"];
+static COMMON_VALUE_OPTS: &[(&str, ValueKind)] = &[
+    ("--log-backend", ValueKind::Words(&["term", "syslog", "journald"])),
+    ("--log-file", ValueKind::Path),
+];
+
+// shared by scan and sync
+static SCAN_FLAGS: &[&str] = &["--phash", "--previews", "--create-output", "--exclude-output", "--follow-links",
+    "--unique-dirs", "--one-file-system", "--relative-links", "--interactive", "--learn", "--config-fallback", "--uti",
+    "--hash", "--merge", "--flat", "--sandbox", "--isolate", "--skip-capacity-check",
+    "--case-insensitive", "--gitignore", "--checkpoint"];
+static SCAN_VALUE_OPTS: &[(&str, ValueKind)] = &[
+    ("--metrics-addr", ValueKind::Any),
+    ("--link-mode", ValueKind::Words(LINK_MODES)),
+    ("--cross-device-fallback", ValueKind::Words(&["symlink", "copy", "none"])),
+    ("--save-overrides", ValueKind::Path),
+    ("--only-mime", ValueKind::MimeType),
+    ("--skip-mime", ValueKind::MimeType),
+    ("--set", ValueKind::Any),
+    ("--profile", ValueKind::Words(PROFILE_NAMES)),
+    ("--checksums", ValueKind::Words(&["gnu", "bsd"])),
+    ("--audit-log", ValueKind::Path),
+    ("--upload", ValueKind::Any),
+    ("--extra-output", ValueKind::Path),
+    ("--output-split", ValueKind::Words(&["round-robin", "mime-group"])),
+    ("--files-from", ValueKind::Path),
+    ("--from-manifest", ValueKind::Path),
+    ("--input-archive", ValueKind::Path),
+    ("--output-archive", ValueKind::Path),
+];
+
+static SCAN: VerbSpec = VerbSpec{
+    name: "scan",
+    flags: SCAN_FLAGS,
+    value_opts: SCAN_VALUE_OPTS,
+    positional: ValueKind::Path,
+};
+static SYNC: VerbSpec = VerbSpec{
+    name: "sync",
+    flags: SCAN_FLAGS,
+    value_opts: SCAN_VALUE_OPTS,
+    positional: ValueKind::Path,
+};
+static BACKUP: VerbSpec = VerbSpec{
+    name: "backup",
+    flags: &["--create-output", "--exclude-output", "--unique-dirs", "--one-file-system", "--check-links", "--stubs",
+        "--content", "--hash", "--link-index", "--case-insensitive"],
+    value_opts: &[],
+    positional: ValueKind::Path,
+};
+static RESTORE: VerbSpec = VerbSpec{
+    name: "restore",
+    flags: &["--create-output", "--exclude-output", "--unique-dirs", "--one-file-system", "--skip-broken-links",
+        "--case-insensitive"],
+    value_opts: &[("--link-targets", ValueKind::Words(&["reject", "rewrite", "allow"]))],
+    positional: ValueKind::Path,
+};
+static DUPES: VerbSpec = VerbSpec{
+    name: "dupes",
+    flags: &["--fuzzy"],
+    value_opts: &[("--max-distance", ValueKind::Any)],
+    positional: ValueKind::Path,
+};
+static REPORT: VerbSpec = VerbSpec{
+    name: "report",
+    flags: &["--html", "--security", "--config-fallback"],
+    value_opts: &[("--upload", ValueKind::Any), ("--treemap", ValueKind::Words(&["nested", "ncdu"])), ("--set", ValueKind::Any)],
+    positional: ValueKind::Path,
+};
+static RELINK: VerbSpec = VerbSpec{
+    name: "relink",
+    flags: &[],
+    value_opts: &[("--old-prefix", ValueKind::Path), ("--new-prefix", ValueKind::Path)],
+    positional: ValueKind::Path,
+};
+static APPLY_MANIFEST: VerbSpec = VerbSpec{
+    name: "apply-manifest",
+    flags: &["--create-output", "--relative-links", "--uti", "--skip-capacity-check", "--case-insensitive"],
+    value_opts: &[("--link-mode", ValueKind::Words(LINK_MODES))],
+    positional: ValueKind::Path,
+};
+static LEARN: VerbSpec = VerbSpec{
+    name: "learn",
+    flags: &["--config-fallback"],
+    value_opts: &[("--set", ValueKind::Any)],
+    positional: ValueKind::Path,
+};
+static COMPLETIONS: VerbSpec = VerbSpec{
+    name: "completions",
+    flags: &[],
+    value_opts: &[],
+    positional: ValueKind::Words(SHELLS),
+};
+
+static MIME_TYPES: VerbSpec = VerbSpec{
+    name: "mime-types",
+    flags: &["--config-fallback"],
+    value_opts: &[("--set", ValueKind::Any)],
+    positional: ValueKind::Any,
+};
+
+static INIT: VerbSpec = VerbSpec{
+    name: "init",
+    flags: &["--force"],
+    value_opts: &[],
+    positional: ValueKind::Any,
+};
+
+static INSTALL_SERVICE: VerbSpec = VerbSpec{
+    name: "install-service",
+    flags: &["--force", "--config-fallback"],
+    value_opts: &[("--schedule", ValueKind::Any), ("--mount", ValueKind::Path), ("--name", ValueKind::Any),
+        ("--unit-dir", ValueKind::Path), ("--set", ValueKind::Any)],
+    positional: ValueKind::Path,
+};
+
+static WHEREIS: VerbSpec = VerbSpec{
+    name: "whereis",
+    flags: &[],
+    value_opts: &[],
+    positional: ValueKind::Path,
+};
+
+static TAG: VerbSpec = VerbSpec{
+    name: "tag",
+    flags: &[],
+    value_opts: &[("--add", ValueKind::Any), ("--remove", ValueKind::Any)],
+    positional: ValueKind::Path,
+};
+
+static FIND: VerbSpec = VerbSpec{
+    name: "find",
+    flags: &["--links"],
+    value_opts: &[
+        ("--mime", ValueKind::MimeType),
+        ("--name", ValueKind::Any),
+        ("--min-size", ValueKind::Any),
+        ("--max-size", ValueKind::Any),
+        ("--newer", ValueKind::Any),
+        ("--older", ValueKind::Any),
+        ("--tag", ValueKind::Any),
+    ],
+    positional: ValueKind::Path,
+};
+
+static MOUNT: VerbSpec = VerbSpec{
+    name: "mount",
+    flags: &["--config-fallback"],
+    value_opts: &[("--set", ValueKind::Any)],
+    positional: ValueKind::Path,
+};
+
+static HISTORY: VerbSpec = VerbSpec{
+    name: "history",
+    flags: &[],
+    value_opts: &[],
+    positional: ValueKind::Path,
+};
+
+static TREND: VerbSpec = VerbSpec{
+    name: "trend",
+    flags: &[],
+    value_opts: &[("--last", ValueKind::Any)],
+    positional: ValueKind::Path,
+};
+
+static EXPORT: VerbSpec = VerbSpec{
+    name: "export",
+    flags: &[],
+    value_opts: &[("--format", ValueKind::Words(&["csv", "parquet"]))],
+    positional: ValueKind::Path,
+};
+
+static VERIFY: VerbSpec = VerbSpec{
+    name: "verify",
+    flags: &["--checksums", "--config-fallback"],
+    value_opts: &[("--set", ValueKind::Any), ("--audit-log", ValueKind::Path)],
+    positional: ValueKind::Path,
+};
+
+static CLASSIFY: VerbSpec = VerbSpec{
+    name: "classify",
+    flags: &["--stream", "--json", "--config-fallback"],
+    value_opts: &[("--set", ValueKind::Any), ("--profile", ValueKind::Words(PROFILE_NAMES))],
+    positional: ValueKind::Path,
+};
+
+static CARVE_MAP: VerbSpec = VerbSpec{
+    name: "carve-map",
+    flags: &["--all", "--json", "--config-fallback"],
+    value_opts: &[("--set", ValueKind::Any), ("--profile", ValueKind::Words(PROFILE_NAMES))],
+    positional: ValueKind::Path,
+};
+
+// internal, started by scan --isolate
+static CLASSIFY_WORKER: VerbSpec = VerbSpec{
+    name: "classify-worker",
+    flags: &["--config-fallback", "--sandbox"],
+    value_opts: &[("--set", ValueKind::Any), ("--profile", ValueKind::Words(PROFILE_NAMES))],
+    positional: ValueKind::Any,
+};
+// what a classify-worker needs to load the same configuration and log like the scan; not --log-file, the workers
+// would each rotate it from under the scan, they log to stderr instead
+static WORKER_OPTS: &[&str] = &["--config-fallback", "--set", "--profile", "-q", "--quiet", "-v", "-vv",
+    "--log-backend"];
+
+static VERBS: &[&VerbSpec] = &[
+    &SCAN, &SYNC, &BACKUP, &RESTORE, &DUPES, &REPORT, &RELINK, &APPLY_MANIFEST, &LEARN, &COMPLETIONS, &MIME_TYPES,
+    &INIT, &WHEREIS, &VERIFY, &EXPORT, &TAG, &FIND, &MOUNT, &HISTORY, &TREND, &INSTALL_SERVICE, &CLASSIFY,
+    &CARVE_MAP,
+];
+
+// positional arguments and options following a verb
+struct VerbArgs {
+    positional: std::vec::IntoIter<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl VerbArgs {
+    fn parse(args: impl Iterator<Item = String>, spec: &VerbSpec) -> Self {
+        let mut args = args;
+        let mut positional = Vec::new();
+        let mut options = Vec::new();
+
+        let takes_value = |arg: &str| spec.value_opts.iter().chain(COMMON_VALUE_OPTS).any(|(opt, _)| *opt == arg);
+
+        while let Some(arg) = args.next() {
+            if spec.flags.contains(&arg.as_str()) || COMMON_FLAGS.contains(&arg.as_str()) {
+                options.push((arg, None));
+            } else if takes_value(&arg) {
+                let value = args.next().unwrap_or_else(|| {
+                    eprintln!("Error: missing value for option {}", arg);
+                    process::exit(EXIT_USAGE)
+                });
+                options.push((arg, Some(value)));
+            } else if arg.starts_with('-') && arg.len() > 1 {
+                eprintln!("Error: unknown option {}", arg);
+                process::exit(EXIT_USAGE)
+            } else {
+                positional.push(arg);
+            }
+        }
+
+        Self{positional: positional.into_iter(), options}
+    }
+
+    fn path(&mut self, name: &str) -> PathBuf {
+        PathBuf::from(self.positional.next().unwrap_or_else(|| {
+            eprintln!("Error: missing {} argument", name);
+            process::exit(EXIT_USAGE)
+        }))
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(opt, _)| opt == name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev()
+            .find(|(opt, _)| opt == name)
+            .and_then(|(_, val)| val.as_deref())
+    }
+
+    // all values of a repeatable option
+    fn values(&self, name: &str) -> Vec<String> {
+        self.options.iter()
+            .filter(|(opt, _)| opt == name)
+            .filter_map(|(_, val)| val.clone())
+            .collect()
+    }
+
+    // the given options as they were passed, for a child process
+    fn forwarded(&self, names: &[&str]) -> Vec<String> {
+        self.options.iter()
+            .filter(|(opt, _)| names.contains(&opt.as_str()))
+            .flat_map(|(opt, val)| std::iter::once(opt.clone()).chain(val.clone()))
+            .collect()
+    }
+
+    fn parsed<T>(&self, name: &str) -> Option<T>
+        where T: std::str::FromStr, T::Err: std::fmt::Display {
+
+        self.value(name).map(|val| val.parse().unwrap_or_else(|e| {
+            eprintln!("Error: invalid {}: {}", name, e);
+            process::exit(EXIT_USAGE)
+        }))
+    }
+
+    fn logger(&self) -> slog::Logger {
+        self.logger_to(logging::Terminal::Stdout)
+    }
+
+    // for verbs whose stdout is read by another program, e.g. the answers of a classify-worker
+    fn stderr_logger(&self) -> slog::Logger {
+        self.logger_to(logging::Terminal::Stderr)
+    }
+
+    fn logger_to(&self, terminal: logging::Terminal) -> slog::Logger {
+        let backend = self.value("--log-backend").unwrap_or("term");
+        let level = if self.flag("-q") || self.flag("--quiet") {
+            slog::Level::Warning
+        } else if self.flag("-vv") {
+            slog::Level::Trace
+        } else if self.flag("-v") {
+            slog::Level::Debug
+        } else {
+            slog::Level::Info
+        };
+
+        let log_file = self.value("--log-file").map(Path::new);
+
+        logging::root_logger(backend, terminal, log_file, level).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(EXIT_CONFIG)
+        })
+    }
+}
+
 fn main() {
     let mut args = env::args();
     // skip program name
@@ -37,89 +1171,433 @@ fn main() {
 
     let verb = args.next().unwrap_or("".to_owned());
 
-    let decorator = slog_term::TermDecorator::new().stdout().build();
-    let drain = slog_term::CompactFormat::new(decorator).build().fuse();
-    let async_drain = slog_async::Async::new(drain).build().fuse();
+    match verb.as_str() {
+        "scan" | "sync" => {
+            let mut args = VerbArgs::parse(args, if verb == "scan" { &SCAN } else { &SYNC });
+            // the archive takes the place of INPUT_DIR
+            let input_path = match args.value("--input-archive").map(PathBuf::from) {
+                Some(archive) => archive,
+                None => args.path("input path"),
+            };
+            let output_path = args.path("output path");
+            let root_log = args.logger();
+
+            let mut params = Params{
+                input_path,
+                output_path,
+                perceptual_hash: args.flag("--phash"),
+                previews: args.flag("--previews"),
+                metrics_addr: args.value("--metrics-addr").map(str::to_owned),
+                create_output: args.flag("--create-output"),
+                exclude_nested_output: args.flag("--exclude-output"),
+                follow_links: args.flag("--follow-links"),
+                unique_dirs: args.flag("--unique-dirs"),
+                one_file_system: args.flag("--one-file-system"),
+                gitignore: args.flag("--gitignore"),
+                checkpoint: args.flag("--checkpoint"),
+                files_from: args.value("--files-from").map(PathBuf::from),
+                from_manifest: args.value("--from-manifest").map(PathBuf::from),
+                input_archive: args.value("--input-archive").is_some(),
+                output_archive: args.value("--output-archive").map(PathBuf::from),
+                link_mode: args.parsed("--link-mode").unwrap_or_default(),
+                relative_links: args.flag("--relative-links"),
+                interactive: args.flag("--interactive"),
+                save_overrides: args.value("--save-overrides").map(PathBuf::from),
+                learn: args.flag("--learn"),
+                only_mime: args.values("--only-mime"),
+                skip_mime: args.values("--skip-mime"),
+                uti: args.flag("--uti"),
+                content_hash: args.flag("--hash"),
+                merge: args.flag("--merge"),
+                flat: args.flag("--flat"),
+                checksums: args.parsed::<ChecksumFormat>("--checksums"),
+                audit_log: args.value("--audit-log").map(PathBuf::from),
+                log_file: args.value("--log-file").map(PathBuf::from),
+                upload: args.value("--upload").map(str::to_owned),
+                extra_outputs: args.values("--extra-output").into_iter().map(PathBuf::from).collect(),
+                output_split: args.parsed("--output-split").unwrap_or_default(),
+                skip_capacity_check: args.flag("--skip-capacity-check"),
+                case_insensitive: args.flag("--case-insensitive"),
+                sandbox: args.flag("--sandbox"),
+                isolate: args.flag("--isolate"),
+                worker_args: args.forwarded(WORKER_OPTS),
+                cross_device_fallback: match args.value("--cross-device-fallback") {
+                    Some("none") => None,
+                    Some(_) => args.parsed("--cross-device-fallback"),
+                    None => Some(LinkMode::Symlink),
+                },
+                ..Default::default()
+            };
+            let (config, profile) = load_config_with_profile(&args);
+            if let Some(profile) = &profile {
+                profile.apply_params(&mut params);
+            }
 
-    let root_log = slog::Logger::root(async_drain, o!());
+            let result = match verb.as_str() {
+                "scan" => classifiles::run_scan(config, params, &root_log),
+                _ => classifiles::run_sync(config, params, &root_log),
+            };
+            // flush the async logger before a possible exit
+            drop(root_log);
+            if let Err(e) = result {
+                exit_with_error(e);
+            }
+        }
+        "backup" => {
+            let mut args = VerbArgs::parse(args, &BACKUP);
+            let input_path = args.path("input path");
+            let output_path = args.path("output path");
+            let root_log = args.logger();
 
-    match verb.as_str() {
-        "scan" => {
-            let input_path = PathBuf::from(args.next().unwrap_or_else(|| {
-                eprintln!("Error: missing input path argument");
-                process::exit(1)
-            }));
-
-            let output_path = PathBuf::from(args.next().unwrap_or_else(|| {
-                eprintln!("Error: missing output path argument");
-                process::exit(1)
-            }));
-
-            let params = Params{input_path, output_path};
-
-            let config = match config_from_yaml("config.yaml") {
-                Ok(conf) => {
-                    eprintln!("Using configuration from config.yaml");
-                    Config{
-                        mime_info_db_root: PathBuf::from(conf.mime_info_db.root),
-                        libmagic_db_file: PathBuf::from(conf.libmagic.db_file),
-                        libmagic_used_for: conf.libmagic.used_for,
+            let params = Params{
+                input_path,
+                output_path,
+                create_output: args.flag("--create-output"),
+                exclude_nested_output: args.flag("--exclude-output"),
+                unique_dirs: args.flag("--unique-dirs"),
+                one_file_system: args.flag("--one-file-system"),
+                check_links: args.flag("--check-links"),
+                stubs: args.flag("--stubs"),
+                content: args.flag("--content"),
+                content_hash: args.flag("--hash"),
+                link_index: args.flag("--link-index"),
+                case_insensitive: args.flag("--case-insensitive"),
+                ..Default::default()
+            };
+
+            let result = classifiles::run_backup(params, &root_log);
+            // flush the async logger before a possible exit
+            drop(root_log);
+            if let Err(e) = result {
+                exit_with_error(e);
+            }
+        }
+        "restore" => {
+            let mut args = VerbArgs::parse(args, &RESTORE);
+            let input_path = args.path("input path");
+            let output_path = args.path("output path");
+            let root_log = args.logger();
+
+            let params = Params{
+                input_path,
+                output_path,
+                create_output: args.flag("--create-output"),
+                exclude_nested_output: args.flag("--exclude-output"),
+                unique_dirs: args.flag("--unique-dirs"),
+                one_file_system: args.flag("--one-file-system"),
+                link_targets: args.parsed("--link-targets").unwrap_or_default(),
+                skip_broken_links: args.flag("--skip-broken-links"),
+                case_insensitive: args.flag("--case-insensitive"),
+                ..Default::default()
+            };
+
+            let result = classifiles::run_restore(params, &root_log);
+            // flush the async logger before a possible exit
+            drop(root_log);
+            if let Err(e) = result {
+                exit_with_error(e);
+            }
+        }
+        "dupes" => {
+            let mut args = VerbArgs::parse(args, &DUPES);
+            let output_path = args.path("output path");
+
+            if !args.flag("--fuzzy") {
+                eprintln!("Error: only --fuzzy duplicate grouping is supported");
+                process::exit(EXIT_USAGE);
+            }
+
+            let max_distance = args.parsed("--max-distance").unwrap_or(DEFAULT_MAX_DISTANCE);
+
+            match classifiles::run_dupes(&output_path, max_distance) {
+                Ok(groups) => for group in groups {
+                    for path in group {
+                        println!("{}", path.display());
                     }
+                    println!();
+                },
+                Err(e) => exit_with_error(e),
+            }
+        }
+        "report" => {
+            let mut args = VerbArgs::parse(args, &REPORT);
+            let output_path = args.path("output path");
+            let root_log = args.logger();
+            // the config is only needed for the expected executable paths
+            let executable_paths = match args.flag("--security") {
+                true => Some(load_config(&args).executable_paths),
+                false => None,
+            };
+
+            let result = classifiles::run_report(&output_path, args.flag("--html"), args.parsed("--treemap"),
+                executable_paths.as_deref(), args.value("--upload"), &root_log);
+            // flush the async logger before a possible exit
+            drop(root_log);
+            match result {
+                Ok(Some(report_path)) => eprintln!("Written to {}", report_path.display()),
+                Ok(None) => (),
+                Err(e) => exit_with_error(e),
+            }
+        }
+        "verify" => {
+            let mut args = VerbArgs::parse(args, &VERIFY);
+            let root_log = args.logger();
+
+            let audit_log = args.value("--audit-log").map(PathBuf::from);
+            if !args.flag("--checksums") && audit_log.is_none() {
+                eprintln!("Error: verify requires --checksums OUTPUT_DIR or --audit-log FILE");
+                process::exit(EXIT_USAGE);
+            }
+            if let Some(audit_log) = audit_log {
+                match classifiles::run_verify_audit_log(&audit_log) {
+                    Ok(entry_count) => eprintln!("Audit log {} is intact, {} entries", audit_log.display(), entry_count),
+                    Err(e) => exit_with_error(e),
                 }
-                Err(_) => {
-                    eprintln!("Using default configuration");
-                    Config{
-                        mime_info_db_root: PathBuf::from("/usr/share/mime"),
-                        libmagic_db_file: PathBuf::from("/usr/share/file/misc/magic.mgc"),
-                        libmagic_used_for: vec![
-                            "application/zip".to_owned(),
-                            //"application/x-sharedlib".to_owned()
-                        ],
-                    }
+            }
+
+            if args.flag("--checksums") {
+                let output_path = args.path("output path");
+                let config = load_config(&args);
+                let result = classifiles::run_verify(config, &output_path, &root_log);
+                // flush the async logger before a possible exit
+                drop(root_log);
+                if let Err(e) = result {
+                    exit_with_error(e);
                 }
+            }
+        }
+        "classify" => {
+            let mut args = VerbArgs::parse(args, &CLASSIFY);
+            let mut paths = vec![args.path("path")];
+            paths.extend(args.positional.by_ref().map(PathBuf::from));
+            // stdout only carries the identifications, with --json they are read by another program
+            let root_log = args.stderr_logger();
+
+            let result = classifiles::run_classify(load_config(&args), &paths, args.flag("--stream"), args.flag("--json"),
+                &root_log);
+            // flush the async logger before a possible exit
+            drop(root_log);
+            if let Err(e) = result {
+                exit_with_error(e);
+            }
+        }
+        "carve-map" => {
+            let mut args = VerbArgs::parse(args, &CARVE_MAP);
+            let path = args.path("path");
+            // the map alone goes to stdout, the summary and other log lines to stderr
+            let root_log = args.stderr_logger();
+
+            let result = classifiles::run_carve_map(load_config(&args), &path, args.flag("--all"), args.flag("--json"),
+                &root_log);
+            // flush the async logger before a possible exit
+            drop(root_log);
+            if let Err(e) = result {
+                exit_with_error(e);
+            }
+        }
+        "classify-worker" => {
+            let args = VerbArgs::parse(args, &CLASSIFY_WORKER);
+            // stdout carries the answers to the scan
+            let root_log = args.stderr_logger();
+
+            let result = classifiles::run_worker(load_config(&args), args.flag("--sandbox"), &root_log);
+            // flush the async logger before a possible exit
+            drop(root_log);
+            if let Err(e) = result {
+                exit_with_error(e);
+            }
+        }
+        "whereis" => {
+            let mut args = VerbArgs::parse(args, &WHEREIS);
+            let output_entry = args.path("output entry");
+
+            match classifiles::run_whereis(&output_entry) {
+                Ok(input_path) => println!("{}", input_path.display()),
+                Err(e) => exit_with_error(e),
+            }
+        }
+        "tag" => {
+            let mut args = VerbArgs::parse(args, &TAG);
+            let mut output_entries = vec![args.path("output entry")];
+            output_entries.extend(args.positional.by_ref().map(PathBuf::from));
+
+            match classifiles::run_tag(&output_entries, &args.values("--add"), &args.values("--remove")) {
+                Ok(entry_tags) => for (output_entry, tags) in entry_tags {
+                    println!("{}: {}", output_entry.display(), tags.join(","));
+                },
+                Err(e) => exit_with_error(e),
+            }
+        }
+        "find" => {
+            let mut args = VerbArgs::parse(args, &FIND);
+            let output_path = args.path("output directory");
+            let filter = SearchFilter{
+                mime: args.value("--mime").map(str::to_owned),
+                name: args.parsed("--name"),
+                min_size: args.parsed("--min-size"),
+                max_size: args.parsed("--max-size"),
+                newer: args.parsed("--newer"),
+                older: args.parsed("--older"),
+                tags: args.values("--tag"),
             };
 
-            if let Err(e) = classifiles::run_scan(config, params, &root_log) {
-                eprintln!("Error: {}", e);
-                process::exit(1);
+            match classifiles::run_find(&output_path, &filter, args.flag("--links")) {
+                Ok(paths) => for path in paths {
+                    println!("{}", path.display());
+                },
+                Err(e) => exit_with_error(e),
             }
         }
-        "backup" => {
-            let input_path = PathBuf::from(args.next().unwrap_or_else(|| {
-                eprintln!("Error: missing input path argument");
-                process::exit(1)
-            }));
+        "mount" => {
+            let mut args = VerbArgs::parse(args, &MOUNT);
+            let output_path = args.path("output directory");
+            let mountpoint = args.path("mountpoint");
+            let root_log = args.logger();
 
-            let output_path = PathBuf::from(args.next().unwrap_or_else(|| {
-                eprintln!("Error: missing output path argument");
-                process::exit(1)
-            }));
+            let result = classifiles::run_mount(&load_config(&args), &output_path, &mountpoint, &root_log);
+            // flush the async logger before a possible exit
+            drop(root_log);
+            if let Err(e) = result {
+                exit_with_error(e);
+            }
+        }
+        "history" => {
+            let mut args = VerbArgs::parse(args, &HISTORY);
+            let output_path = args.path("output directory");
+
+            if let Err(e) = classifiles::run_history(&output_path) {
+                exit_with_error(e);
+            }
+        }
+        "trend" => {
+            let mut args = VerbArgs::parse(args, &TREND);
+            let output_path = args.path("output directory");
 
-            let params = Params{input_path, output_path};
+            if let Err(e) = classifiles::run_trend(&output_path, args.parsed("--last").unwrap_or(5)) {
+                exit_with_error(e);
+            }
+        }
+        "export" => {
+            let mut args = VerbArgs::parse(args, &EXPORT);
+            let manifest_path = args.path("manifest path");
+            let export_path = args.path("export path");
+            let format = args.parsed("--format").unwrap_or(ExportFormat::Csv);
 
-            if let Err(e) = classifiles::run_backup(params, &root_log) {
-                eprintln!("Error: {}", e);
-                process::exit(1);
+            match classifiles::run_export(&manifest_path, format, &export_path) {
+                Ok(row_count) => eprintln!("Exported {} records to {}", row_count, export_path.display()),
+                Err(e) => exit_with_error(e),
             }
         }
-        "restore" => {
-            let input_path = PathBuf::from(args.next().unwrap_or_else(|| {
-                eprintln!("Error: missing input path argument");
-                process::exit(1)
-            }));
+        "relink" => {
+            let mut args = VerbArgs::parse(args, &RELINK);
+            let output_path = args.path("output path");
+            let root_log = args.logger();
+
+            let (old_prefix, new_prefix) = match (args.value("--old-prefix"), args.value("--new-prefix")) {
+                (Some(old_prefix), Some(new_prefix)) => (PathBuf::from(old_prefix), PathBuf::from(new_prefix)),
+                _ => {
+                    eprintln!("Error: relink requires both --old-prefix and --new-prefix");
+                    process::exit(EXIT_USAGE);
+                }
+            };
+
+            let result = classifiles::run_relink(&output_path, &old_prefix, &new_prefix, &root_log);
+            // flush the async logger before a possible exit
+            drop(root_log);
+            if let Err(e) = result {
+                exit_with_error(e);
+            }
+        }
+        "apply-manifest" => {
+            let mut args = VerbArgs::parse(args, &APPLY_MANIFEST);
+            let input_path = args.path("manifest path");
+            let output_path = args.path("output path");
+            let root_log = args.logger();
+
+            let params = Params{
+                input_path,
+                output_path,
+                create_output: args.flag("--create-output"),
+                link_mode: args.parsed("--link-mode").unwrap_or_default(),
+                relative_links: args.flag("--relative-links"),
+                uti: args.flag("--uti"),
+                skip_capacity_check: args.flag("--skip-capacity-check"),
+                case_insensitive: args.flag("--case-insensitive"),
+                ..Default::default()
+            };
+
+            let result = classifiles::run_apply_manifest(params, &root_log);
+            // flush the async logger before a possible exit
+            drop(root_log);
+            if let Err(e) = result {
+                exit_with_error(e);
+            }
+        }
+        "learn" => {
+            let mut args = VerbArgs::parse(args, &LEARN);
+            let rules_path = args.path("override rules path");
+            let root_log = args.logger();
+
+            let result = classifiles::run_learn(&load_config(&args), &rules_path, &root_log);
+            // flush the async logger before a possible exit
+            drop(root_log);
+            if let Err(e) = result {
+                exit_with_error(e);
+            }
+        }
+        "completions" => {
+            let mut args = VerbArgs::parse(args, &COMPLETIONS);
+            let shell = args.positional.next().unwrap_or_default();
 
-            let output_path = PathBuf::from(args.next().unwrap_or_else(|| {
-                eprintln!("Error: missing output path argument");
-                process::exit(1)
-            }));
+            let script = match shell.as_str() {
+                "bash" => completions::bash(VERBS),
+                "zsh" => completions::zsh(VERBS),
+                "fish" => completions::fish(VERBS),
+                _ => {
+                    eprintln!("Error: invalid shell. Valid shells are: {}", SHELLS.join(", "));
+                    process::exit(EXIT_USAGE);
+                }
+            };
+            print!("{}", script);
+        }
+        "init" => {
+            let args = VerbArgs::parse(args, &INIT);
+            let config_dir = user_config_dir().unwrap_or_else(|| {
+                eprintln!("Error: no home directory to put the configuration in");
+                process::exit(EXIT_FAILURE)
+            });
+            let config_path = config_dir.join(CONFIG_FILE);
 
-            let params = Params{input_path, output_path};
+            if config_path.exists() && !args.flag("--force") {
+                eprintln!("Error: {} already exists, pass --force to overwrite it", config_path.display());
+                process::exit(EXIT_FAILURE);
+            }
+            if let Err(e) = fs::create_dir_all(&config_dir).and_then(|_| fs::write(&config_path, CONFIG_TEMPLATE)) {
+                exit_with_error(format!("could not write {}: {}", config_path.display(), e).into());
+            }
+            eprintln!("Configuration written to {}", config_path.display());
+        }
+        "install-service" => {
+            let mut args = VerbArgs::parse(args, &INSTALL_SERVICE);
+            let input_path = args.path("input directory");
+            let output_path = args.path("output directory");
+            // an invalid config.yaml fails here rather than in every run of the service
+            load_config(&args);
 
-            if let Err(e) = classifiles::run_restore(params, &root_log) {
-                eprintln!("Error: {}", e);
-                process::exit(1);
+            if let Err(e) = install_service(&args, &input_path, &output_path) {
+                exit_with_error(e);
+            }
+        }
+        "mime-types" => {
+            let args = VerbArgs::parse(args, &MIME_TYPES);
+            for mime in classifiles::known_mime_types(&load_config(&args)) {
+                println!("{}", mime);
             }
         }
-        _ => eprintln!("Error: invalid verb. Valid verbs are: scan, backup, restore"),
+        _ => {
+            let verbs: Vec<&str> = VERBS.iter().map(|v| v.name).collect();
+            eprintln!("Error: invalid verb. Valid verbs are: {}", verbs.join(", "));
+            process::exit(EXIT_USAGE);
+        }
     }
 }
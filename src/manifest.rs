@@ -0,0 +1,175 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+static STATE_DIR: &str = ".classifiles";
+static MANIFEST_FILE: &str = "manifest.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub path: PathBuf,
+    pub mime: Option<String>,
+    pub ext: Option<String>,
+    // further extensions the type is known by, for downstream tools (ext is the one used for the output name)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alt_exts: Vec<String>,
+    // relative to the output root
+    pub link: Option<PathBuf>,
+    #[serde(default)]
+    pub size: Option<u64>,
+    // seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<i64>,
+    // user and group names (ids without a name as numbers) and permission bits of the input file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phash: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<PathBuf>,
+    // Apple Uniform Type Identifier of the MIME type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uti: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    // from tag rules and the tag verb
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // why the file could not be classified, e.g. crashed-detector
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+    // verdict of clamd, OK or the name of the signature found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clamav: Option<String>,
+    // Shannon entropy of the leading bytes in bits per byte, with Config::entropy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entropy: Option<f64>,
+    // volumes of a split archive share the name of the set (NAME.7z for NAME.7z.001), numbered from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split_set: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split_part: Option<u32>,
+    // input path of the file a companion is laid out with, e.g. IMG_0001.CR2 for IMG_0001.JPG
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub companion_of: Option<PathBuf>,
+    // EXIF capture time in the camera's local time (YYYY-MM-DDTHH:MM:SS), with Config::photo_events or
+    // Config::geocoding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taken: Option<String>,
+    // event directory the photo is laid out in below its type directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+    // EXIF GPS position as latitude and longitude, with Config::photo_events or Config::geocoding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gps: Option<[f64; 2]>,
+    // ISO 3166 country code and first-level division of the nearest place, with Config::geocoding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gps_country: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gps_region: Option<String>,
+}
+
+// versions of the tool and databases behind a manifest, written as its first line
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Header {
+    pub version: String,
+    #[serde(default)]
+    pub libmagic_version: Option<String>,
+    #[serde(default)]
+    pub libmagic_db: Option<PathBuf>,
+    #[serde(default)]
+    pub libmagic_db_sha256: Option<String>,
+    #[serde(default)]
+    pub mime_info_db: Option<PathBuf>,
+    #[serde(default)]
+    pub shared_mime_info_version: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Header{header: Header},
+    Record(Box<Record>),
+}
+
+// manifest lives in a hidden state directory inside the output tree
+pub fn state_dir(output_root: &Path) -> PathBuf {
+    output_root.join(STATE_DIR)
+}
+
+pub fn manifest_path(output_root: &Path) -> PathBuf {
+    state_dir(output_root).join(MANIFEST_FILE)
+}
+
+// written next to the manifest and renamed over it when finished, an interrupted run leaves the previous one intact
+pub struct ManifestWriter {
+    out: BufWriter<File>,
+    path: PathBuf,
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    PathBuf::from(partial)
+}
+
+impl ManifestWriter {
+    pub fn create(output_root: &Path, header: Option<&Header>) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(state_dir(output_root))?;
+        let path = manifest_path(output_root);
+        let file = File::create(partial_path(&path))?;
+        let mut writer = Self{out: BufWriter::new(file), path};
+        if let Some(header) = header {
+            serde_json::to_writer(&mut writer.out, &Line::Header{header: header.clone()})?;
+            writer.out.write_all(b"\n")?;
+        }
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut self.out, record)?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        self.out.flush()?;
+        fs::rename(partial_path(&self.path), &self.path)?;
+        Ok(())
+    }
+}
+
+pub fn read_manifest(output_root: &Path) -> Result<Vec<Record>, Box<dyn Error>> {
+    read_manifest_file(&manifest_path(output_root))
+}
+
+pub fn read_manifest_file(path: &Path) -> Result<Vec<Record>, Box<dyn Error>> {
+    Ok(read_manifest_file_with_header(path)?.1)
+}
+
+// manifests written before headers were introduced have none
+pub fn read_manifest_file_with_header(path: &Path) -> Result<(Option<Header>, Vec<Record>), Box<dyn Error>> {
+    let file = File::open(path)
+        .map_err(|e| format!("could not open manifest {}: {}", path.display(), e))?;
+
+    let mut header = None;
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line)? {
+            Line::Header{header: h} => header = Some(h),
+            Line::Record(record) => records.push(*record),
+        }
+    }
+    Ok((header, records))
+}
@@ -0,0 +1,114 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+use fnv::FnvHashMap;
+
+#[derive(Default)]
+pub struct ScanMetrics {
+    classified: Mutex<FnvHashMap<String, u64>>,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+    queue_depth: AtomicU64,
+    pipeline_queued: AtomicU64,
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl ScanMetrics {
+    pub fn file_classified(&self, mime: &str, size: u64) {
+        let mut classified = self.classified.lock().unwrap();
+        *classified.entry(mime.to_owned()).or_insert(0) += 1;
+        self.bytes.fetch_add(size, Ordering::Relaxed);
+    }
+
+    pub fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_queue_depth(&self, depth: u64) {
+        self.queue_depth.store(depth, Ordering::Relaxed);
+    }
+
+    pub fn entry_queued(&self) {
+        self.pipeline_queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn entry_dequeued(&self) {
+        self.pipeline_queued.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn pipeline_queued(&self) -> u64 {
+        self.pipeline_queued.load(Ordering::Relaxed)
+    }
+
+    // prometheus text exposition format, throughput is left to rate() on the counters
+    fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP classifiles_files_classified_total Files classified, by detected MIME type.\n");
+        out.push_str("# TYPE classifiles_files_classified_total counter\n");
+        let classified = self.classified.lock().unwrap();
+        let mut mimes: Vec<_> = classified.iter().collect();
+        mimes.sort();
+        for (mime, count) in mimes {
+            out.push_str(&format!("classifiles_files_classified_total{{mime=\"{}\"}} {}\n", escape_label(mime), count));
+        }
+
+        out.push_str("# HELP classifiles_bytes_classified_total Bytes of classified files.\n");
+        out.push_str("# TYPE classifiles_bytes_classified_total counter\n");
+        out.push_str(&format!("classifiles_bytes_classified_total {}\n", self.bytes.load(Ordering::Relaxed)));
+
+        out.push_str("# HELP classifiles_errors_total Entries that could not be read or linked.\n");
+        out.push_str("# TYPE classifiles_errors_total counter\n");
+        out.push_str(&format!("classifiles_errors_total {}\n", self.errors.load(Ordering::Relaxed)));
+
+        out.push_str("# HELP classifiles_queue_depth Files waiting to be classified.\n");
+        out.push_str("# TYPE classifiles_queue_depth gauge\n");
+        out.push_str(&format!("classifiles_queue_depth {}\n", self.queue_depth.load(Ordering::Relaxed)));
+
+        out.push_str("# HELP classifiles_pipeline_queued Entries walked (and hashed) but not classified yet.\n");
+        out.push_str("# TYPE classifiles_pipeline_queued gauge\n");
+        out.push_str(&format!("classifiles_pipeline_queued {}\n", self.pipeline_queued()));
+
+        out
+    }
+}
+
+fn handle_request(stream: TcpStream, metrics: &ScanMetrics) -> io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // skip the request headers
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+        header.clear();
+    }
+
+    let (status, body) = match request_line.split_whitespace().nth(1) {
+        Some("/metrics") => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", "not found\n".to_owned()),
+    };
+
+    let mut out = &stream;
+    write!(out, "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, body.len(), body)?;
+    Ok(())
+}
+
+// serves /metrics from a background thread for as long as the process runs
+pub fn serve(addr: &str, metrics: Arc<ScanMetrics>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().filter_map(|s| s.ok()) {
+            let _ = handle_request(stream, &metrics);
+        }
+    });
+    Ok(())
+}
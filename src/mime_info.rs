@@ -3,6 +3,15 @@ use fnv::FnvHashMap;
 use std::path::{Path, PathBuf};
 use std::io::Read;
 
+use crate::mime_type::MimeType;
+
+// per-type files of shared-mime-info are a few KiB, even with all translations
+const MAX_MIME_INFO_SIZE: u64 = 1024 * 1024;
+// lines of CHILD PARENT, generated by update-mime-database next to the per-type files
+static SUBCLASSES_FILE: &str = "subclasses";
+// every type is a byte stream, its extension (bin) says nothing about a subclass
+static OCTET_STREAM: &str = "application/octet-stream";
+
 #[derive(Debug)]
 struct MimeInfoDbError(String);
 
@@ -17,13 +26,16 @@ impl Error for MimeInfoDbError {}
 #[derive(Debug, PartialEq, Eq)]
 pub enum Mime {
     Generic,
-    WithExt(String),
+    // candidate extensions, the most common first, never empty
+    WithExt(Vec<String>),
     Unknown,
 }
 
 pub struct MimeInfoDb {
     db_root_path: Option<PathBuf>,
     mime_map: FnvHashMap<String, Mime>,
+    // direct parents of each type, e.g. application/x-ndjson is a sub-class of application/json
+    parents: FnvHashMap<String, Vec<String>>,
 }
 
 impl MimeInfoDb {
@@ -42,14 +54,46 @@ impl MimeInfoDb {
             }
         };
 
+        let parents = db_root_opt.map_or_else(FnvHashMap::default, Self::load_subclasses);
         Self{
             db_root_path: db_root_opt.map(PathBuf::from),
             mime_map: FnvHashMap::default(),
+            parents,
+        }
+    }
+
+    // a missing or unreadable file only costs the inherited extensions
+    fn load_subclasses(root_path: &Path) -> FnvHashMap<String, Vec<String>> {
+        let mut parents: FnvHashMap<String, Vec<String>> = FnvHashMap::default();
+        let subclasses = fs::read_to_string(root_path.join(SUBCLASSES_FILE)).unwrap_or_default();
+        for line in subclasses.lines() {
+            if let Some((child, parent)) = line.split_once(' ') {
+                parents.entry(child.to_owned()).or_default().push(parent.trim().to_owned());
+            }
         }
+        parents
+    }
+
+    // extensions of the nearest ancestor that has any, for types without extensions of their own,
+    // e.g. application/x-ndjson -> application/json -> text/plain
+    pub fn inherited(&mut self, mime: &str) -> Option<(String, Vec<String>)> {
+        let mut visited = vec![mime.to_owned()];
+        let mut queue: std::collections::VecDeque<String> = self.parents.get(mime).cloned().unwrap_or_default().into();
+        while let Some(ancestor) = queue.pop_front() {
+            if ancestor == OCTET_STREAM || visited.contains(&ancestor) {
+                continue;
+            }
+            if let Mime::WithExt(exts) = self.get(&ancestor) {
+                return Some((ancestor, exts.clone()));
+            }
+            queue.extend(self.parents.get(&ancestor).cloned().unwrap_or_default());
+            visited.push(ancestor);
+        }
+        None
     }
 
     pub fn get(&mut self, mime: &str) -> &Mime {
-        let Self { db_root_path, mime_map } = self;
+        let Self { db_root_path, mime_map, .. } = self;
 
         let entry = mime_map.entry(mime.to_owned());
         entry.or_insert_with(|| {
@@ -61,7 +105,7 @@ impl MimeInfoDb {
                 // eprintln!("using secondary extension db");
                 match mime_db::extensions(mime) {
                     Some(exts) => if exts.len() > 0 {
-                        Mime::WithExt(exts[0].to_owned())
+                        Mime::WithExt(exts.iter().map(|ext| ext.to_string()).collect())
                     } else {
                         Mime::Generic
                     },
@@ -73,40 +117,86 @@ impl MimeInfoDb {
         })
     }
 
-    pub fn set(&mut self, mime: &str, ext: &str) {
-        self.mime_map.insert(mime.to_owned(), Mime::WithExt(ext.to_owned()));
+    // types described by the shared-mime-info db, i.e. MEDIA/SUBTYPE.xml files under its root
+    pub fn known_types(&self) -> Vec<String> {
+        let db_root = match &self.db_root_path {
+            Some(db_root) => db_root,
+            None => return Vec::new(),
+        };
+
+        let mut types = Vec::new();
+        for media_dir in fs::read_dir(db_root).into_iter().flatten().filter_map(|e| e.ok()) {
+            let media = media_dir.file_name();
+            // packages holds the source definitions, not per-type files
+            if media == "packages" {
+                continue;
+            }
+            for entry in fs::read_dir(media_dir.path()).into_iter().flatten().filter_map(|e| e.ok()) {
+                let path = entry.path();
+                if let (Some("xml"), Some(subtype)) = (path.extension().and_then(|e| e.to_str()), path.file_stem()) {
+                    types.push(format!("{}/{}", media.to_string_lossy(), subtype.to_string_lossy()));
+                }
+            }
+        }
+        types.sort();
+        types
+    }
+
+    pub fn set(&mut self, mime: &str, exts: Vec<String>) {
+        self.mime_map.insert(mime.to_owned(), Mime::WithExt(exts));
     }
 
     fn load_mime_info(root_path: &Path, mime: &str) -> Mime {
-        let mime_path = root_path.join(format!("{}.xml", mime));
+        // a type reported by a plugin could otherwise name any xml file
+        let mime_type = match mime.parse::<MimeType>() {
+            Ok(mime_type) => mime_type,
+            Err(_) => return Mime::Unknown,
+        };
+        let mime_path = root_path.join(mime_type.media()).join(format!("{}.xml", mime_type.subtype()));
         // eprintln!("loading {} from {}", mime, mime_path.display());
 
-        let mime_info_file = File::open(mime_path);
+        let mime_info_file = File::open(&mime_path);
         match mime_info_file {
-            Ok(mut file) => Self::parse_mime_info(&mut file),
+            Ok(file) => Self::parse_mime_info(file).unwrap_or_else(|e| {
+                // a corrupted db only costs the extension of this type
+                eprintln!("Warning: ignoring invalid {}: {}", mime_path.display(), e);
+                Mime::Unknown
+            }),
             Err(_) => Mime::Unknown,
         }
     }
 
-    fn extract_glob(doc: &roxmltree::Document) -> Mime {
-        match doc.descendants().find(|n| n.tag_name().name() == "glob") {
-            Some(node) => match node.attribute("pattern") {
-                Some(pattern) => Mime::WithExt(pattern.trim_start_matches("*.").to_owned()),
-                None => Mime::Generic,
-            },
-            None => Mime::Generic,
+    // extensions of the glob patterns in their order, patterns matching whole names (Makefile, README*) are no extensions
+    fn extract_globs(xml_str: &str) -> Result<Mime, xmlparser::Error> {
+        use xmlparser::{Token, Tokenizer};
+
+        let mut in_glob = false;
+        let mut exts: Vec<String> = Vec::new();
+        for token in Tokenizer::from(xml_str) {
+            match token? {
+                Token::ElementStart{local, ..} => in_glob = local.as_str() == "glob",
+                Token::Attribute{local, value, ..} if in_glob && local.as_str() == "pattern" => {
+                    let ext = match value.as_str().strip_prefix("*.") {
+                        Some(ext) if !ext.is_empty() && !ext.contains(['*', '?', '[']) => ext,
+                        _ => continue,
+                    };
+                    if !exts.iter().any(|e| e == ext) {
+                        exts.push(ext.to_owned());
+                    }
+                }
+                _ => (),
+            }
         }
+        Ok(if exts.is_empty() { Mime::Generic } else { Mime::WithExt(exts) })
     }
 
-    fn parse_mime_info(f: &mut File) -> Mime {
-        use roxmltree::Document;
-        let mut xml_str = String::new();
-        if let Err(_) = f.read_to_string(&mut xml_str) {
-            return Mime::Unknown;
-        }
-        match Document::parse(&xml_str) {
-            Ok(doc) => Self::extract_glob(&doc),
-            Err(e) => panic!("Error: {}", e),
+    fn parse_mime_info(f: File) -> Result<Mime, Box<dyn Error>> {
+        let mut xml = Vec::new();
+        // one more byte than allowed tells an oversized file apart
+        f.take(MAX_MIME_INFO_SIZE + 1).read_to_end(&mut xml)?;
+        if xml.len() as u64 > MAX_MIME_INFO_SIZE {
+            return Err(Box::new(MimeInfoDbError(format!("larger than {} bytes", MAX_MIME_INFO_SIZE))));
         }
+        Ok(Self::extract_globs(str::from_utf8(&xml)?)?)
     }
 }
@@ -0,0 +1,97 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// a MIME type as reported by detectors, e.g. image/svg+xml or text/plain; charset=utf-8,
+// media type and subtype are lower case, the subtype keeps its structured syntax suffix (+xml, +zip)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MimeType {
+    media: String,
+    subtype: String,
+    params: Vec<(String, String)>,
+}
+
+// restricted names of RFC 6838, starting with a letter or digit, so that they are safe directory names too (no ..)
+fn is_token(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+}
+
+impl FromStr for MimeType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid MIME type {}, expected TYPE/SUBTYPE", s);
+        let mut parts = s.split(';');
+        let (media, subtype) = parts.next().unwrap_or("").trim().split_once('/').ok_or_else(invalid)?;
+        if !is_token(media) || !is_token(subtype) {
+            return Err(invalid());
+        }
+
+        let mut params = Vec::new();
+        for param in parts.map(str::trim).filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').ok_or_else(|| format!("invalid parameter {} of MIME type {}", param, s))?;
+            let value = value.trim();
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+            params.push((key.trim().to_ascii_lowercase(), value.to_owned()));
+        }
+        Ok(Self{media: media.to_ascii_lowercase(), subtype: subtype.to_ascii_lowercase(), params})
+    }
+}
+
+impl fmt::Display for MimeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.media, self.subtype)?;
+        for (key, value) in &self.params {
+            write!(f, "; {}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl MimeType {
+    pub fn media(&self) -> &str {
+        &self.media
+    }
+
+    pub fn subtype(&self) -> &str {
+        &self.subtype
+    }
+
+    // zip of application/epub+zip
+    pub fn suffix(&self) -> Option<&str> {
+        self.subtype.rsplit_once('+').map(|(_, suffix)| suffix).filter(|suffix| !suffix.is_empty())
+    }
+
+    // type and subtype without parameters, what records, the mime info db and the output layout use
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.media, self.subtype)
+    }
+
+    // type directory below the output root
+    pub fn dir(&self) -> PathBuf {
+        [&self.media, &self.subtype].iter().collect()
+    }
+
+    // exact type, a prefix ending with * (image/*, application/vnd.*), or any type with a structured syntax suffix
+    // (*/*+zip, image/*+xml), parameters are ignored
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.to_ascii_lowercase();
+        if let Some((media, suffix)) = pattern.split_once("/*+") {
+            return (media == "*" || media == self.media) && self.suffix() == Some(suffix);
+        }
+        let essence = self.essence();
+        match pattern.strip_suffix('*') {
+            Some(prefix) => essence.starts_with(prefix),
+            None => pattern == essence,
+        }
+    }
+}
+
+// the essence of a reported type, types that do not parse are kept as they are
+pub fn normalized(mime: String) -> String {
+    match mime.parse::<MimeType>() {
+        Ok(mime_type) => mime_type.essence(),
+        Err(_) => mime,
+    }
+}
@@ -0,0 +1,223 @@
+use std::error::Error;
+use std::path::Path;
+
+use slog::Logger;
+
+use crate::manifest::Record;
+
+// read-only view of the manifest of an output directory, laid out on the fly instead of as links on disk:
+// by-mime/TYPE/SUBTYPE, by-date/YYYY/MM (modification time, UTC) and by-tag/TAG, every entry a symlink to the input file
+
+#[cfg(feature = "fuse")]
+mod view {
+    use std::collections::BTreeMap;
+    use std::ffi::{OsStr, OsString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use fuser::{FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request, FUSE_ROOT_ID};
+
+    use crate::manifest::Record;
+    use crate::mime_type::MimeType;
+
+    // the view does not change while mounted
+    const TTL: Duration = Duration::from_secs(3600);
+
+    enum Node {
+        Dir{parent: u64, children: BTreeMap<OsString, u64>},
+        Link{target: PathBuf, mtime: SystemTime},
+    }
+
+    pub struct View {
+        // inode n is nodes[n - 1], the root is FUSE_ROOT_ID
+        nodes: Vec<Node>,
+        mounted_at: SystemTime,
+        uid: u32,
+        gid: u32,
+    }
+
+    fn mtime(record: &Record) -> Option<SystemTime> {
+        let mtime = record.mtime?;
+        match mtime >= 0 {
+            true => UNIX_EPOCH.checked_add(Duration::from_secs(mtime as u64)),
+            false => UNIX_EPOCH.checked_sub(Duration::from_secs(mtime.unsigned_abs())),
+        }
+    }
+
+    impl View {
+        // relative input paths of records are resolved against input_dir, the links of a view must not depend on
+        // where they are looked at from
+        pub fn new(records: &[Record], lowercase_ext: bool, input_dir: &Path) -> Self {
+            // SAFETY: getuid and getgid cannot fail
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            let mut view = Self{
+                nodes: vec![Node::Dir{parent: FUSE_ROOT_ID, children: BTreeMap::new()}],
+                mounted_at: SystemTime::now(),
+                uid,
+                gid,
+            };
+            for record in records {
+                let file_name = match record.path.file_name() {
+                    Some(file_name) => crate::append_ext_if_needed(file_name, &record.ext, lowercase_ext),
+                    None => continue,
+                };
+                let mtime = mtime(record);
+                let target = input_dir.join(&record.path);
+                let type_dir = record.mime.as_deref()
+                    .and_then(|mime| mime.parse::<MimeType>().ok())
+                    .map_or_else(|| PathBuf::from(crate::OUTPUT_UNKNOWN), |mime| mime.dir());
+                view.add(&Path::new("by-mime").join(type_dir), &file_name, &target, mtime);
+                // files without a modification time (older manifests) or from before 1970 are left out
+                if let Some(time) = mtime.filter(|&time| time >= UNIX_EPOCH) {
+                    let timestamp = crate::audit::utc_timestamp(time);
+                    let date_dir: PathBuf = ["by-date", &timestamp[..4], &timestamp[5..7]].iter().collect();
+                    view.add(&date_dir, &file_name, &target, mtime);
+                }
+                for tag in &record.tags {
+                    view.add(&Path::new("by-tag").join(tag), &file_name, &target, mtime);
+                }
+            }
+            view
+        }
+
+        fn dir(&mut self, path: &Path) -> u64 {
+            let mut ino = FUSE_ROOT_ID;
+            for name in path.iter() {
+                ino = match self.child(ino, name) {
+                    Some(child) => child,
+                    None => self.insert(ino, name.to_owned(), Node::Dir{parent: ino, children: BTreeMap::new()}),
+                };
+            }
+            ino
+        }
+
+        fn node(&self, ino: u64) -> Option<&Node> {
+            self.nodes.get((ino as usize).checked_sub(1)?)
+        }
+
+        fn child(&self, parent: u64, name: &OsStr) -> Option<u64> {
+            match self.node(parent)? {
+                Node::Dir{children, ..} => children.get(name).copied(),
+                Node::Link{..} => None,
+            }
+        }
+
+        fn insert(&mut self, parent: u64, name: OsString, node: Node) -> u64 {
+            self.nodes.push(node);
+            let ino = self.nodes.len() as u64;
+            if let Node::Dir{children, ..} = &mut self.nodes[parent as usize - 1] {
+                children.insert(name, ino);
+            }
+            ino
+        }
+
+        // name collisions get a counter before the extension, stable between mounts of the same manifest
+        fn add(&mut self, dir: &Path, file_name: &Path, target: &Path, mtime: Option<SystemTime>) {
+            let parent = self.dir(dir);
+            let mut name = file_name.as_os_str().to_owned();
+            let mut n = 1;
+            while self.child(parent, &name).is_some() {
+                n += 1;
+                name = match crate::split_ext(file_name.as_os_str()) {
+                    (stem, Some(ext)) if !stem.is_empty() => {
+                        let mut name = stem.to_owned();
+                        name.push(format!("-{}.", n));
+                        name.push(ext);
+                        name
+                    }
+                    _ => {
+                        let mut name = file_name.as_os_str().to_owned();
+                        name.push(format!("-{}", n));
+                        name
+                    }
+                };
+            }
+            self.insert(parent, name, Node::Link{target: target.to_owned(), mtime: mtime.unwrap_or(self.mounted_at)});
+        }
+
+        fn attr(&self, ino: u64) -> Option<FileAttr> {
+            let (kind, perm, size, time) = match self.node(ino)? {
+                Node::Dir{..} => (FileType::Directory, 0o555, 0, self.mounted_at),
+                Node::Link{target, mtime} => (FileType::Symlink, 0o777, target.as_os_str().len() as u64, *mtime),
+            };
+            Some(FileAttr{ino, size, blocks: 0, atime: time, mtime: time, ctime: time, crtime: time, kind, perm,
+                nlink: 1, uid: self.uid, gid: self.gid, rdev: 0, blksize: 4096, flags: 0})
+        }
+    }
+
+    impl Filesystem for View {
+        fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+            match self.child(parent, name).and_then(|ino| self.attr(ino)) {
+                Some(attr) => reply.entry(&TTL, &attr, 0),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+            match self.attr(ino) {
+                Some(attr) => reply.attr(&TTL, &attr),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+            match self.node(ino) {
+                Some(Node::Link{target, ..}) => reply.data(target.as_os_str().as_bytes()),
+                Some(Node::Dir{..}) => reply.error(libc::EINVAL),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+            let (parent, children) = match self.node(ino) {
+                Some(Node::Dir{parent, children}) => (*parent, children),
+                Some(Node::Link{..}) => return reply.error(libc::ENOTDIR),
+                None => return reply.error(libc::ENOENT),
+            };
+            let entries = vec![(ino, FileType::Directory, OsStr::new(".")), (parent, FileType::Directory, OsStr::new(".."))]
+                .into_iter()
+                .chain(children.iter().map(|(name, &child)| {
+                    let kind = match self.nodes[child as usize - 1] {
+                        Node::Dir{..} => FileType::Directory,
+                        Node::Link{..} => FileType::Symlink,
+                    };
+                    (child, kind, name.as_os_str())
+                }));
+            // the offset of an entry is the one to continue after it
+            for (i, (child, kind, name)) in entries.enumerate().skip(offset as usize) {
+                if reply.add(child, i as i64 + 1, kind, name) {
+                    break;
+                }
+            }
+            reply.ok();
+        }
+    }
+}
+
+// blocks until the view is unmounted (fusermount -u MOUNTPOINT)
+#[cfg(feature = "fuse")]
+pub fn mount(records: &[Record], lowercase_ext: bool, mountpoint: &Path, log: &Logger) -> Result<(), Box<dyn Error>> {
+    use fuser::{MountOption, Session};
+    use slog::info;
+
+    // manifests written before input paths were recorded as absolute ones hold them relative to where scan ran
+    let view = view::View::new(records, lowercase_ext, &std::env::current_dir()?);
+    let options = [MountOption::RO, MountOption::FSName("classifiles".to_owned()), MountOption::DefaultPermissions];
+    let mut session = Session::new(view, mountpoint, &options)
+        .map_err(|e| format!("could not mount {}: {}", mountpoint.display(), e))?;
+    info!(log, "Mounted {} files at {}", records.len(), mountpoint.display());
+    // readiness of the NAME-mount.service written by install-service
+    if let Err(e) = crate::sd_notify::notify("READY=1") {
+        slog::warn!(log, "Could not notify systemd: {}", e);
+    }
+    session.run().map_err(|e| format!("could not serve {}: {}", mountpoint.display(), e))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "fuse"))]
+pub fn mount(_records: &[Record], _lowercase_ext: bool, _mountpoint: &Path, _log: &Logger) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(crate::ClassifierError(crate::ErrorKind::Config,
+        "mount requires classifiles to be built with the fuse feature".to_owned()
+    )))
+}
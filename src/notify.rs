@@ -0,0 +1,200 @@
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use slog::{Logger, debug, warn};
+
+use crate::email::{self, Email};
+use crate::history::Snapshot;
+use crate::{manifest, report};
+
+// notifications sent when a scan or sync finishes, successfully or not
+#[derive(Debug, Clone, Default)]
+pub struct Notify {
+    // command run with the stats JSON on stdin, {status} and {output} in the arguments are replaced
+    pub exec: Vec<String>,
+    // URL the stats JSON is POSTed to (requires the webhooks feature)
+    pub webhook: Option<String>,
+    // notify-send on Linux, Notification Center on macOS
+    pub desktop: bool,
+    // mails the report of the output tree (requires the email feature)
+    pub email: Option<Email>,
+}
+
+impl Notify {
+    pub fn is_empty(&self) -> bool {
+        self.exec.is_empty() && self.webhook.is_none() && !self.desktop && self.email.is_none()
+    }
+}
+
+// completed, file-errors (completed, but some files failed), policy-violations (completed, but the files violate
+// the policy) or failed
+fn status(result: &Result<(), Box<dyn Error>>) -> &'static str {
+    match result {
+        Ok(()) => "completed",
+        Err(e) => match e.downcast_ref::<crate::ClassifierError>() {
+            Some(e) if e.kind() == crate::ErrorKind::FileErrors => "file-errors",
+            Some(e) if e.kind() == crate::ErrorKind::Policy => "policy-violations",
+            _ => "failed",
+        },
+    }
+}
+
+pub fn stats(verb: &str, input: &Path, output: &Path, elapsed: Duration, snapshot: Option<&Snapshot>,
+    result: &Result<(), Box<dyn Error>>) -> Value {
+
+    json!({
+        "verb": verb,
+        "status": status(result),
+        "error": result.as_ref().err().map(|e| e.to_string()),
+        "input": input,
+        "output": output,
+        "seconds": elapsed.as_secs(),
+        "files": snapshot.map(|s| s.total.count),
+        "size": snapshot.map(|s| s.total.size),
+        "types": snapshot.map(|s| &s.types),
+    })
+}
+
+fn run_command(exec: &[String], stats: &Value, log: &Logger) -> Result<(), Box<dyn Error>> {
+    let status = stats["status"].as_str().unwrap_or("");
+    let output = stats["output"].as_str().unwrap_or("");
+    let mut args = exec.iter().map(|arg| arg.replace("{status}", status).replace("{output}", output));
+    let mut command = Command::new(args.next().ok_or("empty command")?);
+    command.args(args).stdin(Stdio::piped());
+    debug!(log, "Running notification command {:?}", command);
+
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // a command that does not read its input is fine
+        let _ = stdin.write_all(format!("{}\n", stats).as_bytes());
+    }
+    match child.wait()? {
+        status if status.success() => Ok(()),
+        status => Err(format!("{} failed: {}", exec[0], status).into()),
+    }
+}
+
+fn desktop_message(stats: &Value) -> String {
+    let output = stats["output"].as_str().unwrap_or("");
+    match (stats["status"].as_str(), stats["files"].as_u64()) {
+        (Some("completed"), Some(files)) => format!("{} files classified into {}", files, output),
+        (Some("file-errors"), Some(files)) => format!("{} files classified into {}, some failed", files, output),
+        (Some("policy-violations"), Some(files)) =>
+            format!("{} files classified into {}, policy violated", files, output),
+        _ => format!("Classifying into {} failed", output),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn desktop_command(message: &str) -> Command {
+    let quoted = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!("display notification {} with title \"classifiles\"", quoted(message)));
+    command
+}
+
+#[cfg(not(target_os = "macos"))]
+fn desktop_command(message: &str) -> Command {
+    let mut command = Command::new("notify-send");
+    command.args(["--app-name=classifiles", "classifiles", message]);
+    command
+}
+
+// the text report of the output tree, and the HTML one if asked for, after a line with the outcome of the run;
+// only the outcome when the run did not write a manifest
+fn email_bodies(output_root: &Path, stats: &Value, html: bool) -> Result<(String, Option<String>), Box<dyn Error>> {
+    let mut text = format!("classifiles {} of {} into {}: {}\n", stats["verb"].as_str().unwrap_or(""),
+        stats["input"].as_str().unwrap_or(""), output_root.display(), stats["status"].as_str().unwrap_or(""));
+    if let Some(error) = stats["error"].as_str() {
+        text += &format!("\n{}\n", error);
+    }
+    if stats["files"].is_null() {
+        return Ok((text, None));
+    }
+    let (header, records) = manifest::read_manifest_file_with_header(&manifest::manifest_path(output_root))?;
+    let summary = report::summarize(&records);
+    let mut text = format!("{}\n", text).into_bytes();
+    report::write_text(&summary, header.as_ref(), None, &mut text)?;
+    let html = match html {
+        true => {
+            let mut html = Vec::new();
+            report::write_html(&summary, header.as_ref(), None, &mut html)?;
+            Some(String::from_utf8(html)?)
+        }
+        false => None,
+    };
+    Ok((String::from_utf8(text)?, html))
+}
+
+fn send_email(email: &Email, output_root: &Path, stats: &Value) -> Result<(), Box<dyn Error>> {
+    let subject = format!("classifiles {} {}: {}", stats["verb"].as_str().unwrap_or(""),
+        stats["status"].as_str().unwrap_or(""), output_root.display());
+    let (text, html) = email_bodies(output_root, stats, email.html)?;
+    email::send(email, &subject, text, html)
+}
+
+#[cfg(feature = "webhooks")]
+fn post(url: &str, stats: &Value) -> Result<(), Box<dyn Error>> {
+    let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(30)).build();
+    agent.post(url).set("Content-Type", "application/json").send_string(&stats.to_string())?;
+    Ok(())
+}
+
+#[cfg(feature = "webhooks")]
+fn check_webhook() -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+#[cfg(not(feature = "webhooks"))]
+fn post(_url: &str, _stats: &Value) -> Result<(), Box<dyn Error>> {
+    check_webhook()
+}
+
+#[cfg(not(feature = "webhooks"))]
+fn check_webhook() -> Result<(), Box<dyn Error>> {
+    Err(Box::new(crate::ClassifierError(crate::ErrorKind::Config,
+        "notify.webhook requires classifiles to be built with the webhooks feature".to_owned()
+    )))
+}
+
+// fails before a long scan instead of after it
+pub fn check(notify: &Notify) -> Result<(), Box<dyn Error>> {
+    if notify.webhook.is_some() {
+        check_webhook()?;
+    }
+    if let Some(email) = &notify.email {
+        email::check(email)?;
+    }
+    Ok(())
+}
+
+// failures are only logged, they never change the outcome of the run
+pub fn send(notify: &Notify, output_root: &Path, stats: &Value, log: &Logger) {
+    if !notify.exec.is_empty() {
+        if let Err(e) = run_command(&notify.exec, stats, log) {
+            warn!(log, "Could not run notification command: {}", e);
+        }
+    }
+    if let Some(url) = &notify.webhook {
+        if let Err(e) = post(url, stats) {
+            warn!(log, "Could not POST to {}: {}", url, e);
+        }
+    }
+    if notify.desktop {
+        match desktop_command(&desktop_message(stats)).status() {
+            Ok(status) if status.success() => (),
+            Ok(status) => warn!(log, "Desktop notification failed: {}", status),
+            Err(e) => warn!(log, "Could not send desktop notification: {}", e),
+        }
+    }
+    if let Some(email) = &notify.email {
+        match send_email(email, output_root, stats) {
+            Ok(()) => debug!(log, "Mailed the report to {}", email.to.join(", ")),
+            Err(e) => warn!(log, "Could not mail the report: {}", e),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+// libmagic's description of anything it does not recognize, useless as a key
+static GENERIC_DESCRIPTION: &str = "data";
+const SIGNATURE_LEN: usize = 8;
+
+// user correction keyed by libmagic description and/or leading bytes (hex), all given keys have to match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverrideRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leading_bytes: Option<String>,
+    pub mime: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ext: Option<String>,
+}
+
+pub struct Signature {
+    pub description: Option<String>,
+    pub leading_bytes: Vec<u8>,
+}
+
+impl Signature {
+    pub fn of(path: &Path, description: Option<String>) -> io::Result<Self> {
+        let mut leading_bytes = Vec::with_capacity(SIGNATURE_LEN);
+        File::open(path)?.take(SIGNATURE_LEN as u64).read_to_end(&mut leading_bytes)?;
+        Ok(Self{description, leading_bytes})
+    }
+
+    // of content read into memory, e.g. an archive entry
+    pub fn of_bytes(data: &[u8], description: Option<String>) -> Self {
+        Self{description, leading_bytes: data[..data.len().min(SIGNATURE_LEN)].to_vec()}
+    }
+
+    // rule skeleton for a correction of this file: the description if it says anything, the leading bytes otherwise
+    pub fn rule(&self, mime: String, ext: Option<String>) -> OverrideRule {
+        match &self.description {
+            Some(d) if !d.is_empty() && d != GENERIC_DESCRIPTION =>
+                OverrideRule{description: Some(d.clone()), leading_bytes: None, mime, ext},
+            _ => OverrideRule{description: None, leading_bytes: Some(encode_hex(&self.leading_bytes)), mime, ext},
+        }
+    }
+}
+
+impl OverrideRule {
+    pub fn same_key(&self, other: &OverrideRule) -> bool {
+        self.description == other.description && self.leading_bytes == other.leading_bytes
+    }
+
+    pub fn matches(&self, signature: &Signature) -> bool {
+        if self.description.is_none() && self.leading_bytes.is_none() {
+            return false;
+        }
+
+        let description_matches = match &self.description {
+            Some(d) => signature.description.as_ref() == Some(d),
+            None => true,
+        };
+        let bytes_match = match self.leading_bytes.as_deref().map(decode_hex) {
+            Some(Some(bytes)) => signature.leading_bytes.starts_with(&bytes),
+            Some(None) => false,
+            None => true,
+        };
+        description_matches && bytes_match
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
+
+// local override db learned from user corrections
+pub fn default_db_path() -> Option<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+    Some(data_home.join("classifiles").join("overrides.yaml"))
+}
+
+pub fn load_rules(path: &Path) -> Result<Vec<OverrideRule>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let rules_str = fs::read_to_string(path)?;
+    Ok(serde_yaml::from_str(&rules_str)?)
+}
+
+// merges new rules into the rules file, a new rule replaces one with the same key
+pub fn save_rules(path: &Path, new_rules: Vec<OverrideRule>) -> Result<(), Box<dyn Error>> {
+    let mut rules = load_rules(path)?;
+    for rule in new_rules {
+        match rules.iter_mut().find(|r| r.same_key(&rule)) {
+            Some(existing) => *existing = rule,
+            None => rules.push(rule),
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_yaml::to_string(&rules)?)?;
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use std::ffi::CStr;
+use std::os::unix::fs::MetadataExt;
+
+use fnv::FnvHashMap;
+use libc::c_char;
+
+// user and group names by id, looked up once per id; ids without a name (or unreadable /etc/passwd and /etc/group,
+// e.g. under --sandbox) are kept as numbers
+#[derive(Default)]
+pub struct Owners {
+    users: FnvHashMap<u32, String>,
+    groups: FnvHashMap<u32, String>,
+}
+
+// getpwuid_r and getgrgid_r report ERANGE until the buffer fits the entry
+fn lookup(id: u32, get: impl Fn(&mut [c_char]) -> Result<Option<*const c_char>, i32>) -> String {
+    let mut buf: Vec<c_char> = vec![0; 1024];
+    loop {
+        match get(&mut buf) {
+            // SAFETY: the name points into buf, which outlives it here
+            Ok(Some(name)) => return unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned(),
+            Err(libc::ERANGE) if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            _ => return id.to_string(),
+        }
+    }
+}
+
+fn user_name(uid: u32) -> String {
+    lookup(uid, |buf| {
+        // SAFETY: passwd and result are written by getpwuid_r, buf is as long as passed
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::passwd = std::ptr::null_mut();
+        match unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) } {
+            0 if result.is_null() => Ok(None),
+            0 => Ok(Some(passwd.pw_name as *const c_char)),
+            e => Err(e),
+        }
+    })
+}
+
+fn group_name(gid: u32) -> String {
+    lookup(gid, |buf| {
+        // SAFETY: group and result are written by getgrgid_r, buf is as long as passed
+        let mut group: libc::group = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::group = std::ptr::null_mut();
+        match unsafe { libc::getgrgid_r(gid, &mut group, buf.as_mut_ptr(), buf.len(), &mut result) } {
+            0 if result.is_null() => Ok(None),
+            0 => Ok(Some(group.gr_name as *const c_char)),
+            e => Err(e),
+        }
+    })
+}
+
+impl Owners {
+    // owner and group of a file
+    pub fn of(&mut self, meta: &std::fs::Metadata) -> (String, String) {
+        let owner = self.users.entry(meta.uid()).or_insert_with_key(|&uid| user_name(uid)).clone();
+        let group = self.groups.entry(meta.gid()).or_insert_with_key(|&gid| group_name(gid)).clone();
+        (owner, group)
+    }
+}
+
+// the most notable trait of the permission bits, for layouts like all world-writable files
+pub fn perm_class(mode: u32) -> &'static str {
+    match mode {
+        _ if mode & libc::S_ISUID != 0 => "setuid",
+        _ if mode & libc::S_ISGID != 0 => "setgid",
+        _ if mode & 0o002 != 0 => "world-writable",
+        _ if mode & 0o020 != 0 => "group-writable",
+        _ if mode & 0o077 == 0 => "private",
+        _ => "standard",
+    }
+}
@@ -0,0 +1,65 @@
+use fnv::FnvHashMap;
+
+#[cfg(feature = "phash")]
+use std::{error::Error, path::Path};
+
+// difference hash: one bit per horizontally adjacent pixel pair of a 9x8 grayscale thumbnail,
+// so resized or recompressed copies of an image end up only a few bits apart
+#[cfg(feature = "phash")]
+pub fn dhash(path: &Path) -> Result<u64, Box<dyn Error>> {
+    use image::imageops::FilterType;
+
+    let thumb = image::open(path)?
+        .resize_exact(9, 8, FilterType::Triangle)
+        .into_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if thumb.get_pixel(x, y)[0] < thumb.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    Ok(hash)
+}
+
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+// single-linkage grouping of hashes within max_distance of each other,
+// returns groups of indices into hashes (only groups with more than one member)
+pub fn group_similar(hashes: &[u64], max_distance: u32) -> Vec<Vec<usize>> {
+    let mut parent: Vec<usize> = (0..hashes.len()).collect();
+
+    for i in 0..hashes.len() {
+        for j in (i + 1)..hashes.len() {
+            if distance(hashes[i], hashes[j]) <= max_distance {
+                let (ri, rj) = (root(&mut parent, i), root(&mut parent, j));
+                parent[rj] = ri;
+            }
+        }
+    }
+
+    let mut groups: FnvHashMap<usize, Vec<usize>> = FnvHashMap::default();
+    for i in 0..hashes.len() {
+        let r = root(&mut parent, i);
+        groups.entry(r).or_default().push(i);
+    }
+
+    let mut groups: Vec<Vec<usize>> = groups.into_values()
+        .filter(|g| g.len() > 1)
+        .collect();
+    groups.sort_by_key(|g| g[0]);
+    groups
+}
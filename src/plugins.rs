@@ -0,0 +1,108 @@
+// detector plugins are shared objects implementing the C ABI in include/classifiles_plugin.h
+
+#[cfg(feature = "plugins")]
+mod dynamic {
+    use std::error::Error;
+    use std::ffi::CString;
+    use std::fs;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    use libc::{c_char, c_int, size_t};
+    use libloading::Library;
+
+    const ABI_VERSION: u32 = 1;
+    const MIME_CAP: usize = 256;
+    const EXT_CAP: usize = 32;
+
+    type AbiVersionFn = unsafe extern "C" fn() -> u32;
+    type DetectFn = unsafe extern "C" fn(*const u8, size_t, *const c_char, *mut c_char, size_t, *mut c_char, size_t) -> c_int;
+
+    pub struct Plugin {
+        path: PathBuf,
+        detect: DetectFn,
+        // keeps detect valid
+        _library: Library,
+    }
+
+    fn c_string(buf: &[u8]) -> Option<String> {
+        let end = buf.iter().position(|&b| b == 0)?;
+        Some(String::from_utf8_lossy(&buf[..end]).into_owned())
+    }
+
+    impl Plugin {
+        fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+            // loading runs the library's initializers, plugins directories have to be trusted
+            let library = unsafe { Library::new(path)? };
+            let abi_version = unsafe { library.get::<AbiVersionFn>(b"classifiles_plugin_abi_version\0")?() };
+            if abi_version != ABI_VERSION {
+                return Err(format!("unsupported plugin ABI version {}, expected {}", abi_version, ABI_VERSION).into());
+            }
+            let detect = unsafe { *library.get::<DetectFn>(b"classifiles_detect\0")? };
+            Ok(Self{path: path.to_owned(), detect, _library: library})
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+
+        pub fn detect(&self, buf: &[u8], path: &Path) -> Option<(String, Option<String>)> {
+            let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+            let mut mime = [0u8; MIME_CAP];
+            let mut ext = [0u8; EXT_CAP];
+
+            let found = unsafe {
+                (self.detect)(buf.as_ptr(), buf.len(), c_path.as_ptr(),
+                    mime.as_mut_ptr() as *mut c_char, MIME_CAP, ext.as_mut_ptr() as *mut c_char, EXT_CAP)
+            };
+            if found != 1 {
+                return None;
+            }
+            let mime = c_string(&mime).filter(|m| m.contains('/'))?;
+            Some((mime, c_string(&ext).filter(|e| !e.is_empty())))
+        }
+    }
+
+    pub fn load_dir(dir: &Path) -> Result<Vec<Plugin>, Box<dyn Error>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|e| format!("could not read plugins directory {}: {}", dir.display(), e))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| matches!(p.extension().and_then(|e| e.to_str()), Some("so") | Some("dylib")))
+            .collect();
+        paths.sort();
+
+        paths.iter()
+            .map(|p| Plugin::load(p).map_err(|e| -> Box<dyn Error> { format!("could not load plugin {}: {}", p.display(), e).into() }))
+            .collect()
+    }
+}
+
+#[cfg(feature = "plugins")]
+pub use dynamic::{Plugin, load_dir};
+
+#[cfg(not(feature = "plugins"))]
+mod stub {
+    use std::error::Error;
+    use std::path::Path;
+
+    pub enum Plugin {}
+
+    impl Plugin {
+        pub fn path(&self) -> &Path {
+            match *self {}
+        }
+
+        pub fn detect(&self, _buf: &[u8], _path: &Path) -> Option<(String, Option<String>)> {
+            match *self {}
+        }
+    }
+
+    pub fn load_dir(_dir: &Path) -> Result<Vec<Plugin>, Box<dyn Error>> {
+        Err(Box::new(crate::ClassifierError(crate::ErrorKind::Config,
+            "detector plugins require classifiles to be built with the plugins feature".to_owned()
+        )))
+    }
+}
+
+#[cfg(not(feature = "plugins"))]
+pub use stub::{Plugin, load_dir};
@@ -0,0 +1,70 @@
+use std::os::unix::ffi::OsStrExt;
+
+use crate::manifest::Record;
+use crate::tags::glob_matches;
+
+// violating files listed per rule, the count covers all of them
+const LISTED_FILES: usize = 20;
+
+// an expectation checked after each scan and sync, e.g. no application/x-dosexec below /srv/uploads
+#[derive(Debug, Clone, Default)]
+pub struct PolicyRule {
+    // shown with the violations instead of the position of the rule
+    pub name: Option<String>,
+    // glob over the whole input path as for tag rules, all files if None
+    pub path: Option<String>,
+    // MIME patterns the files must not match
+    pub deny: Vec<String>,
+    // MIME patterns the files must match one of, if any are given
+    pub allow: Vec<String>,
+    // violating files tolerated, e.g. the known exceptions of a baseline
+    pub max_count: usize,
+}
+
+impl PolicyRule {
+    fn violated_by(&self, record: &Record) -> bool {
+        let mime = record.mime.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN);
+        self.path.as_deref().is_none_or(|pattern| glob_matches(pattern.as_bytes(), record.path.as_os_str().as_bytes()))
+            && (self.deny.iter().any(|pattern| crate::mime_matches(pattern, mime))
+                || (!self.allow.is_empty() && !self.allow.iter().any(|pattern| crate::mime_matches(pattern, mime))))
+    }
+}
+
+pub struct Violation<'a> {
+    pub rule: String,
+    pub max_count: usize,
+    pub records: Vec<&'a Record>,
+}
+
+// rules with more violating files than they tolerate
+pub fn check<'a>(rules: &[PolicyRule], records: impl Iterator<Item = &'a Record> + Clone) -> Vec<Violation<'a>> {
+    rules.iter().enumerate().filter_map(|(i, rule)| {
+        let violating: Vec<&Record> = records.clone().filter(|record| rule.violated_by(record)).collect();
+        match violating.len() > rule.max_count {
+            true => Some(Violation{
+                rule: rule.name.clone().unwrap_or_else(|| format!("policy[{}]", i)),
+                max_count: rule.max_count,
+                records: violating,
+            }),
+            false => None,
+        }
+    }).collect()
+}
+
+// the message of the error scan and sync end with
+pub fn summary(violations: &[Violation]) -> String {
+    let mut summary = format!("{} policy rules violated", violations.len());
+    for violation in violations {
+        summary += &format!("\n{}: {} files", violation.rule, violation.records.len());
+        if violation.max_count > 0 {
+            summary += &format!(" ({} tolerated)", violation.max_count);
+        }
+        for record in violation.records.iter().take(LISTED_FILES) {
+            summary += &format!("\n    {}  {}", record.path.display(), record.mime.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN));
+        }
+        if violation.records.len() > LISTED_FILES {
+            summary += &format!("\n    and {} more", violation.records.len() - LISTED_FILES);
+        }
+    }
+    summary
+}
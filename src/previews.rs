@@ -0,0 +1,59 @@
+use std::{error::Error, fs};
+use std::path::{Path, PathBuf};
+
+static PREVIEW_DIR: &str = "previews";
+
+const THUMBNAIL_SIZE: u32 = 256;
+
+// previews mirror the link tree under OUTPUT_DIR/previews
+pub fn preview_path(output_root: &Path, link: &Path) -> PathBuf {
+    let link_rel = link.strip_prefix(output_root).unwrap_or(link);
+    let mut preview = output_root.join(PREVIEW_DIR).join(link_rel).into_os_string();
+    preview.push(".png");
+    PathBuf::from(preview)
+}
+
+pub fn write_preview(input: &Path, mime: &str, dst: &Path) -> Result<bool, Box<dyn Error>> {
+    if mime.starts_with("image/") {
+        if let Some(dst_dir) = dst.parent() {
+            fs::create_dir_all(dst_dir)?;
+        }
+        image::open(input)?
+            .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+            .save(dst)?;
+        return Ok(true);
+    }
+
+    #[cfg(feature = "video-previews")]
+    {
+        if mime.starts_with("video/") {
+            if let Some(dst_dir) = dst.parent() {
+                fs::create_dir_all(dst_dir)?;
+            }
+            video_keyframe(input, dst)?;
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+// grabs the first keyframe after one second using the ffmpeg binary
+#[cfg(feature = "video-previews")]
+fn video_keyframe(input: &Path, dst: &Path) -> Result<(), Box<dyn Error>> {
+    use std::process::{Command, Stdio};
+
+    let status = Command::new("ffmpeg")
+        .args(&["-nostdin", "-loglevel", "error", "-y", "-ss", "1", "-i"])
+        .arg(input)
+        .args(&["-frames:v", "1", "-vf"])
+        .arg(format!("scale='min({0},iw)':'min({0},ih)':force_original_aspect_ratio=decrease", THUMBNAIL_SIZE))
+        .arg(dst)
+        .stdout(Stdio::null())
+        .status()?;
+
+    if !status.success() {
+        return Err(format!("ffmpeg exited with {}", status).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::str::FromStr;
+
+use crate::{Config, DetectionStrategy, Detector, Params};
+
+// named combinations of options for scan --profile, explicit --set values and flags still apply on top
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    // file name extensions only, file contents are never read
+    Fast,
+    // every built-in detector including libmagic and the zip container peek, the most specific result wins
+    Accurate,
+    // accurate detection plus content hashes, each directory visited once
+    Forensic,
+    // accurate detection plus content hashes, staying on one filesystem (no /proc or network mounts),
+    // for audits of system trees with report --security
+    Security,
+}
+
+pub static PROFILE_NAMES: &[&str] = &["fast", "accurate", "forensic", "security"];
+
+impl Profile {
+    pub fn apply_config(self, config: &mut Config) {
+        match self {
+            Profile::Fast => {
+                config.detectors = vec![Detector::Extension];
+                config.detection_strategy = DetectionStrategy::FirstMatch;
+            }
+            Profile::Accurate | Profile::Forensic | Profile::Security => {
+                config.detectors = vec![Detector::Overrides, Detector::Plugins, Detector::Container,
+                    Detector::LibMagic, Detector::TreeMagic, Detector::Extension];
+                config.detection_strategy = DetectionStrategy::BestConfidence;
+            }
+        }
+    }
+
+    pub fn apply_params(self, params: &mut Params) {
+        match self {
+            Profile::Forensic => {
+                params.content_hash = true;
+                params.unique_dirs = true;
+            }
+            Profile::Security => {
+                params.content_hash = true;
+                params.one_file_system = true;
+            }
+            Profile::Fast | Profile::Accurate => (),
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(Profile::Fast),
+            "accurate" => Ok(Profile::Accurate),
+            "forensic" => Ok(Profile::Forensic),
+            "security" => Ok(Profile::Security),
+            _ => Err(format!("invalid profile {}, valid profiles are: {}", s, PROFILE_NAMES.join(", "))),
+        }
+    }
+}
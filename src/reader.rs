@@ -0,0 +1,267 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+use std::{ptr, slice};
+
+use slog::{Logger, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadBackend {
+    // plain read calls
+    #[default]
+    Blocking,
+    // io_uring with several reads in flight per file (requires the io-uring feature, Linux only)
+    IoUring,
+}
+
+impl FromStr for ReadBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blocking" => Ok(ReadBackend::Blocking),
+            "io_uring" => Ok(ReadBackend::IoUring),
+            _ => Err(format!("invalid read backend {}, valid backends are: blocking, io_uring", s)),
+        }
+    }
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::fs::FileExt;
+    use std::os::unix::io::AsRawFd;
+
+    use io_uring::{opcode, types, IoUring};
+
+    // reads submitted at once when hashing
+    const QUEUE_DEPTH: usize = 8;
+
+    pub struct Ring {
+        ring: IoUring,
+        chunks: Vec<Vec<u8>>,
+    }
+
+    // submits one read per buffer and waits for all of them, returns the bytes read into each
+    fn read_batch(ring: &mut IoUring, file: &File, bufs: &mut [(u64, &mut [u8])]) -> io::Result<Vec<usize>> {
+        for (i, (offset, buf)) in bufs.iter_mut().enumerate() {
+            let entry = opcode::Read::new(types::Fd(file.as_raw_fd()), buf.as_mut_ptr(), buf.len() as u32)
+                .offset(*offset)
+                .build()
+                .user_data(i as u64);
+            // the buffers stay borrowed until every completion has been reaped below
+            unsafe {
+                ring.submission().push(&entry)
+                    .map_err(|_| io::Error::new(io::ErrorKind::Other, "io_uring submission queue is full"))?;
+            }
+        }
+        ring.submit_and_wait(bufs.len())?;
+
+        // all completions are reaped before reporting an error so none leaks into the next batch
+        let mut read_lens = vec![0; bufs.len()];
+        let mut error = None;
+        for cqe in ring.completion() {
+            match cqe.result() {
+                res if res < 0 => error = Some(io::Error::from_raw_os_error(-res)),
+                res => read_lens[cqe.user_data() as usize] = res as usize,
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(read_lens),
+        }
+    }
+
+    impl Ring {
+        pub fn new(chunk_len: usize) -> io::Result<Self> {
+            let ring = IoUring::new(QUEUE_DEPTH as u32)?;
+            let chunks = (0..QUEUE_DEPTH).map(|_| vec![0; chunk_len]).collect();
+            Ok(Self{ring, chunks})
+        }
+
+        pub fn read_leading(&mut self, file: &File, buf: &mut [u8]) -> io::Result<usize> {
+            let mut filled = 0;
+            while filled < buf.len() {
+                match read_batch(&mut self.ring, file, &mut [(filled as u64, &mut buf[filled..])])?[0] {
+                    0 => break,
+                    n => filled += n,
+                }
+            }
+            Ok(filled)
+        }
+
+        // hands the whole file to f chunk by chunk, in order
+        pub fn read_chunks(&mut self, file: &File, f: &mut dyn FnMut(&[u8])) -> io::Result<()> {
+            let len = file.metadata()?.len();
+            let chunk_len = self.chunks[0].len() as u64;
+            let mut offset = 0;
+
+            while offset < len {
+                let mut batch: Vec<(u64, &mut [u8])> = self.chunks.iter_mut()
+                    .enumerate()
+                    .map(|(i, chunk)| (offset + i as u64 * chunk_len, chunk))
+                    .take_while(|(chunk_offset, _)| *chunk_offset < len)
+                    .map(|(chunk_offset, chunk)| {
+                        let expected = chunk_len.min(len - chunk_offset) as usize;
+                        (chunk_offset, &mut chunk[..expected])
+                    })
+                    .collect();
+                let read_lens = read_batch(&mut self.ring, file, &mut batch)?;
+
+                // short reads are rare on regular files, the rest is read synchronously
+                for ((chunk_offset, chunk), read_len) in batch.iter_mut().zip(read_lens) {
+                    if read_len < chunk.len() {
+                        file.read_exact_at(&mut chunk[read_len..], *chunk_offset + read_len as u64)?;
+                    }
+                    f(chunk);
+                }
+                offset += batch.len() as u64 * chunk_len;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+mod uring {
+    use std::fs::File;
+    use std::io;
+
+    pub enum Ring {}
+
+    impl Ring {
+        pub fn new(_chunk_len: usize) -> io::Result<Self> {
+            Err(io::Error::other("io_uring support not built"))
+        }
+
+        pub fn read_leading(&mut self, _file: &File, _buf: &mut [u8]) -> io::Result<usize> {
+            match *self {}
+        }
+
+        pub fn read_chunks(&mut self, _file: &File, _f: &mut dyn FnMut(&[u8])) -> io::Result<()> {
+            match *self {}
+        }
+    }
+}
+
+use uring::Ring;
+
+// read-only mapping of a whole file, unmapped on drop
+struct Mapping {
+    addr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(file: &File, len: usize) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        let addr = unsafe { libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // only a hint, the content is read front to back once
+        unsafe { libc::madvise(addr, len, libc::MADV_SEQUENTIAL) };
+        Ok(Self{addr, len})
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.addr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.addr, self.len) };
+    }
+}
+
+// a file truncated by another process while it is mapped kills the scan with SIGBUS, files on read-only filesystems
+// (snapshots, read-only mounts) cannot be
+fn on_read_only_fs(file: &File) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    unsafe { libc::fstatvfs(file.as_raw_fd(), &mut stat) == 0 && stat.f_flag & libc::ST_RDONLY != 0 }
+}
+
+// reads the leading bytes for sniffing and whole files for hashing, buffers are reused across files
+pub struct FileReader {
+    sniff_buf: Vec<u8>,
+    read_buf: Vec<u8>,
+    ring: Option<Ring>,
+    // files at least this large on read-only filesystems are memory mapped instead of read
+    mmap_min_size: Option<u64>,
+}
+
+impl FileReader {
+    pub fn new(backend: ReadBackend, sniff_len: usize, read_len: usize, mmap_min_size: Option<u64>, log: &Logger)
+        -> Result<Self, Box<dyn Error>> {
+
+        let ring = match backend {
+            ReadBackend::Blocking => None,
+            ReadBackend::IoUring if !cfg!(all(feature = "io-uring", target_os = "linux")) => {
+                return Err(Box::new(crate::ClassifierError(crate::ErrorKind::Config,
+                    "the io_uring read backend requires classifiles to be built with the io-uring feature on Linux".to_owned()
+                )));
+            }
+            // kernels before 5.1 or seccomp filters refuse to set up a ring
+            ReadBackend::IoUring => match Ring::new(read_len) {
+                Ok(ring) => Some(ring),
+                Err(e) => {
+                    warn!(log, "io_uring unavailable, falling back to blocking reads: {}", e);
+                    None
+                }
+            },
+        };
+        // a 32-bit address space cannot map multi-GB files
+        let mmap_min_size = mmap_min_size.filter(|_| cfg!(target_pointer_width = "64"));
+        Ok(Self{sniff_buf: vec![0; sniff_len], read_buf: vec![0; read_len], ring, mmap_min_size})
+    }
+
+    // the first sniff_len bytes, fewer for shorter files
+    pub fn read_leading(&mut self, path: &Path) -> io::Result<&[u8]> {
+        let mut file = File::open(path)?;
+        let len = match &mut self.ring {
+            Some(ring) => ring.read_leading(&file, &mut self.sniff_buf)?,
+            None => {
+                let mut filled = 0;
+                while filled < self.sniff_buf.len() {
+                    match file.read(&mut self.sniff_buf[filled..])? {
+                        0 => break,
+                        n => filled += n,
+                    }
+                }
+                filled
+            }
+        };
+        Ok(&self.sniff_buf[..len])
+    }
+
+    // large files are handed to f in one piece when mapped, otherwise chunk by chunk
+    pub fn read_chunks(&mut self, path: &Path, f: &mut dyn FnMut(&[u8])) -> io::Result<()> {
+        let mut file = File::open(path)?;
+        if let Some(min_size) = self.mmap_min_size {
+            let len = file.metadata()?.len();
+            // mapping fails e.g. on some network filesystems, those are read normally
+            if len >= min_size && len > 0 && on_read_only_fs(&file) {
+                if let Ok(mapping) = Mapping::new(&file, len as usize) {
+                    f(mapping.as_slice());
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(ring) = &mut self.ring {
+            return ring.read_chunks(&file, f);
+        }
+        loop {
+            match file.read(&mut self.read_buf)? {
+                0 => return Ok(()),
+                n => f(&self.read_buf[..n]),
+            }
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+
+use fnv::FnvHashMap;
+
+use crate::manifest::{Header, Record};
+
+static OCTET_STREAM: &str = "application/octet-stream";
+
+pub struct TypeStats {
+    pub mime: String,
+    pub count: usize,
+    pub size: u64,
+}
+
+pub struct Summary<'a> {
+    pub types: Vec<TypeStats>,
+    // files with no detected type (or just a generic byte stream)
+    pub unknown: Vec<&'a Record>,
+    // files with a detected type but no extension to go with it
+    pub ambiguous: Vec<&'a Record>,
+    pub total_count: usize,
+    pub total_size: u64,
+}
+
+pub fn summarize(records: &[Record]) -> Summary<'_> {
+    let mut type_map: FnvHashMap<&str, TypeStats> = FnvHashMap::default();
+    let mut unknown = Vec::new();
+    let mut ambiguous = Vec::new();
+    let mut total_size = 0;
+
+    for record in records {
+        let size = record.size.unwrap_or(0);
+        total_size += size;
+
+        let mime = record.mime.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN);
+        let stats = type_map.entry(mime).or_insert_with(|| TypeStats{mime: mime.to_owned(), count: 0, size: 0});
+        stats.count += 1;
+        stats.size += size;
+
+        match &record.mime {
+            None => unknown.push(record),
+            Some(m) if m == OCTET_STREAM => unknown.push(record),
+            Some(_) if record.ext.is_none() => ambiguous.push(record),
+            _ => (),
+        }
+    }
+
+    let mut types: Vec<TypeStats> = type_map.into_values().collect();
+    types.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.mime.cmp(&b.mime)));
+
+    Summary{types, unknown, ambiguous, total_count: records.len(), total_size}
+}
+
+// binary executables and libraries, scripts only count with an execute bit
+static EXECUTABLE_TYPES: &[&str] = &[
+    "application/x-executable",
+    "application/x-pie-executable",
+    "application/x-sharedlib",
+    "application/x-mach-binary",
+    "application/x-dosexec",
+    "application/vnd.microsoft.portable-executable",
+];
+
+// findings of report --security, from the permission bits recorded by the scan and the detected types
+pub struct Findings<'a> {
+    pub setuid: Vec<&'a Record>,
+    pub setgid: Vec<&'a Record>,
+    pub world_writable: Vec<&'a Record>,
+    // executables whose path matches none of the expected path globs
+    pub stray_executables: Vec<&'a Record>,
+}
+
+fn is_executable(record: &Record) -> bool {
+    let mime = record.mime.as_deref().unwrap_or("");
+    let execute_bit = record.mode.is_some_and(|mode| mode & 0o111 != 0);
+    EXECUTABLE_TYPES.contains(&mime)
+        || (execute_bit && (mime.starts_with("text/") || (mime.starts_with("application/x-") && mime.ends_with("script"))))
+}
+
+pub fn security_findings<'a>(records: &'a [Record], executable_paths: &[String]) -> Findings<'a> {
+    let mut findings = Findings{setuid: Vec::new(), setgid: Vec::new(), world_writable: Vec::new(),
+        stray_executables: Vec::new()};
+    for record in records {
+        let mode = match record.mode {
+            Some(mode) => mode,
+            None => continue,
+        };
+        if mode & libc::S_ISUID != 0 {
+            findings.setuid.push(record);
+        }
+        if mode & libc::S_ISGID != 0 {
+            findings.setgid.push(record);
+        }
+        if mode & 0o002 != 0 {
+            findings.world_writable.push(record);
+        }
+        let path = record.path.as_os_str().as_bytes();
+        if is_executable(record) && !executable_paths.iter().any(|glob| crate::tags::glob_matches(glob.as_bytes(), path)) {
+            findings.stray_executables.push(record);
+        }
+    }
+    findings
+}
+
+impl Findings<'_> {
+    fn sections(&self) -> [(&'static str, &[&Record]); 4] {
+        [
+            ("Setuid", &self.setuid),
+            ("Setgid", &self.setgid),
+            ("World-writable", &self.world_writable),
+            ("Executables outside expected paths", &self.stray_executables),
+        ]
+    }
+}
+
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", size, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+// e.g. classifiles 0.3.0, libmagic 5.45 (db sha256 1f2e...), shared-mime-info 2.4
+fn versions(header: &Header) -> String {
+    let mut versions = format!("classifiles {}", header.version);
+    if let Some(libmagic) = &header.libmagic_version {
+        versions += &format!(", libmagic {}", libmagic);
+        if let Some(sha256) = &header.libmagic_db_sha256 {
+            versions += &format!(" (db sha256 {})", sha256);
+        }
+    }
+    if let Some(shared_mime_info) = &header.shared_mime_info_version {
+        versions += &format!(", shared-mime-info {}", shared_mime_info);
+    }
+    versions
+}
+
+// manifests without a header predate it, their versions are unknown
+pub fn write_text(summary: &Summary, header: Option<&Header>, findings: Option<&Findings>, out: &mut dyn Write)
+    -> io::Result<()> {
+
+    if let Some(header) = header {
+        writeln!(out, "{}", versions(header))?;
+        writeln!(out)?;
+    }
+    writeln!(out, "{:>10}  {:>12}  type", "files", "size")?;
+    for t in &summary.types {
+        writeln!(out, "{:>10}  {:>12}  {}", t.count, format_size(t.size), t.mime)?;
+    }
+    writeln!(out, "{:>10}  {:>12}  total", summary.total_count, format_size(summary.total_size))?;
+    writeln!(out)?;
+    writeln!(out, "unknown: {}, ambiguous: {}", summary.unknown.len(), summary.ambiguous.len())?;
+    for (title, records) in findings.iter().flat_map(|findings| findings.sections()) {
+        writeln!(out)?;
+        writeln!(out, "{} ({}):", title.to_lowercase(), records.len())?;
+        for r in records {
+            writeln!(out, "  {} ({}, owner {}, mode {:04o})", r.path.display(),
+                r.mime.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN), r.owner.as_deref().unwrap_or("?"), r.mode.unwrap_or(0))?;
+        }
+    }
+    Ok(())
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// percent-encode a relative link so that it can be used as href
+fn escape_href(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'.' | b'-' | b'_' | b'~' => escaped.push(b as char),
+            _ => escaped.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    escaped
+}
+
+static HTML_STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { padding: 0.2em 0.8em; text-align: left; border-bottom: 1px solid #ddd; }
+td.num { text-align: right; white-space: nowrap; }
+.bar { background: #4a90d9; height: 0.9em; }
+";
+
+fn write_file_list(title: &str, records: &[&Record], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "<h2>{} ({})</h2>", title, records.len())?;
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "<table><tr><th>original path</th><th>type</th><th>size</th></tr>")?;
+    for r in records {
+        let path = r.path.to_string_lossy();
+        let path_cell = match &r.link {
+            Some(link) => format!("<a href=\"{}\">{}</a>", escape_href(&link.to_string_lossy()), escape_html(&path)),
+            None => escape_html(&path),
+        };
+        writeln!(out, "<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td></tr>",
+            path_cell,
+            escape_html(r.mime.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN)),
+            r.size.map(format_size).unwrap_or_default())?;
+    }
+    writeln!(out, "</table>")?;
+    Ok(())
+}
+
+// self-contained report, links are relative so it is meant to be placed in the output root
+pub fn write_html(summary: &Summary, header: Option<&Header>, findings: Option<&Findings>, out: &mut dyn Write)
+    -> io::Result<()> {
+
+    writeln!(out, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>classifiles report</title><style>{}</style></head><body>", HTML_STYLE)?;
+    writeln!(out, "<h1>classifiles report</h1>")?;
+    writeln!(out, "<p>{} files, {} total</p>", summary.total_count, format_size(summary.total_size))?;
+    if let Some(header) = header {
+        writeln!(out, "<p>{}</p>", escape_html(&versions(header)))?;
+    }
+
+    let max_size = summary.types.iter().map(|t| t.size).max().unwrap_or(0).max(1);
+    writeln!(out, "<h2>Types</h2>")?;
+    writeln!(out, "<table><tr><th>type</th><th>files</th><th>size</th><th></th></tr>")?;
+    for t in &summary.types {
+        let width = t.size as f64 / max_size as f64 * 300.0;
+        writeln!(out, "<tr><td><a href=\"{}/\">{}</a></td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+            <td><div class=\"bar\" style=\"width: {:.0}px\"></div></td></tr>",
+            escape_href(&t.mime), escape_html(&t.mime), t.count, format_size(t.size), width)?;
+    }
+    writeln!(out, "</table>")?;
+
+    write_file_list("Unknown", &summary.unknown, out)?;
+    write_file_list("Ambiguous", &summary.ambiguous, out)?;
+    for (title, records) in findings.iter().flat_map(|findings| findings.sections()) {
+        write_file_list(title, records, out)?;
+    }
+
+    writeln!(out, "</body></html>")?;
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+
+use crate::manifest::Record;
+use crate::mime_type::MimeType;
+use crate::overrides::{OverrideRule, Signature};
+
+static OCTET_STREAM: &str = "application/octet-stream";
+const HEXDUMP_LEN: usize = 64;
+
+// unknown, generic byte stream or no extension guessed
+pub fn needs_review(record: &Record) -> bool {
+    match &record.mime {
+        None => true,
+        Some(mime) => mime == OCTET_STREAM || record.ext.is_none(),
+    }
+}
+
+fn hexdump(path: &Path) -> io::Result<String> {
+    let mut buf = Vec::with_capacity(HEXDUMP_LEN);
+    File::open(path)?.take(HEXDUMP_LEN as u64).read_to_end(&mut buf)?;
+
+    let mut dump = String::new();
+    for (i, chunk) in buf.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk.iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        dump += &format!("{:08x}  {:<47}  |{}|\n", i * 16, hex.join(" "), ascii);
+    }
+    Ok(dump)
+}
+
+enum Answer {
+    Keep,
+    Quit,
+    Type(String, Option<String>),
+}
+
+fn ask(input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<Answer> {
+    write!(output, "type MIME [EXT], empty to keep, q to stop reviewing: ")?;
+    output.flush()?;
+
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(Answer::Quit);
+    }
+
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next()) {
+        (None, _) => Ok(Answer::Keep),
+        (Some("q"), None) => Ok(Answer::Quit),
+        (Some(mime), ext) if mime.parse::<MimeType>().is_ok() => {
+            Ok(Answer::Type(crate::mime_type::normalized(mime.to_owned()), ext.map(str::to_owned)))
+        }
+        _ => {
+            writeln!(output, "not a MIME type, keeping the detected one")?;
+            Ok(Answer::Keep)
+        }
+    }
+}
+
+// presents files that need review one by one and applies the chosen types,
+// a choice is reused for all later files with the same signature
+pub fn review(records: &mut [Record], describe: &dyn Fn(&Path) -> Option<String>,
+    input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<Vec<OverrideRule>> {
+
+    let mut decisions: Vec<OverrideRule> = Vec::new();
+    let review_count = records.iter().filter(|r| needs_review(r)).count();
+
+    for (i, record) in records.iter_mut().filter(|r| needs_review(r)).enumerate() {
+        let description = describe(&record.path);
+        let signature = Signature::of(&record.path, description.clone()).ok();
+
+        if let Some(rule) = signature.as_ref().and_then(|sig| decisions.iter().find(|r| r.matches(sig))) {
+            record.mime = Some(rule.mime.clone());
+            record.ext = rule.ext.clone();
+            record.alt_exts.clear();
+            continue;
+        }
+
+        writeln!(output, "\n[{}/{}] {}", i + 1, review_count, record.path.display())?;
+        writeln!(output, "detected: {} ({})",
+            record.mime.as_deref().unwrap_or("unknown"),
+            record.ext.as_deref().map_or("no extension".to_owned(), |e| format!(".{}", e)))?;
+        writeln!(output, "magic: {}", description.as_deref().unwrap_or("unavailable"))?;
+        match hexdump(&record.path) {
+            Ok(dump) => write!(output, "{}", dump)?,
+            Err(e) => writeln!(output, "cannot read file: {}", e)?,
+        }
+
+        match ask(input, output)? {
+            Answer::Keep => (),
+            Answer::Quit => break,
+            Answer::Type(mime, ext) => {
+                record.mime = Some(mime.clone());
+                record.ext = ext.clone();
+                record.alt_exts.clear();
+                if let Some(sig) = &signature {
+                    decisions.push(sig.rule(mime, ext));
+                }
+            }
+        }
+    }
+
+    Ok(decisions)
+}
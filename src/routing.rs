@@ -0,0 +1,98 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::manifest::Record;
+
+// output location computed by a routing script, dir is relative to the output root
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub struct Route {
+    pub dir: PathBuf,
+    pub name: Option<String>,
+}
+
+#[cfg(feature = "scripting")]
+impl Route {
+    fn new(dir: String, name: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let dir = PathBuf::from(dir);
+        if dir.components().any(|c| !matches!(c, std::path::Component::Normal(_))) {
+            return Err(format!("routing script returned {}, only relative paths without .. are allowed", dir.display()).into());
+        }
+        if let Some(name) = &name {
+            if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+                return Err(format!("routing script returned invalid file name {}", name).into());
+            }
+        }
+        Ok(Self{dir, name})
+    }
+}
+
+// script defining fn route(file), file is a map with path, name, mime, ext, size, tags (an array), owner, group,
+// perm_class, gps_country and gps_region;
+// it returns a directory, a map with dir and optionally name, or () to keep the default layout
+#[cfg(feature = "scripting")]
+pub struct Router {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+}
+
+#[cfg(feature = "scripting")]
+impl Router {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let engine = rhai::Engine::new();
+        let ast = engine.compile_file(path.to_owned())
+            .map_err(|e| format!("could not load routing script {}: {}", path.display(), e))?;
+        Ok(Self{engine, ast})
+    }
+
+    pub fn route(&self, record: &Record) -> Result<Option<Route>, Box<dyn Error>> {
+        use rhai::Dynamic;
+
+        let string_or_unit = |s: Option<&str>| s.map_or(Dynamic::UNIT, |s| Dynamic::from(s.to_owned()));
+
+        let mut file = rhai::Map::new();
+        file.insert("path".into(), Dynamic::from(record.path.to_string_lossy().into_owned()));
+        file.insert("name".into(), string_or_unit(record.path.file_name().and_then(|n| n.to_str())));
+        file.insert("mime".into(), string_or_unit(record.mime.as_deref()));
+        file.insert("ext".into(), string_or_unit(record.ext.as_deref()));
+        file.insert("size".into(), record.size.map_or(Dynamic::UNIT, |s| Dynamic::from(s as i64)));
+        file.insert("tags".into(), Dynamic::from(record.tags.iter().cloned().map(Dynamic::from).collect::<rhai::Array>()));
+        file.insert("owner".into(), string_or_unit(record.owner.as_deref()));
+        file.insert("group".into(), string_or_unit(record.group.as_deref()));
+        file.insert("perm_class".into(), string_or_unit(record.mode.map(crate::owners::perm_class)));
+        file.insert("gps_country".into(), string_or_unit(record.gps_country.as_deref()));
+        file.insert("gps_region".into(), string_or_unit(record.gps_region.as_deref()));
+
+        let result: Dynamic = self.engine.call_fn(&mut rhai::Scope::new(), &self.ast, "route", (file,))?;
+
+        if result.is_unit() {
+            Ok(None)
+        } else if result.is_string() {
+            Ok(Some(Route::new(result.into_string()?, None)?))
+        } else if let Some(route) = result.try_cast::<rhai::Map>() {
+            let field = |key: &str| route.get(key)
+                .filter(|v| !v.is_unit())
+                .map(|v| v.clone().into_string().map_err(|t| format!("routing script returned {} as {}", t, key)))
+                .transpose();
+            let dir = field("dir")?.ok_or("routing script returned a map without dir")?;
+            Ok(Some(Route::new(dir, field("name")?)?))
+        } else {
+            Err("routing script has to return a string, a map or ()".into())
+        }
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+pub enum Router {}
+
+#[cfg(not(feature = "scripting"))]
+impl Router {
+    pub fn load(_path: &Path) -> Result<Self, Box<dyn Error>> {
+        Err(Box::new(crate::ClassifierError(crate::ErrorKind::Config,
+            "routing scripts require classifiles to be built with the scripting feature".to_owned()
+        )))
+    }
+
+    pub fn route(&self, _record: &Record) -> Result<Option<Route>, Box<dyn Error>> {
+        match *self {}
+    }
+}
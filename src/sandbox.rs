@@ -0,0 +1,112 @@
+use std::error::Error;
+use std::path::Path;
+
+use slog::Logger;
+
+#[cfg(all(feature = "sandbox", target_os = "linux"))]
+mod linux {
+    use std::collections::BTreeMap;
+    use std::convert::TryInto;
+    use std::error::Error;
+    use std::path::Path;
+
+    use landlock::{Access, AccessFs, PathBeneath, PathFd, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus, ABI};
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter, SeccompRule};
+    use slog::{Logger, debug, info, warn};
+
+    // nothing a file classifier needs: spawning programs, the network, debugging or reconfiguring the system
+    static DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_execve,
+        libc::SYS_execveat,
+        libc::SYS_socket,
+        libc::SYS_socketpair,
+        libc::SYS_connect,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_setns,
+        libc::SYS_unshare,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_kexec_load,
+        libc::SYS_reboot,
+        // operations of a new ring would bypass this filter, the read ring is set up already
+        libc::SYS_io_uring_setup,
+    ];
+
+    // read access everywhere, writes only below the writable root (nowhere without one)
+    pub fn restrict_writes(writable: Option<&Path>, log: &Logger) -> Result<(), Box<dyn Error>> {
+        let abi = ABI::V2;
+        // hardlinks into the output need the refer right on the source directory as well
+        let read = AccessFs::from_read(abi) | AccessFs::Refer;
+        let mut ruleset = Ruleset::default()
+            .handle_access(AccessFs::from_all(abi))?
+            .create()?
+            .add_rule(PathBeneath::new(PathFd::new("/")?, read))?;
+        if let Some(writable) = writable {
+            ruleset = ruleset.add_rule(PathBeneath::new(PathFd::new(writable)?, AccessFs::from_all(abi)))?;
+        }
+        match ruleset.restrict_self()?.ruleset {
+            RulesetStatus::FullyEnforced => match writable {
+                Some(writable) => info!(log, "Sandbox restricts writes to {}", writable.display()),
+                None => debug!(log, "Sandbox denies all writes"),
+            },
+            RulesetStatus::PartiallyEnforced => warn!(log, "Sandbox write restrictions only partially supported by this kernel"),
+            RulesetStatus::NotEnforced => warn!(log, "Landlock not supported by this kernel, writes are not restricted"),
+        }
+        Ok(())
+    }
+
+    // a denylist, an allowlist would break with every libc or allocator update
+    pub fn deny_syscalls() -> Result<(), Box<dyn Error>> {
+        let rules: BTreeMap<i64, Vec<SeccompRule>> = DENIED_SYSCALLS.iter()
+            .map(|&nr| (nr as i64, Vec::new()))
+            .collect();
+        let filter = SeccompFilter::new(
+            rules,
+            SeccompAction::Allow,
+            SeccompAction::Errno(libc::EPERM as u32),
+            std::env::consts::ARCH.try_into()?,
+        )?;
+        let program: BpfProgram = filter.try_into()?;
+        // threads started before (logging, metrics) are covered as well
+        seccompiler::apply_filter_all_threads(&program)?;
+        Ok(())
+    }
+}
+
+// the process is restricted for the rest of the run, there is no way back
+#[cfg(all(feature = "sandbox", target_os = "linux"))]
+pub fn enter(writable: Option<&Path>, log: &Logger) -> Result<(), Box<dyn Error>> {
+    linux::restrict_writes(writable, log)?;
+    linux::deny_syscalls()
+}
+
+// without the seccomp filter, for a process that still starts workers
+#[cfg(all(feature = "sandbox", target_os = "linux"))]
+pub fn restrict_writes(writable: Option<&Path>, log: &Logger) -> Result<(), Box<dyn Error>> {
+    linux::restrict_writes(writable, log)
+}
+
+#[cfg(not(all(feature = "sandbox", target_os = "linux")))]
+pub fn enter(_writable: Option<&Path>, _log: &Logger) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(crate::ClassifierError(crate::ErrorKind::Config,
+        "--sandbox requires classifiles to be built with the sandbox feature on Linux".to_owned()
+    )))
+}
+
+#[cfg(not(all(feature = "sandbox", target_os = "linux")))]
+pub fn restrict_writes(writable: Option<&Path>, log: &Logger) -> Result<(), Box<dyn Error>> {
+    enter(writable, log)
+}
@@ -0,0 +1,32 @@
+use std::env;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixDatagram;
+
+// the sd_notify protocol of Type=notify services, e.g. READY=1 once serving (see sd_notify(3));
+// does nothing when not started by systemd
+pub fn notify(state: &str) -> io::Result<()> {
+    let socket_path = match env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let socket = UnixDatagram::unbound()?;
+    match socket_path.as_bytes().strip_prefix(b"@") {
+        Some(name) => send_abstract(&socket, name, state),
+        None => socket.send_to(state.as_bytes(), &socket_path).map(|_| ()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn send_abstract(socket: &UnixDatagram, name: &[u8], state: &str) -> io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+
+    socket.send_to_addr(state.as_bytes(), &SocketAddr::from_abstract_name(name)?).map(|_| ())
+}
+
+// abstract sockets only exist on Linux
+#[cfg(not(target_os = "linux"))]
+fn send_abstract(_socket: &UnixDatagram, _name: &[u8], _state: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "abstract NOTIFY_SOCKET"))
+}
@@ -0,0 +1,84 @@
+use std::str::FromStr;
+
+use regex::Regex;
+
+use crate::manifest::Record;
+
+// seconds since the epoch of a UTC date (YYYY-MM-DD) or date and time (YYYY-MM-DDTHH:MM[:SS])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(pub i64);
+
+// civil date to days since the epoch after Howard Hinnant, the inverse of audit::utc_timestamp
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn number(s: &str, digits: usize, max: i64) -> Option<i64> {
+    match s.len() == digits && s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse().ok().filter(|&n| n <= max),
+        false => None,
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid date {}, expected YYYY-MM-DD or YYYY-MM-DDTHH:MM[:SS] (UTC)", s);
+        let (date, time) = s.split_once(['T', ' ']).unwrap_or((s, "00:00"));
+        let mut date_parts = date.split('-');
+        let mut time_parts = time.split(':');
+        let year = date_parts.next().and_then(|y| number(y, 4, 9999)).ok_or_else(invalid)?;
+        let month = date_parts.next().and_then(|m| number(m, 2, 12)).filter(|&m| m >= 1).ok_or_else(invalid)?;
+        let day = date_parts.next().and_then(|d| number(d, 2, 31)).filter(|&d| d >= 1).ok_or_else(invalid)?;
+        let hour = time_parts.next().and_then(|h| number(h, 2, 23)).ok_or_else(invalid)?;
+        let minute = time_parts.next().and_then(|m| number(m, 2, 59)).ok_or_else(invalid)?;
+        let second = match time_parts.next() {
+            Some(sec) => number(sec, 2, 59).ok_or_else(invalid)?,
+            None => 0,
+        };
+        if date_parts.next().is_some() || time_parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Timestamp(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second))
+    }
+}
+
+// conditions a record has to meet all of, unset ones match everything
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilter {
+    // MIME pattern as for --only-mime
+    pub mime: Option<String>,
+    // searched for in the file name of the input path
+    pub name: Option<Regex>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    // modification time range, records without mtime (older manifests) never match it
+    pub newer: Option<Timestamp>,
+    pub older: Option<Timestamp>,
+    // all of them
+    pub tags: Vec<String>,
+}
+
+impl SearchFilter {
+    pub fn matches(&self, record: &Record) -> bool {
+        let mime = record.mime.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN);
+        let size = record.size.unwrap_or(0);
+        let in_range = |from: Option<Timestamp>, to: Option<Timestamp>| match record.mtime {
+            Some(mtime) => from.is_none_or(|from| mtime >= from.0) && to.is_none_or(|to| mtime < to.0),
+            None => from.is_none() && to.is_none(),
+        };
+        self.mime.as_deref().is_none_or(|pattern| crate::mime_matches(pattern, mime))
+            && self.name.as_ref().is_none_or(|name| record.path.file_name()
+                .is_some_and(|file_name| name.is_match(&file_name.to_string_lossy())))
+            && self.min_size.is_none_or(|min| size >= min)
+            && self.max_size.is_none_or(|max| size <= max)
+            && in_range(self.newer, self.older)
+            && self.tags.iter().all(|tag| record.tags.contains(tag))
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+
+// systemd units written by install-service, see systemd.service(5), systemd.timer(5) and systemd.syntax(7)
+pub struct Service {
+    // unit names are NAME-sync.service, NAME-sync.timer and NAME-mount.service
+    pub name: String,
+    pub program: PathBuf,
+    pub input: PathBuf,
+    pub output: PathBuf,
+    // OnCalendar= of the timer, e.g. weekly or Sun 03:00
+    pub schedule: String,
+    pub mountpoint: Option<PathBuf>,
+    // options passed on to each run, --set and --config-fallback
+    pub args: Vec<String>,
+    // the config in effect when installing: WorkingDirectory= for a local config.yaml,
+    // and Environment= for XDG_CONFIG_HOME and the CLASSIFILES_* overrides
+    pub working_dir: Option<PathBuf>,
+    pub env: Vec<(String, String)>,
+}
+
+// values of Description= and WorkingDirectory= only get specifiers (%) expanded
+fn escape_specifiers(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+// double-quoted with specifiers and variables ($) escaped, valid for ExecStart= and Environment=
+fn quote(arg: &str) -> String {
+    let escaped = escape_specifiers(&arg.replace('\\', "\\\\").replace('"', "\\\"")).replace('$', "$$");
+    format!("\"{}\"", escaped)
+}
+
+fn command_line(args: &[&str]) -> String {
+    args.iter().map(|arg| quote(arg)).collect::<Vec<_>>().join(" ")
+}
+
+fn path_str(path: &Path) -> &str {
+    // install-service refuses paths that are not valid UTF-8 before getting here
+    path.to_str().expect("UTF-8 path")
+}
+
+impl Service {
+    fn command_line(&self, verb: &str, paths: &[&Path]) -> String {
+        let mut args = vec![path_str(&self.program), verb];
+        args.extend(self.args.iter().map(String::as_str));
+        args.extend(paths.iter().map(|path| path_str(path)));
+        command_line(&args)
+    }
+
+    fn service_section(&self, lines: &mut Vec<String>) {
+        if let Some(dir) = &self.working_dir {
+            lines.push(format!("WorkingDirectory={}", escape_specifiers(path_str(dir))));
+        }
+        for (key, value) in &self.env {
+            lines.push(format!("Environment={}", quote(&format!("{}={}", key, value))));
+        }
+    }
+
+    fn description(&self, what: &str) -> String {
+        escape_specifiers(&format!("Description={} of {} into {}", what, self.input.display(), self.output.display()))
+    }
+
+    pub fn sync_service(&self) -> String {
+        let mut lines = vec![
+            "[Unit]".to_owned(),
+            self.description("classifiles sync"),
+            "After=local-fs.target".to_owned(),
+            "".to_owned(),
+            "[Service]".to_owned(),
+            "Type=oneshot".to_owned(),
+        ];
+        self.service_section(&mut lines);
+        lines.push(format!("ExecStart={}", self.command_line("sync", &[&self.input, &self.output])));
+        // the walk should not starve interactive use of the machine
+        lines.push("Nice=10".to_owned());
+        lines.push("IOSchedulingClass=idle".to_owned());
+        lines.join("\n") + "\n"
+    }
+
+    pub fn sync_timer(&self) -> String {
+        [
+            "[Unit]".to_owned(),
+            self.description("scheduled classifiles sync"),
+            "".to_owned(),
+            "[Timer]".to_owned(),
+            format!("OnCalendar={}", self.schedule),
+            // a run missed while the machine was off starts at the next boot
+            "Persistent=true".to_owned(),
+            "RandomizedDelaySec=10min".to_owned(),
+            "".to_owned(),
+            "[Install]".to_owned(),
+            "WantedBy=timers.target".to_owned(),
+        ].join("\n") + "\n"
+    }
+
+    // classifiles mount reports readiness with sd_notify once the view is mounted
+    pub fn mount_service(&self) -> Option<String> {
+        let mountpoint = self.mountpoint.as_deref()?;
+        let mut lines = vec![
+            "[Unit]".to_owned(),
+            escape_specifiers(&format!("Description=classifiles view of {} at {}", self.output.display(),
+                mountpoint.display())),
+            "After=local-fs.target".to_owned(),
+            "".to_owned(),
+            "[Service]".to_owned(),
+            "Type=notify".to_owned(),
+        ];
+        self.service_section(&mut lines);
+        lines.push(format!("ExecStart={}", self.command_line("mount", &[&self.output, mountpoint])));
+        // a killed session leaves a stale mount behind
+        lines.push(format!("ExecStopPost=-fusermount -u {}", quote(path_str(mountpoint))));
+        lines.push("Restart=on-failure".to_owned());
+        lines.push("".to_owned());
+        lines.push("[Install]".to_owned());
+        lines.push("WantedBy=default.target".to_owned());
+        Some(lines.join("\n") + "\n")
+    }
+
+    // file names and contents
+    pub fn units(&self) -> Vec<(String, String)> {
+        let mut units = vec![
+            (format!("{}-sync.service", self.name), self.sync_service()),
+            (format!("{}-sync.timer", self.name), self.sync_timer()),
+        ];
+        if let Some(mount) = self.mount_service() {
+            units.push((format!("{}-mount.service", self.name), mount));
+        }
+        units
+    }
+}
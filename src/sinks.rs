@@ -0,0 +1,337 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::os::unix::fs as unix_fs;
+use std::path::{Path, PathBuf};
+
+use fnv::FnvHashSet;
+use slog::{Logger, debug, warn};
+
+use crate::lns::{IndexWriter, LinkInfo};
+use crate::reader::FileReader;
+use crate::stubs::FileStub;
+use crate::{hashing, LinkMode, Params};
+
+// creates the output entries of a scan, apply-manifest and sync, and the entries of a backup; the layout
+// (directory, name, collisions) is decided by materialize, a sink only puts an input file at the output path it
+// is given
+pub trait OutputSink {
+    // recorded in the audit log with each entry
+    fn name(&self) -> &str;
+
+    // output paths taken already, new entries get another name
+    fn exists(&self, entry: &Path) -> bool {
+        fs::symlink_metadata(entry).is_ok()
+    }
+
+    // entry does not exist yet, its directory may not either
+    fn put(&mut self, input: &Path, entry: &Path, log: &Logger) -> io::Result<()>;
+
+    // called once all entries are put, e.g. to complete an archive
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn create_parent(entry: &Path) -> io::Result<()> {
+    entry.parent().map_or(Ok(()), fs::create_dir_all)
+}
+
+// links to the absolute input path, or relative to the entry's directory
+pub struct SymlinkSink {
+    pub relative: bool,
+}
+
+impl OutputSink for SymlinkSink {
+    fn name(&self) -> &str {
+        "symlink"
+    }
+
+    fn put(&mut self, input: &Path, entry: &Path, _log: &Logger) -> io::Result<()> {
+        create_parent(entry)?;
+        let target = crate::absolute_entry_path(input)?;
+        if self.relative {
+            let entry_dir = crate::absolute_entry_path(entry)?;
+            let entry_dir = entry_dir.parent().unwrap_or(&entry_dir);
+            unix_fs::symlink(crate::relative_path(entry_dir, &target), entry)
+        } else {
+            unix_fs::symlink(target, entry)
+        }
+    }
+}
+
+// copying special files would read from fifos and devices, they are symlinked instead
+pub struct CopySink {
+    pub special_files: SymlinkSink,
+}
+
+impl OutputSink for CopySink {
+    fn name(&self) -> &str {
+        "copy"
+    }
+
+    fn put(&mut self, input: &Path, entry: &Path, log: &Logger) -> io::Result<()> {
+        if !fs::metadata(input)?.is_file() {
+            return self.special_files.put(input, entry, log);
+        }
+        create_parent(entry)?;
+        fs::copy(input, entry).map(|_| ())
+    }
+}
+
+// hardlinks fail across filesystems, the fallback (if any) takes over then
+pub struct HardlinkSink {
+    pub cross_device_fallback: Option<Box<dyn OutputSink>>,
+    fallback_logged: bool,
+}
+
+impl HardlinkSink {
+    pub fn new(cross_device_fallback: Option<Box<dyn OutputSink>>) -> Self {
+        Self{cross_device_fallback, fallback_logged: false}
+    }
+}
+
+impl OutputSink for HardlinkSink {
+    fn name(&self) -> &str {
+        "hardlink"
+    }
+
+    fn put(&mut self, input: &Path, entry: &Path, log: &Logger) -> io::Result<()> {
+        create_parent(entry)?;
+        match (fs::hard_link(input, entry), &mut self.cross_device_fallback) {
+            (Err(e), Some(fallback)) if e.raw_os_error() == Some(libc::EXDEV) => {
+                if !self.fallback_logged {
+                    warn!(log, "Cannot hardlink across filesystems, falling back to {}", fallback.name());
+                    self.fallback_logged = true;
+                }
+                fallback.put(input, entry, log)
+            }
+            (result, _) => result,
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.cross_device_fallback.as_mut().map_or(Ok(()), |fallback| fallback.finish())
+    }
+}
+
+// lays out the output in the manifest only, e.g. to preview a scan or for a mount of the view; nothing is
+// created on disk
+#[derive(Default)]
+pub struct ManifestOnlySink {
+    entries: FnvHashSet<PathBuf>,
+}
+
+impl OutputSink for ManifestOnlySink {
+    fn name(&self) -> &str {
+        "manifest-only"
+    }
+
+    fn exists(&self, entry: &Path) -> bool {
+        self.entries.contains(entry) || fs::symlink_metadata(entry).is_ok()
+    }
+
+    fn put(&mut self, _input: &Path, entry: &Path, _log: &Logger) -> io::Result<()> {
+        self.entries.insert(entry.to_owned());
+        Ok(())
+    }
+}
+
+// writes the output entries into a tar archive instead of the output tree, named by their paths below the output
+// root; special files are archived as symlinks to them, as CopySink links them (requires the archives feature)
+#[cfg(feature = "archives")]
+pub struct ArchiveSink {
+    output_root: PathBuf,
+    builder: tar::Builder<io::BufWriter<fs::File>>,
+    entries: FnvHashSet<PathBuf>,
+}
+
+#[cfg(feature = "archives")]
+impl ArchiveSink {
+    // replaces an existing archive
+    pub fn create(archive: &Path, output_root: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = fs::File::create(archive).map_err(|e| format!("could not create {}: {}", archive.display(), e))?;
+        let builder = tar::Builder::new(io::BufWriter::new(file));
+        Ok(Self{output_root: output_root.to_owned(), builder, entries: FnvHashSet::default()})
+    }
+}
+
+#[cfg(feature = "archives")]
+impl OutputSink for ArchiveSink {
+    fn name(&self) -> &str {
+        "archive"
+    }
+
+    // the output tree on disk is not looked at
+    fn exists(&self, entry: &Path) -> bool {
+        self.entries.contains(entry)
+    }
+
+    fn put(&mut self, input: &Path, entry: &Path, _log: &Logger) -> io::Result<()> {
+        let name = entry.strip_prefix(&self.output_root).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput,
+            format!("{} is not below the output root", entry.display())))?;
+        let meta = fs::metadata(input)?;
+        if meta.is_file() {
+            self.builder.append_file(name, &mut fs::File::open(input)?)?;
+        } else {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            header.set_mode(0o777);
+            header.set_mtime(std::os::unix::fs::MetadataExt::mtime(&meta).max(0) as u64);
+            self.builder.append_link(&mut header, name, crate::absolute_entry_path(input)?)?;
+        }
+        self.entries.insert(entry.to_owned());
+        Ok(())
+    }
+
+    // the archive is unreadable without its end
+    fn finish(&mut self) -> io::Result<()> {
+        use std::io::Write;
+
+        self.builder.finish()?;
+        self.builder.get_mut().flush()
+    }
+}
+
+#[cfg(not(feature = "archives"))]
+pub enum ArchiveSink {}
+
+#[cfg(not(feature = "archives"))]
+impl ArchiveSink {
+    pub fn create(_archive: &Path, _output_root: &Path) -> Result<Self, Box<dyn Error>> {
+        Err(Box::new(crate::ClassifierError(crate::ErrorKind::Config,
+            "--output-archive requires classifiles to be built with the archives feature".to_owned()
+        )))
+    }
+}
+
+#[cfg(not(feature = "archives"))]
+impl OutputSink for ArchiveSink {
+    fn name(&self) -> &str {
+        match *self {}
+    }
+
+    fn put(&mut self, _input: &Path, _entry: &Path, _log: &Logger) -> io::Result<()> {
+        match *self {}
+    }
+}
+
+pub fn for_mode(mode: LinkMode, relative_links: bool, cross_device_fallback: Option<LinkMode>) -> Box<dyn OutputSink> {
+    match mode {
+        LinkMode::Symlink => Box::new(SymlinkSink{relative: relative_links}),
+        LinkMode::Copy => Box::new(CopySink{special_files: SymlinkSink{relative: relative_links}}),
+        LinkMode::Hardlink => Box::new(HardlinkSink::new(cross_device_fallback
+            .filter(|&fallback| fallback != LinkMode::Hardlink)
+            .map(|fallback| for_mode(fallback, relative_links, None)))),
+    }
+}
+
+// the sink of --output-archive, or of --link-mode, --relative-links and --cross-device-fallback
+pub fn for_params(params: &Params) -> Result<Box<dyn OutputSink>, Box<dyn Error>> {
+    match &params.output_archive {
+        Some(archive) => Ok(Box::new(ArchiveSink::create(archive, &params.output_path)?)),
+        None => Ok(for_mode(params.link_mode, params.relative_links, params.cross_device_fallback)),
+    }
+}
+
+// backup records symlinks in NAME.lns files next to where they would be, or in the link index of the backup
+pub struct LinkRecordSink {
+    backup_root: PathBuf,
+    // whether the targets exist and where they resolve to are recorded too
+    check_links: bool,
+    index: Option<IndexWriter>,
+}
+
+impl LinkRecordSink {
+    pub fn new(backup_root: &Path, check_links: bool, index: Option<IndexWriter>) -> Self {
+        Self{backup_root: backup_root.to_owned(), check_links, index}
+    }
+}
+
+impl OutputSink for LinkRecordSink {
+    fn name(&self) -> &str {
+        "lns"
+    }
+
+    fn put(&mut self, input: &Path, entry: &Path, log: &Logger) -> io::Result<()> {
+        let mut info = LinkInfo{target: fs::read_link(input)?, ..Default::default()};
+        if self.check_links {
+            // fails for dangling links as well as loops
+            let resolved = input.canonicalize();
+            info.exists = Some(resolved.is_ok());
+            // a dangling target is still resolved up to its last existing directory
+            info.resolved = resolved.ok().or_else(|| {
+                let link_dir = input.parent()?;
+                crate::absolute_path(&link_dir.join(&info.target)).ok()
+            });
+            if info.exists == Some(false) {
+                debug!(log, "Link target {} does not exist", info.target.display());
+            }
+        }
+
+        if let Some(index) = &mut self.index {
+            let link = entry.strip_prefix(&self.backup_root).expect("backup entry in backup root");
+            debug!(log, "{} -> {}", input.display(), link.display());
+            return index.add(link, &info);
+        }
+
+        let mut record = entry.as_os_str().to_owned();
+        record.push(".lns");
+        let record = PathBuf::from(record);
+        debug!(log, "{} -> {}", input.display(), record.display());
+        fs::write(record, info.to_bytes())
+    }
+
+    // the index is unreadable without its end
+    fn finish(&mut self) -> io::Result<()> {
+        self.index.take().map_or(Ok(()), IndexWriter::finish)
+    }
+}
+
+// backup --stubs records regular files in NAME.stub files, by size, mtime and with a reader their content hash
+pub struct StubSink {
+    pub hash_reader: Option<FileReader>,
+}
+
+impl OutputSink for StubSink {
+    fn name(&self) -> &str {
+        "stub"
+    }
+
+    fn put(&mut self, input: &Path, entry: &Path, log: &Logger) -> io::Result<()> {
+        let meta = fs::metadata(input)?;
+        let sha256 = match &mut self.hash_reader {
+            Some(reader) => Some(hashing::sha256_file(input, reader)?),
+            None => None,
+        };
+        let stub = FileStub{size: meta.len(), mtime: meta.modified()?, sha256};
+
+        let mut record = entry.as_os_str().to_owned();
+        record.push(".stub");
+        let record = PathBuf::from(record);
+        debug!(log, "{} -> {}", input.display(), record.display());
+        fs::write(record, stub.to_bytes())
+    }
+}
+
+// backup --content copies regular files unless unchanged since the last backup (compared by content hash with a
+// reader), names that would be taken for records are escaped
+pub struct ContentSink {
+    pub hash_reader: Option<FileReader>,
+}
+
+impl OutputSink for ContentSink {
+    fn name(&self) -> &str {
+        "content"
+    }
+
+    fn put(&mut self, input: &Path, entry: &Path, log: &Logger) -> io::Result<()> {
+        let copy = crate::escaped_file_name(entry);
+        match crate::copy_if_changed(input, &fs::metadata(input)?, &copy, self.hash_reader.as_mut())? {
+            true => debug!(log, "{} -> {}", input.display(), copy.display()),
+            false => debug!(log, "{} unchanged", copy.display()),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::Path;
+
+use fnv::FnvHashMap;
+use slog::{Logger, debug, warn};
+
+use crate::manifest::Record;
+
+// volumes of split and multi-volume archives, recognized by their names within one directory:
+// NAME.001, NAME.002, ... (7-Zip, HJSplit, split -d), NAME.z01, NAME.z02, ..., NAME.zip (spanned zip),
+// NAME.part1.rar, NAME.part2.rar, ... and NAME.rar, NAME.r00, NAME.r01, ... (RAR)
+
+const MISSING_LISTED: usize = 20;
+// longer numbers are no volumes, like a.z4000000000
+const MAX_DIGITS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Scheme {
+    Numbered,
+    ZipSpan,
+    RarParts,
+    RarOld,
+}
+
+// digits of a volume number, at least two as in z01 and r00
+fn number(digits: &str) -> Option<u32> {
+    match (2..=MAX_DIGITS).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit()) {
+        true => digits.parse().ok(),
+        false => None,
+    }
+}
+
+// the set a file name belongs to if it is a volume; the number is None for NAME.zip and NAME.rar, whose place
+// depends on the other volumes
+fn parse(name: &str) -> Option<(String, Scheme, Option<u32>)> {
+    let (stem, ext) = name.rsplit_once('.')?;
+    if stem.is_empty() {
+        return None;
+    }
+    // three digits, NAME.2024 is rather a year
+    if ext.len() == 3 && ext.bytes().all(|b| b.is_ascii_digit()) {
+        return Some((stem.to_owned(), Scheme::Numbered, ext.parse().ok()));
+    }
+    let lower = ext.to_ascii_lowercase();
+    match lower.as_str() {
+        "zip" => Some((name.to_owned(), Scheme::ZipSpan, None)),
+        "rar" => match stem.rsplit_once('.') {
+            Some((base, part)) if part.get(..4).is_some_and(|p| p.eq_ignore_ascii_case("part")) => {
+                let digits = &part[4..];
+                let n = digits.parse().ok()
+                    .filter(|_| digits.len() <= MAX_DIGITS && digits.bytes().all(|b| b.is_ascii_digit()))?;
+                Some((format!("{}.{}", base, ext), Scheme::RarParts, Some(n)))
+            }
+            _ => Some((name.to_owned(), Scheme::RarOld, None)),
+        },
+        _ if lower.starts_with('z') => number(&ext[1..]).map(|n| (format!("{}.zip", stem), Scheme::ZipSpan, Some(n))),
+        _ if lower.starts_with('r') => number(&ext[1..]).map(|n| (format!("{}.rar", stem), Scheme::RarOld, Some(n))),
+        _ => None,
+    }
+}
+
+// the first volume is part 1, except for NAME.000 sets
+fn part(scheme: Scheme, number: Option<u32>, last_number: u32) -> u32 {
+    match (scheme, number) {
+        (Scheme::ZipSpan, None) => last_number.saturating_add(1),
+        (Scheme::RarOld, None) => 1,
+        (Scheme::RarOld, Some(n)) => n.saturating_add(2),
+        (_, Some(n)) => n,
+        (_, None) => 0,
+    }
+}
+
+// record indexes and numbers of the volumes of a set
+type Volumes = Vec<(usize, Option<u32>)>;
+
+// sets split_set and split_part of the records of sets with at least two volumes, missing volumes are logged
+pub fn mark(records: &mut [Record], log: &Logger) {
+    // by the lowercase set name, NAME.ZIP and name.z01 are volumes of one set; it is named after the first of its
+    // spellings in sort order
+    let mut sets: FnvHashMap<(&Path, String, Scheme), (String, Volumes)> = FnvHashMap::default();
+    for (i, record) in records.iter().enumerate() {
+        let name = match record.path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+        if let (Some(dir), Some((set, scheme, number))) = (record.path.parent(), parse(name)) {
+            let key = (dir, set.to_lowercase(), scheme);
+            let (set_name, volumes) = sets.entry(key).or_insert_with(|| (set.clone(), Vec::new()));
+            if set < *set_name {
+                *set_name = set;
+            }
+            volumes.push((i, number));
+        }
+    }
+
+    let mut parts = Vec::new();
+    for ((dir, _, scheme), (set, volumes)) in sets {
+        // NAME.zip and NAME.rar alone are plain archives
+        if volumes.len() < 2 || volumes.iter().all(|(_, number)| number.is_none()) {
+            continue;
+        }
+        let last_number = volumes.iter().filter_map(|(_, number)| *number).max().unwrap_or(0);
+        let mut numbers: Vec<u32> = volumes.iter().map(|(_, number)| part(scheme, *number, last_number)).collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+        let first = numbers[0].min(1);
+        let last = numbers[numbers.len() - 1];
+        // all numbers are within the range, so the missing ones are counted rather than collected
+        match (last - first) as usize + 1 - numbers.len() {
+            0 => debug!(log, "Split archive {} in {} has {} volumes", set, dir.display(), volumes.len()),
+            count => {
+                let listed: Vec<String> = (first..=last).filter(|n| numbers.binary_search(n).is_err())
+                    .take(MISSING_LISTED).map(|n| n.to_string()).collect();
+                let more = match count > MISSING_LISTED {
+                    true => format!(" and {} more", count - MISSING_LISTED),
+                    false => String::new(),
+                };
+                warn!(log, "Split archive {} in {} is missing volumes {}{}", set, dir.display(), listed.join(", "), more);
+            }
+        }
+        for (i, number) in volumes {
+            parts.push((i, set.clone(), part(scheme, number, last_number)));
+        }
+    }
+    for (i, set, part) in parts {
+        records[i].split_set = Some(set);
+        records[i].split_part = Some(part);
+    }
+}
+
+// the type of the first volume of known type of each set by its directory and name (the last one of a spanned
+// zip archive), the other volumes are laid out by it, next to it
+pub fn layout_types(records: &[Record]) -> FnvHashMap<(&Path, &str), Option<&str>> {
+    let mut first: FnvHashMap<(&Path, &str), (bool, u32, Option<&str>)> = FnvHashMap::default();
+    for record in records {
+        if let (Some(dir), Some(set), Some(part)) = (record.path.parent(), &record.split_set, record.split_part) {
+            let unknown = matches!(record.mime.as_deref(), None | Some("application/octet-stream"));
+            let candidate = (unknown, part, record.mime.as_deref());
+            let entry = first.entry((dir, set.as_str())).or_insert(candidate);
+            if (candidate.0, candidate.1) < (entry.0, entry.1) {
+                *entry = candidate;
+            }
+        }
+    }
+    first.into_iter().map(|(key, (_, _, mime))| (key, mime)).collect()
+}
@@ -0,0 +1,56 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::lns;
+
+// a regular file as stored by backup --stubs in NAME.stub, in the record format of .lns files
+#[derive(Debug)]
+pub struct FileStub {
+    pub size: u64,
+    pub mtime: SystemTime,
+    // with backup --hash
+    pub sha256: Option<String>,
+}
+
+static HEADER: &[u8] = b"classifiles-stub 1\n";
+static EXTENSION: &str = "stub";
+
+pub fn is_stub(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == EXTENSION)
+}
+
+impl FileStub {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // seconds.nanoseconds since the epoch, earlier times are clamped to it
+        let mtime = self.mtime.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut bytes = HEADER.to_vec();
+        lns::push_field(&mut bytes, "size", self.size.to_string().as_bytes());
+        lns::push_field(&mut bytes, "mtime", format!("{}.{:09}", mtime.as_secs(), mtime.subsec_nanos()).as_bytes());
+        if let Some(sha256) = &self.sha256 {
+            lns::push_field(&mut bytes, "sha256", sha256.as_bytes());
+        }
+        bytes
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let fields = lns::parse_fields(bytes.strip_prefix(HEADER).ok_or("not a file stub")?)?;
+
+        let (mut size, mut mtime, mut sha256) = (None, None, None);
+        for (key, value) in fields {
+            let value = std::str::from_utf8(value).map_err(|_| format!("invalid {}", key))?;
+            match key {
+                "size" => size = Some(value.parse().map_err(|_| "invalid size")?),
+                "mtime" => mtime = Some(parse_mtime(value).ok_or("invalid mtime")?),
+                "sha256" => sha256 = Some(value.to_owned()),
+                // written by a newer version
+                _ => (),
+            }
+        }
+        Ok(Self{size: size.ok_or("missing size")?, mtime: mtime.ok_or("missing mtime")?, sha256})
+    }
+}
+
+fn parse_mtime(value: &str) -> Option<SystemTime> {
+    let (secs, nanos) = value.split_once('.')?;
+    UNIX_EPOCH.checked_add(Duration::new(secs.parse().ok()?, nanos.parse().ok().filter(|&n| n < 1_000_000_000)?))
+}
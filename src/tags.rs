@@ -0,0 +1,87 @@
+use std::os::unix::ffi::OsStrExt;
+
+use crate::manifest::Record;
+
+// tag attached to every classified file matching all of the given conditions
+#[derive(Debug, Clone, Default)]
+pub struct TagRule {
+    pub tag: String,
+    // MIME pattern as for --only-mime
+    pub mime: Option<String>,
+    // glob over the whole input path, * and ? also match /
+    pub path: Option<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+}
+
+// tags can end up in directory names through hooks and routing scripts and are listed comma-separated
+pub fn check_tag(tag: &str) -> Result<(), String> {
+    match tag {
+        "" => Err("empty tag".to_owned()),
+        "." | ".." => Err(format!("invalid tag {}", tag)),
+        _ if tag.contains(['/', ',']) || tag.chars().any(char::is_control) => {
+            Err(format!("invalid tag {}, tags cannot contain / , or control characters", tag))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    // backtracking to the last * only, enough for patterns without character classes
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+impl TagRule {
+    fn matches(&self, record: &Record) -> bool {
+        let mime = record.mime.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN);
+        let size = record.size.unwrap_or(0);
+        self.mime.as_deref().is_none_or(|pattern| crate::mime_matches(pattern, mime))
+            && self.path.as_deref().is_none_or(|pattern| glob_matches(pattern.as_bytes(), record.path.as_os_str().as_bytes()))
+            && self.min_size.is_none_or(|min| size >= min)
+            && self.max_size.is_none_or(|max| size <= max)
+    }
+}
+
+// adds the tags of all matching rules, each tag once
+pub fn apply(rules: &[TagRule], record: &mut Record) {
+    let matching: Vec<&TagRule> = rules.iter().filter(|rule| rule.matches(record)).collect();
+    for rule in matching {
+        add(record, &rule.tag);
+    }
+}
+
+pub fn add(record: &mut Record, tag: &str) -> bool {
+    if record.tags.iter().any(|t| t == tag) {
+        return false;
+    }
+    record.tags.push(tag.to_owned());
+    true
+}
+
+pub fn remove(record: &mut Record, tag: &str) -> bool {
+    let count = record.tags.len();
+    record.tags.retain(|t| t != tag);
+    record.tags.len() != count
+}
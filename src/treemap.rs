@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use serde_json::{json, Value};
+
+use crate::manifest::Record;
+
+// disk usage of the input tree for treemap tools: nested JSON of directory -> type -> size (e.g. for d3.treemap),
+// or the export format of ncdu (ncdu -f FILE)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreemapFormat {
+    Nested,
+    Ncdu,
+}
+
+impl FromStr for TreemapFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nested" => Ok(TreemapFormat::Nested),
+            "ncdu" => Ok(TreemapFormat::Ncdu),
+            _ => Err(format!("invalid treemap format {}, valid formats are: nested, ncdu", s)),
+        }
+    }
+}
+
+impl TreemapFormat {
+    // written to the output root, next to report.html
+    pub fn file_name(self) -> &'static str {
+        match self {
+            TreemapFormat::Nested => "treemap.json",
+            TreemapFormat::Ncdu => "ncdu.json",
+        }
+    }
+}
+
+// version of the ncdu export format, 1.2 is read by ncdu 1.9 and later
+static NCDU_MAJOR: u32 = 1;
+static NCDU_MINOR: u32 = 2;
+
+#[derive(Default)]
+struct Dir<'a> {
+    dirs: BTreeMap<&'a OsStr, Dir<'a>>,
+    // files directly in the directory with their size
+    files: Vec<(&'a OsStr, u64)>,
+    // count and size of the files directly in the directory by type
+    types: BTreeMap<&'a str, (usize, u64)>,
+}
+
+impl<'a> Dir<'a> {
+    fn size(&self) -> u64 {
+        self.files.iter().map(|(_, size)| size).sum::<u64>() + self.dirs.values().map(Dir::size).sum::<u64>()
+    }
+
+    fn nested(&self, name: &str) -> Value {
+        let mut children: Vec<Value> = self.dirs.iter()
+            .map(|(dir_name, dir)| dir.nested(&dir_name.to_string_lossy()))
+            .collect();
+        children.extend(self.types.iter()
+            .map(|(mime, (count, size))| json!({"name": mime, "mime": mime, "count": count, "size": size})));
+        json!({"name": name, "size": self.size(), "children": children})
+    }
+
+    fn ncdu(&self, name: &str) -> Value {
+        let mut entries = vec![json!({"name": name})];
+        entries.extend(self.files.iter()
+            .map(|(file_name, size)| json!({"name": file_name.to_string_lossy(), "asize": size, "dsize": size})));
+        entries.extend(self.dirs.iter().map(|(dir_name, dir)| dir.ncdu(&dir_name.to_string_lossy())));
+        Value::Array(entries)
+    }
+}
+
+// the deepest directory containing all files, the input root of a single scan
+fn common_root(records: &[Record]) -> PathBuf {
+    let mut root: Option<&Path> = None;
+    for parent in records.iter().filter_map(|r| r.path.parent()) {
+        root = Some(match root {
+            None => parent,
+            Some(root) => root.ancestors().find(|ancestor| parent.starts_with(ancestor)).unwrap_or(root),
+        });
+    }
+    root.map(Path::to_owned).unwrap_or_default()
+}
+
+pub fn write(records: &[Record], format: TreemapFormat, out: &mut impl Write) -> io::Result<()> {
+    let root_path = common_root(records);
+    let mut root = Dir::default();
+    for record in records {
+        let rel_path = match record.path.strip_prefix(&root_path) {
+            Ok(rel_path) => rel_path,
+            Err(_) => continue,
+        };
+        let (file_name, rel_dir) = match (rel_path.file_name(), rel_path.parent()) {
+            (Some(file_name), Some(rel_dir)) => (file_name, rel_dir),
+            _ => continue,
+        };
+        let dir = rel_dir.iter().fold(&mut root, |dir, name| dir.dirs.entry(name).or_default());
+        let size = record.size.unwrap_or(0);
+        dir.files.push((file_name, size));
+        let stats = dir.types.entry(record.mime.as_deref().unwrap_or(crate::OUTPUT_UNKNOWN)).or_default();
+        stats.0 += 1;
+        stats.1 += size;
+    }
+
+    let root_name = root_path.to_string_lossy();
+    let value = match format {
+        TreemapFormat::Nested => root.nested(&root_name),
+        TreemapFormat::Ncdu => {
+            let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            let meta = json!({"progname": "classifiles", "progver": env!("CARGO_PKG_VERSION"), "timestamp": timestamp});
+            json!([NCDU_MAJOR, NCDU_MINOR, meta, root.ncdu(&root_name)])
+        }
+    };
+    serde_json::to_writer(&mut *out, &value)?;
+    writeln!(out)
+}
@@ -0,0 +1,55 @@
+use std::error::Error;
+
+use slog::Logger;
+
+#[cfg(feature = "object-store")]
+mod store {
+    use std::error::Error;
+
+    use object_store::{ObjectStore, path::Path};
+    use url::Url;
+
+    use crate::{ClassifierError, ErrorKind};
+
+    // credentials and region come from the usual variables of each provider, e.g. AWS_ACCESS_KEY_ID and AWS_REGION
+    pub fn parse(url: &str) -> Result<(Box<dyn ObjectStore>, Path), Box<dyn Error>> {
+        let config_error = |e: &dyn Error| ClassifierError(ErrorKind::Config, format!("invalid upload URL {}: {}", url, e));
+        let parsed = Url::parse(url).map_err(|e| config_error(&e))?;
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        Ok(object_store::parse_url_opts(&parsed, options).map_err(|e| config_error(&e))?)
+    }
+
+    pub fn put(url: &str, name: &str, bytes: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let (store, prefix) = parse(url)?;
+        // the rest of classifiles is synchronous, a runtime just for the upload
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        runtime.block_on(store.put(&prefix.child(name), bytes.into()))?;
+        Ok(())
+    }
+}
+
+// fails early on a malformed URL or a build without object store support, before a long scan
+#[cfg(feature = "object-store")]
+pub fn check_url(url: &str) -> Result<(), Box<dyn Error>> {
+    store::parse(url).map(|_| ())
+}
+
+// stored as NAME below the URL, e.g. s3://bucket/hosts/web1/manifest.jsonl
+#[cfg(feature = "object-store")]
+pub fn upload(url: &str, name: &str, bytes: Vec<u8>, log: &Logger) -> Result<(), Box<dyn Error>> {
+    store::put(url, name, bytes).map_err(|e| format!("could not upload {} to {}: {}", name, url, e))?;
+    slog::info!(log, "Uploaded {} to {}", name, url);
+    Ok(())
+}
+
+#[cfg(not(feature = "object-store"))]
+pub fn check_url(_url: &str) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(crate::ClassifierError(crate::ErrorKind::Config,
+        "--upload requires classifiles to be built with the object-store feature".to_owned()
+    )))
+}
+
+#[cfg(not(feature = "object-store"))]
+pub fn upload(url: &str, _name: &str, _bytes: Vec<u8>, _log: &Logger) -> Result<(), Box<dyn Error>> {
+    check_url(url)
+}
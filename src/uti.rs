@@ -0,0 +1,78 @@
+use crate::mime_type::MimeType;
+
+// Apple Uniform Type Identifiers of common MIME types
+static UTI_MAP: &[(&str, &str)] = &[
+    ("application/gzip", "org.gnu.gnu-zip-archive"),
+    ("application/json", "public.json"),
+    ("application/msword", "com.microsoft.word.doc"),
+    ("application/octet-stream", "public.data"),
+    ("application/pdf", "com.adobe.pdf"),
+    ("application/rtf", "public.rtf"),
+    ("application/vnd.ms-excel", "com.microsoft.excel.xls"),
+    ("application/vnd.ms-powerpoint", "com.microsoft.powerpoint.ppt"),
+    ("application/vnd.openxmlformats-officedocument.presentationml.presentation", "org.openxmlformats.presentationml.presentation"),
+    ("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet", "org.openxmlformats.spreadsheetml.sheet"),
+    ("application/vnd.openxmlformats-officedocument.wordprocessingml.document", "org.openxmlformats.wordprocessingml.document"),
+    ("application/x-bzip2", "public.bzip2-archive"),
+    ("application/x-executable", "public.unix-executable"),
+    ("application/x-iso9660-image", "public.iso-image"),
+    ("application/x-mach-binary", "com.apple.mach-o-binary"),
+    ("application/x-sharedlib", "public.unix-executable"),
+    ("application/x-tar", "public.tar-archive"),
+    ("application/x-7z-compressed", "org.7-zip.7-zip-archive"),
+    ("application/xml", "public.xml"),
+    ("application/zip", "public.zip-archive"),
+    ("audio/aac", "public.aac-audio"),
+    ("audio/flac", "org.xiph.flac"),
+    ("audio/mp4", "public.mpeg-4-audio"),
+    ("audio/mpeg", "public.mp3"),
+    ("audio/x-aiff", "public.aiff-audio"),
+    ("audio/x-wav", "com.microsoft.waveform-audio"),
+    ("image/bmp", "com.microsoft.bmp"),
+    ("image/gif", "com.compuserve.gif"),
+    ("image/heic", "public.heic"),
+    ("image/jpeg", "public.jpeg"),
+    ("image/png", "public.png"),
+    ("image/svg+xml", "public.svg-image"),
+    ("image/tiff", "public.tiff"),
+    ("image/vnd.adobe.photoshop", "com.adobe.photoshop-image"),
+    ("image/webp", "org.webmproject.webp"),
+    ("inode/directory", "public.folder"),
+    ("text/css", "public.css"),
+    ("text/csv", "public.comma-separated-values-text"),
+    ("text/html", "public.html"),
+    ("text/markdown", "net.daringfireball.markdown"),
+    ("text/plain", "public.plain-text"),
+    ("text/x-c", "public.c-source"),
+    ("text/x-python", "public.python-script"),
+    ("text/x-shellscript", "public.shell-script"),
+    ("video/mp4", "public.mpeg-4"),
+    ("video/mpeg", "public.mpeg"),
+    ("video/quicktime", "com.apple.quicktime-movie"),
+    ("video/x-matroska", "org.matroska.mkv"),
+    ("video/x-msvideo", "public.avi"),
+];
+
+// media type fallbacks for types without their own entry
+static UTI_MEDIA_MAP: &[(&str, &str)] = &[
+    ("audio/", "public.audio"),
+    ("image/", "public.image"),
+    ("text/", "public.text"),
+    ("video/", "public.movie"),
+];
+
+// structured syntax suffix fallbacks, e.g. application/epub+zip is at least a zip archive
+static UTI_SUFFIX_MAP: &[(&str, &str)] = &[
+    ("json", "public.json"),
+    ("xml", "public.xml"),
+    ("zip", "public.zip-archive"),
+];
+
+pub fn from_mime(mime: &str) -> Option<&'static str> {
+    let suffix = mime.parse::<MimeType>().ok().and_then(|mime| mime.suffix().map(str::to_owned));
+    UTI_MAP.iter()
+        .find(|(m, _)| *m == mime)
+        .or_else(|| UTI_SUFFIX_MAP.iter().find(|(s, _)| Some(*s) == suffix.as_deref()))
+        .or_else(|| UTI_MEDIA_MAP.iter().find(|(prefix, _)| mime.starts_with(prefix)))
+        .map(|(_, uti)| *uti)
+}
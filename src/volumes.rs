@@ -0,0 +1,74 @@
+// partition tables, filesystems and volume formats recognized in the leading bytes of a device or disk image,
+// most have no MIME type of their own; see the superblock layouts in util-linux's libblkid
+
+// the deepest is the btrfs superblock at 64 KiB
+static SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"LUKS\xba\xbe", "LUKS encrypted volume"),
+    (3, b"-FVE-FS-", "BitLocker encrypted volume"),
+    (0, b"QFI\xfb", "qcow2 disk image"),
+    (0, b"KDMV", "VMDK disk image"),
+    (0, b"vhdxfile", "VHDX disk image"),
+    (0x40, b"<<< Oracle VM VirtualBox Disk Image >>>", "VDI disk image"),
+    (3, b"NTFS    ", "NTFS filesystem"),
+    (3, b"EXFAT   ", "exFAT filesystem"),
+    (0x52, b"FAT32   ", "FAT32 filesystem"),
+    (0x36, b"FAT16   ", "FAT16 filesystem"),
+    (0x36, b"FAT12   ", "FAT12 filesystem"),
+    (0, b"XFSB", "XFS filesystem"),
+    (0, b"hsqs", "SquashFS filesystem"),
+    (0x10040, b"_BHRfS_M", "Btrfs filesystem"),
+    (0x8001, b"CD001", "ISO 9660 filesystem"),
+    (0x400, b"\x10\x20\xf5\xf2", "F2FS filesystem"),
+    (0x400, b"H+", "HFS+ filesystem"),
+    (0x400, b"HX", "HFSX filesystem"),
+    (0x20, b"NXSB", "APFS container"),
+    (0x218, b"LVM2 001", "LVM2 physical volume"),
+    (0x1000, b"\xfc\x4e\x2b\xa9", "Linux RAID member"),
+    // at the end of the first page, 4 KiB pages assumed
+    (0xff6, b"SWAPSPACE2", "Linux swap"),
+];
+
+// ext2, ext3 and ext4 share the superblock magic, the feature flags tell them apart
+const EXT_MAGIC_OFFSET: usize = 0x438;
+const EXT_COMPAT_OFFSET: usize = 0x45c;
+const EXT_INCOMPAT_OFFSET: usize = 0x460;
+const EXT_COMPAT_HAS_JOURNAL: u32 = 0x4;
+const EXT_INCOMPAT_EXTENTS: u32 = 0x40;
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn ext_version(data: &[u8]) -> Option<&'static str> {
+    if data.get(EXT_MAGIC_OFFSET..EXT_MAGIC_OFFSET + 2)? != b"\x53\xef" {
+        return None;
+    }
+    let name = if u32_at(data, EXT_INCOMPAT_OFFSET)? & EXT_INCOMPAT_EXTENTS != 0 {
+        "ext4 filesystem"
+    } else if u32_at(data, EXT_COMPAT_OFFSET)? & EXT_COMPAT_HAS_JOURNAL != 0 {
+        "ext3 filesystem"
+    } else {
+        "ext2 filesystem"
+    };
+    Some(name)
+}
+
+// volumes and filesystems first, FAT and NTFS boot sectors end with the MBR signature as well
+pub fn identify(data: &[u8]) -> Option<&'static str> {
+    let has = |offset: usize, magic: &[u8]| data.get(offset..offset + magic.len()) == Some(magic);
+
+    if let Some((_, _, name)) = SIGNATURES.iter().find(|(offset, magic, _)| has(*offset, magic)) {
+        return Some(name);
+    }
+    if let Some(name) = ext_version(data) {
+        return Some(name);
+    }
+    if has(0x200, b"EFI PART") {
+        Some("GPT partition table")
+    } else if has(0x1fe, b"\x55\xaa") {
+        Some("MBR partition table")
+    } else {
+        None
+    }
+}
@@ -0,0 +1,146 @@
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+use slog::{Logger, debug, warn};
+
+use crate::FileType;
+
+// one JSON line per file in each direction
+#[derive(Serialize, Deserialize)]
+struct Request {
+    path: PathBuf,
+}
+
+pub enum Outcome {
+    Classified(FileType),
+    Crashed,
+    // the worker was killed, e.g. stuck in pathologically slow magic matching
+    TimedOut,
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Drop for Process {
+    // idle between requests, nothing is lost
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// a classify-worker child process, started on first use and again after each crash
+pub struct Worker {
+    args: Vec<String>,
+    timeout: Option<Duration>,
+    process: Option<Process>,
+}
+
+// false when nothing arrived in time
+fn wait_readable(stdout: &BufReader<ChildStdout>, timeout: Duration) -> io::Result<bool> {
+    if !stdout.buffer().is_empty() {
+        return Ok(true);
+    }
+    let mut fds = libc::pollfd{fd: stdout.get_ref().as_raw_fd(), events: libc::POLLIN, revents: 0};
+    let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+    loop {
+        match unsafe { libc::poll(&mut fds, 1, timeout_ms) } {
+            // a crashed worker shows up as readable (end of file)
+            n if n >= 0 => return Ok(n > 0),
+            _ => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+impl Worker {
+    // args are passed to the classify-worker verb, so that it loads the same configuration
+    pub fn new(args: Vec<String>, timeout: Option<Duration>) -> Self {
+        Self{args, timeout, process: None}
+    }
+
+    fn spawn(&self) -> io::Result<Process> {
+        let mut child = Command::new(std::env::current_exe()?)
+            .arg("classify-worker")
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("piped stdin");
+        let stdout = BufReader::new(child.stdout.take().expect("piped stdout"));
+        Ok(Process{child, stdin, stdout})
+    }
+
+    fn request(&self, process: &mut Process, path: &Path) -> io::Result<Outcome> {
+        let mut line = serde_json::to_vec(&Request{path: path.to_owned()})?;
+        line.push(b'\n');
+        // a worker that died on the previous file is only noticed here
+        if process.stdin.write_all(&line).and_then(|_| process.stdin.flush()).is_err() {
+            return Ok(Outcome::Crashed);
+        }
+
+        if let Some(timeout) = self.timeout {
+            if !wait_readable(&process.stdout, timeout)? {
+                return Ok(Outcome::TimedOut);
+            }
+        }
+        let mut response = String::new();
+        if process.stdout.read_line(&mut response)? == 0 {
+            return Ok(Outcome::Crashed);
+        }
+        Ok(serde_json::from_str(&response).map_or(Outcome::Crashed, Outcome::Classified))
+    }
+
+    // an error only when no worker can be started
+    pub fn classify(&mut self, path: &Path, log: &Logger) -> io::Result<Outcome> {
+        let mut process = match self.process.take() {
+            Some(process) => process,
+            None => {
+                let process = self.spawn()?;
+                debug!(log, "Started classify worker {}", process.child.id());
+                process
+            }
+        };
+
+        let outcome = self.request(&mut process, path)?;
+        match &outcome {
+            Outcome::Classified(_) => self.process = Some(process),
+            Outcome::Crashed => {
+                // also ends a worker that answered with garbage
+                let _ = process.child.kill();
+                let status = process.child.wait()?;
+                warn!(log, "Classify worker {} crashed ({}), starting a new one", process.child.id(), status);
+            }
+            // killed when process is dropped
+            Outcome::TimedOut => warn!(log, "Classification timed out, starting a new classify worker"),
+        }
+        Ok(outcome)
+    }
+}
+
+// the classify-worker side: classifies each requested file and answers on stdout
+pub fn serve(mut classify: impl FnMut(&Path) -> FileType) -> Result<(), Box<dyn Error>> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let request: Request = serde_json::from_str(&line?)?;
+        let mut response = serde_json::to_vec(&classify(&request.path))?;
+        response.push(b'\n');
+        out.write_all(&response)?;
+        out.flush()?;
+    }
+    Ok(())
+}